@@ -85,6 +85,18 @@ impl Object {
         gl::BindVertexArray(0);
     }
 
+    /// Replaces part of the vertex buffer, starting an offset in bytes in. The buffer doesn't
+    /// grow, so the data must fit in what set_vertices last uploaded.
+    pub unsafe fn update_vertices(
+        &mut self,
+        offset: gl::types::GLintptr,
+        size: gl::types::GLsizeiptr,
+        data: *const std::os::raw::c_void,
+    ) {
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer_id);
+        gl::BufferSubData(gl::ARRAY_BUFFER, offset, size, data);
+    }
+
     pub fn set_indices(&mut self, data: Vec<[gl::types::GLuint; 3]>) {
         unsafe {
             gl::BindVertexArray(self.vertex_array_id);
@@ -128,6 +140,7 @@ macro_rules! attribs {
     (pub struct $name:ident {
         $(pub $field_name:ident: $field_type:ty,)*
     }) => {
+        #[derive(Clone, Copy)]
         #[repr(packed)]
         pub struct $name {
             $(pub $field_name: $field_type,)*
@@ -156,6 +169,17 @@ macro_rules! attribs {
                         data.as_ptr() as *const _);
                 }
             }
+
+            /// Replaces vertices starting at an index, without reallocating the buffer
+            pub fn update_vertices(object: &mut gl_basic::Object, first: usize, data: &[$name]) {
+                use std;
+                unsafe {
+                    object.update_vertices(
+                        (first * std::mem::size_of::<$name>()) as gl::types::GLintptr,
+                        (data.len() * std::mem::size_of::<$name>()) as gl::types::GLsizeiptr,
+                        data.as_ptr() as *const _);
+                }
+            }
         }
     }
 }
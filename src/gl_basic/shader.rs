@@ -1,6 +1,7 @@
 extern crate gl;
 
 use std;
+use std::collections::HashMap;
 
 const MAX_LOG_LENGTH: usize = 1024;
 
@@ -71,6 +72,8 @@ impl Drop for Shader {
 
 pub struct Program {
     pub id: gl::types::GLuint,
+    /// Locations of the active uniforms by name, looked up once after linking
+    uniforms: HashMap<String, gl::types::GLint>,
 }
 
 impl Program {
@@ -88,7 +91,10 @@ impl Program {
             info_log.set_len(MAX_LOG_LENGTH - 1); // subtract 1 to skip the trailing null character
             gl::GetProgramiv(id, gl::LINK_STATUS, &mut success);
             if success == gl::TRUE as i32 {
-                Ok(Program { id: id })
+                Ok(Program {
+                    id: id,
+                    uniforms: uniform_locations(id),
+                })
             } else {
                 gl::GetProgramInfoLog(
                     id,
@@ -115,6 +121,36 @@ impl Program {
         }
     }
 
+    /// Sets an int uniform, which keeps its value for later draws with the program
+    pub fn set_uniform_i32(&self, name: &str, value: i32) {
+        let location = self.uniform_location(name);
+        self.bind_then(|| unsafe {
+            gl::Uniform1i(location, value);
+        });
+    }
+
+    /// Sets a float uniform, which keeps its value for later draws with the program
+    pub fn set_uniform_f32(&self, name: &str, value: f32) {
+        let location = self.uniform_location(name);
+        self.bind_then(|| unsafe {
+            gl::Uniform1f(location, value);
+        });
+    }
+
+    /// Sets a 4x4 matrix uniform from 16 floats in column-major order
+    pub fn set_uniform_mat4(&self, name: &str, value: &[f32; 16]) {
+        let location = self.uniform_location(name);
+        self.bind_then(|| unsafe {
+            gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr());
+        });
+    }
+
+    /// Location of a uniform, or -1, which setting ignores, if the program has no such uniform
+    /// or it was optimized out
+    fn uniform_location(&self, name: &str) -> gl::types::GLint {
+        self.uniforms.get(name).cloned().unwrap_or(-1)
+    }
+
     pub fn bind_then<F>(&self, mut operation: F)
     where
         F: FnMut(),
@@ -129,6 +165,38 @@ impl Program {
     }
 }
 
+/// Looks up the locations of the active uniforms of a linked program. Arrays are listed by their
+/// first element, as `name[0]`, and are stored by their name.
+fn uniform_locations(id: gl::types::GLuint) -> HashMap<String, gl::types::GLint> {
+    let mut uniforms = HashMap::new();
+    unsafe {
+        let mut count = 0;
+        gl::GetProgramiv(id, gl::ACTIVE_UNIFORMS, &mut count);
+        let mut max_length = 0;
+        gl::GetProgramiv(id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+        let mut name: Vec<u8> = vec![0; max_length.max(1) as usize];
+        for i in 0..count as gl::types::GLuint {
+            let mut length = 0;
+            let mut size = 0;
+            let mut kind = 0;
+            gl::GetActiveUniform(
+                id,
+                i,
+                name.len() as i32,
+                &mut length,
+                &mut size,
+                &mut kind,
+                name.as_mut_ptr() as *mut _,
+            );
+            let location = gl::GetUniformLocation(id, name.as_ptr() as *const _);
+            let name = String::from_utf8_lossy(&name[..length as usize]);
+            let name = name.trim_end_matches("[0]");
+            uniforms.insert(name.to_string(), location);
+        }
+    }
+    uniforms
+}
+
 impl Drop for Program {
     fn drop(&mut self) {
         unsafe {
@@ -27,6 +27,25 @@ impl Texture {
             gl::BindTexture(gl::TEXTURE_2D, 0);
         }
     }
+
+    /// Binds the texture to a texture unit other than the first for the operation, which is how
+    /// a shader samples more than one texture
+    pub fn bind_unit_then<F>(&self, unit: u32, mut operation: F)
+    where
+        F: FnMut(),
+    {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        operation();
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
 }
 
 impl Drop for Texture {
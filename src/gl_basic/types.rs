@@ -4,12 +4,14 @@ use std::mem;
 
 pub type Float = gl::types::GLfloat;
 
+#[derive(Clone, Copy)]
 #[repr(packed)]
 pub struct Vec2 {
     pub x: Float,
     pub y: Float,
 }
 
+#[derive(Clone, Copy)]
 #[repr(packed)]
 pub struct Vec3 {
     pub x: Float,
@@ -17,6 +19,7 @@ pub struct Vec3 {
     pub z: Float,
 }
 
+#[derive(Clone, Copy)]
 #[repr(packed)]
 pub struct Vec4 {
     pub x: Float,
@@ -9,7 +9,7 @@ mod text;
 use glutin::{Api, GlContext, GlRequest};
 
 use rusttype::gpu_cache::CacheBuilder;
-use rusttype::{point, vector, Font, FontCollection, PositionedGlyph, Rect, Scale};
+use rusttype::{vector, Font, Rect};
 
 use std::mem;
 use std::ptr;
@@ -51,67 +51,23 @@ fn main() {
         ..CacheBuilder::default()
     }.build();*/
 
+    // Rasterise "RustType" on the CPU, for the texture of the triangle
     let font_data = include_bytes!("../fonts/wqy-microhei/WenQuanYiMicroHei.ttf");
-    let collection = FontCollection::from_bytes(font_data as &[u8]).unwrap_or_else(|e| {
-        panic!("error constructing a FontCollection from bytes: {}", e);
-    });
-    let font = collection.into_font() // only succeeds if collection consists of one font
-        .unwrap_or_else(|e| {
-            panic!("error turning FontCollection into a Font: {}", e);
-        });
-
-    // Desired font pixel height
-    let height: f32 = 12.4; // to get 80 chars across (fits most terminals); adjust as desired
-    let pixel_height = height.ceil() as usize;
-
-    // 2x scale in x direction to counter the aspect ratio of monospace characters.
-    let scale = Scale {
-        x: height * 2.0,
-        y: height,
-    };
-
-    // The origin of a line of text is at the baseline (roughly where
-    // non-descending letters sit). We don't want to clip the text, so we shift
-    // it down with an offset when laying it out. v_metrics.ascent is the
-    // distance between the baseline and the highest edge of any glyph in
-    // the font. That's enough to guarantee that there's no clipping.
-    let v_metrics = font.v_metrics(scale);
-    let offset = point(0.0, v_metrics.ascent);
-
-    // Glyphs to draw for "RustType". Feel free to try other strings.
-    let glyphs: Vec<PositionedGlyph> = font.layout("RustType", scale, offset).collect();
-
-    // Find the most visually pleasing width to display
-    let width = glyphs
-        .iter()
-        .rev()
-        .map(|g| g.position().x as f32 + g.unpositioned().h_metrics().advance_width)
-        .next()
-        .unwrap_or(0.0)
-        .ceil() as usize;
-
-    println!("width: {}, height: {}", width, pixel_height);
-
-    // Rasterise directly into ASCII art.
-    let mut pixel_data: Vec<u8> = vec![128; width * pixel_height * 4];
-    for g in glyphs {
-        if let Some(bb) = g.pixel_bounding_box() {
-            g.draw(|x, y, v| {
-                // v should be in the range 0.0 to 1.0
-                let x = x as i32 + bb.min.x;
-                let y = y as i32 + bb.min.y;
-                // There's still a possibility that the glyph clips the boundaries of the bitmap
-                if x >= 0 && x < width as i32 && y >= 0 && y < pixel_height as i32 {
-                    let x = x as usize;
-                    let y = y as usize;
-                    pixel_data[(x + y * width) * 4 + 2] = (v * 255.0) as u8;
-                    pixel_data[(x + y * width) * 4 + 1] = 0;
-                    pixel_data[(x + y * width) * 4 + 0] = 255;
-                    print!(".");
-                }
-            })
-        }
+    let mut fonts = text::FontSet::new();
+    if let Err(e) = fonts.load("sans", font_data as &[u8]) {
+        panic!("FontSet: {}", e);
     }
+    let style = text::TextStyle::new(12.4).with_color([1.0, 0.0, 1.0, 1.0]);
+    let layout = text::layout::layout_paragraph(
+        &mut fonts,
+        "RustType",
+        &style,
+        &text::LayoutOptions::new(1000.0),
+    );
+    let image = text::ImageRenderer::new(1.0).render(&layout, text::PixelFormat::Rgba);
+    let (width, pixel_height) = (image.width as usize, image.height as usize);
+    println!("width: {}, height: {}", width, pixel_height);
+    let pixel_data = image.data;
 
     let mut texture = match gl_basic::Texture::new() {
         Ok(p) => p,
@@ -196,11 +152,22 @@ fn main() {
     drawable.set_indices(vec![[0, 1, 2]]);
 
     let (window_width, window_height) = gl_window.get_inner_size().unwrap();
+    let dpi_factor = gl_window.get_hidpi_factor();
 
-    let mut text_obj = match text::GlGlyphRenderer::new(window_width as f32, window_height as f32) {
+    let mut text_obj = match text::GlGlyphRenderer::new(
+        window_width as f32 / dpi_factor,
+        window_height as f32 / dpi_factor,
+    ) {
         Ok(o) => o,
         Err(e) => panic!("GlGlyphRenderer: {}", e),
     };
+    let raster_options = text::RasterOptions {
+        snap_baselines: true,
+        ..text::RasterOptions::new(dpi_factor)
+    };
+    if let Err(e) = text_obj.set_raster_options(raster_options) {
+        panic!("GlGlyphRenderer: {}", e);
+    }
 
     //let text = "A japanese poem:\n\n色は匂へど散りぬるを我が世誰ぞ常ならむ有為の奥山今日越えて浅き夢見じ酔ひもせず";
 
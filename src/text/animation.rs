@@ -0,0 +1,106 @@
+use std::f32::consts::PI;
+use std::u32;
+
+use rusttype::vector;
+
+use text::layout::Layout;
+use text::transform::GlyphTransform;
+
+/// An animation of the clusters of a layout. Clusters are taken in the order of the text, not
+/// the order they are drawn in.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Shows clusters one at a time, a number of them per second
+    Typewriter { rate: f32 },
+    /// Fades each cluster in over a duration in seconds, starting a delay after the one before it
+    FadeIn { delay: f32, duration: f32 },
+    /// Moves clusters up and down in a wave that travels along the text
+    Wave {
+        amplitude: f32,
+        /// Number of clusters in one wave
+        wavelength: f32,
+        /// Waves per second
+        frequency: f32,
+    },
+    /// Moves clusters about at random, to a new offset a number of times per second
+    Shake { amplitude: f32, rate: f32 },
+}
+
+impl Effect {
+    /// Seconds until the effect is over for a number of clusters, or None if it doesn't end
+    pub fn duration(&self, clusters: usize) -> Option<f32> {
+        match *self {
+            Effect::Typewriter { rate } => Some(clusters as f32 / rate),
+            Effect::FadeIn { delay, duration } => {
+                Some(clusters.saturating_sub(1) as f32 * delay + duration)
+            }
+            Effect::Wave { .. } | Effect::Shake { .. } => None,
+        }
+    }
+
+    /// Changes the transform of the cluster that is nth in the text a time in seconds after the
+    /// effect started
+    fn apply(&self, n: usize, time: f32, transform: &mut GlyphTransform) {
+        match *self {
+            Effect::Typewriter { rate } => {
+                if n as f32 >= time * rate {
+                    transform.opacity = 0.0;
+                }
+            }
+            Effect::FadeIn { delay, duration } => {
+                let elapsed = time - n as f32 * delay;
+                let fade = if duration > 0.0 {
+                    elapsed / duration
+                } else if elapsed >= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+                transform.opacity *= fade.max(0.0).min(1.0);
+            }
+            Effect::Wave {
+                amplitude,
+                wavelength,
+                frequency,
+            } => {
+                let phase = n as f32 / wavelength - time * frequency;
+                transform.offset.y += amplitude * (phase * 2.0 * PI).sin();
+            }
+            Effect::Shake { amplitude, rate } => {
+                let step = (time * rate).floor() as u32;
+                transform.offset = transform.offset
+                    + vector(
+                        amplitude * noise(n as u32, step, 0),
+                        amplitude * noise(n as u32, step, 1),
+                    );
+            }
+        }
+    }
+}
+
+/// Returns the transform of each cluster of a layout a time in seconds after the effects started,
+/// for GlGlyphRenderer::set_glyph_transforms. Nothing is laid out or rasterized again, so this
+/// can be called every frame.
+pub fn animate(layout: &Layout, effects: &[Effect], time: f32) -> Vec<GlyphTransform> {
+    let mut order: Vec<usize> = (0..layout.clusters.len()).collect();
+    order.sort_by_key(|&i| layout.clusters[i].range.start);
+    let mut transforms = vec![GlyphTransform::identity(); layout.clusters.len()];
+    for (n, i) in order.into_iter().enumerate() {
+        for effect in effects {
+            effect.apply(n, time, &mut transforms[i]);
+        }
+    }
+    transforms
+}
+
+/// A number from -1 to 1 that is the same for the same arguments
+fn noise(a: u32, b: u32, c: u32) -> f32 {
+    let mut h = a
+        .wrapping_mul(0x9E37_79B1)
+        .wrapping_add(b.wrapping_mul(0x85EB_CA77))
+        .wrapping_add(c.wrapping_mul(0xC2B2_AE3D));
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
@@ -0,0 +1,72 @@
+extern crate unicode_bidi;
+
+use std::ops::Range;
+
+use self::unicode_bidi::BidiInfo;
+
+/// A run of text that is laid out in a single direction
+pub struct VisualRun {
+    pub range: Range<usize>,
+    pub rtl: bool,
+}
+
+/// Splits a line of a paragraph into directional runs, in visual (left to right) order
+pub fn visual_runs(info: &BidiInfo, paragraph: usize, line: Range<usize>) -> Vec<VisualRun> {
+    let para = &info.paragraphs[paragraph];
+    let (levels, runs) = info.visual_runs(para, line);
+    runs.into_iter()
+        .map(|range| VisualRun {
+            rtl: levels[range.start].is_rtl(),
+            range: range,
+        })
+        .collect()
+}
+
+/// Returns the mirrored form of a character that is displayed right to left (rule L4 of UAX #9)
+pub fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '<' => '>',
+        '>' => '<',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        '⁅' => '⁆',
+        '⁆' => '⁅',
+        '⁽' => '⁾',
+        '⁾' => '⁽',
+        '₍' => '₎',
+        '₎' => '₍',
+        '≤' => '≥',
+        '≥' => '≤',
+        '⟨' => '⟩',
+        '⟩' => '⟨',
+        '〈' => '〉',
+        '〉' => '〈',
+        '《' => '》',
+        '》' => '《',
+        '「' => '」',
+        '」' => '「',
+        '『' => '』',
+        '』' => '『',
+        '【' => '】',
+        '】' => '【',
+        '〔' => '〕',
+        '〕' => '〔',
+        '（' => '）',
+        '）' => '（',
+        '［' => '］',
+        '］' => '［',
+        '｛' => '｝',
+        '｝' => '｛',
+        '＜' => '＞',
+        '＞' => '＜',
+        c => c,
+    }
+}
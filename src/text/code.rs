@@ -0,0 +1,671 @@
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use text::color::Color;
+use text::font::FaceId;
+use text::layout::{self, LayoutOptions};
+use text::rich::RichText;
+use text::style::TextStyle;
+use text::view::TextView;
+use text::GlGlyphRenderer;
+
+/// Family name the code font is loaded into the renderer with
+const MONO: &str = "mono";
+
+/// Character between the line numbers and the code
+const GUTTER_RULE: char = '\u{2502}';
+
+/// What a piece of source text is, which picks its color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    /// Words such as true, false and null
+    Literal,
+    String,
+    Number,
+    Comment,
+    /// The name before `=` or `:` in a config file
+    Key,
+    /// A section header, such as `[server]` in an INI file
+    Section,
+    /// A variable that is expanded, such as `$HOME` in a shell script
+    Variable,
+}
+
+/// Splits source text into tokens
+pub trait Highlighter {
+    /// Returns the byte ranges of the text that aren't plain and their tokens, in order and not
+    /// overlapping
+    fn highlight(&self, text: &str) -> Vec<(Range<usize>, Token)>;
+}
+
+/// A highlighter for the simple languages of config files and scripts, made of words, strings
+/// and comments
+#[derive(Clone)]
+pub struct Grammar {
+    pub keywords: Vec<String>,
+    pub literals: Vec<String>,
+    /// Starts of comments that run to the end of the line. They only count at the start of a
+    /// line or after whitespace, so `#` in `${#list}` isn't a comment.
+    pub line_comments: Vec<String>,
+    /// Start and end of comments that may span lines
+    pub block_comment: Option<(String, String)>,
+    /// Characters that start and end strings
+    pub quotes: Vec<char>,
+    /// If a backslash escapes the character after it in strings
+    pub escapes: bool,
+    /// If `$name` and `${...}` are variables, as in shell scripts
+    pub variables: bool,
+    /// If `[name]` on a line of its own is a section header, as in INI files
+    pub sections: bool,
+    /// If the text before the first `=` or `:` on a line, or a string followed by `:`, is a key
+    pub keys: bool,
+}
+
+impl Grammar {
+    /// A grammar that finds nothing, for plain text
+    pub fn new() -> Grammar {
+        Grammar {
+            keywords: Vec::new(),
+            literals: Vec::new(),
+            line_comments: Vec::new(),
+            block_comment: None,
+            quotes: Vec::new(),
+            escapes: false,
+            variables: false,
+            sections: false,
+            keys: false,
+        }
+    }
+
+    /// POSIX shell and bash scripts
+    pub fn shell() -> Grammar {
+        Grammar {
+            keywords: words(&[
+                "if", "then", "else", "elif", "fi", "case", "esac", "for", "while", "until", "do",
+                "done", "in", "function", "select", "return", "exit", "break", "continue", "local",
+                "export", "readonly", "declare", "unset", "shift", "source", "trap", "eval",
+                "exec",
+            ]),
+            literals: words(&["true", "false"]),
+            line_comments: words(&["#"]),
+            quotes: vec!['"', '\'', '`'],
+            escapes: true,
+            variables: true,
+            ..Grammar::new()
+        }
+    }
+
+    /// INI files and similar config files, which TOML is close enough to
+    pub fn ini() -> Grammar {
+        Grammar {
+            literals: words(&["true", "false", "yes", "no", "on", "off"]),
+            line_comments: words(&["#", ";"]),
+            quotes: vec!['"', '\''],
+            escapes: true,
+            sections: true,
+            keys: true,
+            ..Grammar::new()
+        }
+    }
+
+    pub fn json() -> Grammar {
+        Grammar {
+            literals: words(&["true", "false", "null"]),
+            quotes: vec!['"'],
+            escapes: true,
+            keys: true,
+            ..Grammar::new()
+        }
+    }
+
+    pub fn yaml() -> Grammar {
+        Grammar {
+            literals: words(&["true", "false", "yes", "no", "on", "off", "null"]),
+            line_comments: words(&["#"]),
+            quotes: vec!['"', '\''],
+            escapes: true,
+            keys: true,
+            ..Grammar::new()
+        }
+    }
+
+    /// Returns the end of a key at the start of a line, if the line has one
+    fn key_end(&self, text: &str, start: usize) -> Option<usize> {
+        let line = &text[start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let end = line.find(|c| c == '=' || c == ':')?;
+        let key = &line[..end];
+        let comment = self
+            .line_comments
+            .iter()
+            .any(|comment| key.contains(comment.as_str()));
+        if key.trim().is_empty() || comment || key.contains(|c| self.quotes.contains(&c)) {
+            return None;
+        }
+        Some(start + key.trim_end().len())
+    }
+}
+
+impl Highlighter for Grammar {
+    fn highlight(&self, text: &str) -> Vec<(Range<usize>, Token)> {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        // if only whitespace has been passed since the start of the line
+        let mut line_start = true;
+        while i < text.len() {
+            let rest = &text[i..];
+            let c = rest.chars().next().unwrap();
+            if c.is_whitespace() {
+                line_start |= c == '\n';
+                i += c.len_utf8();
+                continue;
+            }
+            let before = text[..i].chars().next_back();
+            let after_space = before.map_or(true, char::is_whitespace);
+            let word_start = before.map_or(true, |b| !(b.is_alphanumeric() || b == '_'));
+            let starts_line = line_start;
+            let key_end = if starts_line && self.keys {
+                self.key_end(text, i)
+            } else {
+                None
+            };
+            line_start = false;
+
+            let (len, token) = if self.sections && starts_line && c == '[' {
+                (rest.find('\n').unwrap_or(rest.len()), Token::Section)
+            } else if let Some(end) = key_end {
+                (end - i, Token::Key)
+            } else if after_space
+                && self
+                    .line_comments
+                    .iter()
+                    .any(|comment| rest.starts_with(comment.as_str()))
+            {
+                (rest.find('\n').unwrap_or(rest.len()), Token::Comment)
+            } else if let Some(len) = self.block_comment_len(rest) {
+                (len, Token::Comment)
+            } else if self.quotes.contains(&c) {
+                let len = self.string_len(rest, c);
+                // a string followed by a colon is a key, as in JSON
+                if self.keys && rest[len..].trim_start().starts_with(':') {
+                    (len, Token::Key)
+                } else {
+                    (len, Token::String)
+                }
+            } else if self.variables && c == '$' {
+                (variable_len(rest), Token::Variable)
+            } else if word_start
+                && (c.is_ascii_digit()
+                    || c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+            {
+                (word_len(rest, 1), Token::Number)
+            } else if c.is_alphabetic() || c == '_' {
+                let len = word_len(rest, 0);
+                let word = &rest[..len];
+                if self.keywords.iter().any(|k| k == word) {
+                    (len, Token::Keyword)
+                } else if self.literals.iter().any(|l| l == word) {
+                    (len, Token::Literal)
+                } else {
+                    (len, Token::Plain)
+                }
+            } else {
+                (c.len_utf8(), Token::Plain)
+            };
+            if token != Token::Plain {
+                tokens.push((i..i + len, token));
+            }
+            i += len.max(c.len_utf8());
+        }
+        tokens
+    }
+}
+
+impl Grammar {
+    /// Length of the block comment at the start of some text, if it starts with one
+    fn block_comment_len(&self, text: &str) -> Option<usize> {
+        let (ref start, ref end) = *self.block_comment.as_ref()?;
+        if !text.starts_with(start.as_str()) {
+            return None;
+        }
+        Some(match text[start.len()..].find(end.as_str()) {
+            Some(e) => start.len() + e + end.len(),
+            None => text.len(),
+        })
+    }
+
+    /// Length of the string at the start of some text, up to and including its closing quote or
+    /// the end of the text
+    fn string_len(&self, text: &str, quote: char) -> usize {
+        let mut escaped = false;
+        for (i, c) in text.char_indices().skip(1) {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && self.escapes && quote != '\'' {
+                escaped = true;
+            } else if c == quote {
+                return i + c.len_utf8();
+            }
+        }
+        text.len()
+    }
+}
+
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|w| w.to_string()).collect()
+}
+
+/// Length of the word at the start of some text, from a byte index on. Words may have dashes and
+/// dots inside them, as in `max-age` or `1.5e3`.
+fn word_len(text: &str, from: usize) -> usize {
+    text[from..]
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        .map(|len| from + len)
+        .unwrap_or(text.len())
+}
+
+/// Length of the variable at the start of some text, as `$name`, `${name}` or `$1`
+fn variable_len(text: &str) -> usize {
+    let name = &text[1..];
+    if name.starts_with('{') {
+        return match name.find('}') {
+            Some(end) => end + 2,
+            None => 1,
+        };
+    }
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {
+            1 + name
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(name.len())
+        }
+        Some(c) if c.is_ascii_digit() || "@*#?$!-".contains(c) => 2,
+        _ => 1,
+    }
+}
+
+/// Colors of each token and of the gutter
+#[derive(Clone, PartialEq)]
+pub struct Theme {
+    pub plain: Color,
+    pub keyword: Color,
+    pub literal: Color,
+    pub string: Color,
+    pub number: Color,
+    pub comment: Color,
+    pub key: Color,
+    pub section: Color,
+    pub variable: Color,
+    pub line_number: Color,
+    pub gutter_rule: Color,
+}
+
+impl Theme {
+    /// Colors for a dark background
+    pub fn dark() -> Theme {
+        Theme {
+            plain: [0.85, 0.85, 0.85, 1.0],
+            keyword: [0.78, 0.57, 0.92, 1.0],
+            literal: [0.82, 0.6, 0.4, 1.0],
+            string: [0.6, 0.8, 0.47, 1.0],
+            number: [0.82, 0.6, 0.4, 1.0],
+            comment: [0.5, 0.53, 0.58, 1.0],
+            key: [0.38, 0.69, 0.94, 1.0],
+            section: [0.9, 0.75, 0.48, 1.0],
+            variable: [0.88, 0.42, 0.46, 1.0],
+            line_number: [0.45, 0.47, 0.5, 1.0],
+            gutter_rule: [0.3, 0.31, 0.33, 1.0],
+        }
+    }
+
+    /// Colors for a light background
+    pub fn light() -> Theme {
+        Theme {
+            plain: [0.15, 0.15, 0.15, 1.0],
+            keyword: [0.55, 0.1, 0.6, 1.0],
+            literal: [0.6, 0.35, 0.0, 1.0],
+            string: [0.2, 0.5, 0.1, 1.0],
+            number: [0.6, 0.35, 0.0, 1.0],
+            comment: [0.45, 0.47, 0.5, 1.0],
+            key: [0.1, 0.35, 0.7, 1.0],
+            section: [0.65, 0.4, 0.0, 1.0],
+            variable: [0.75, 0.15, 0.2, 1.0],
+            line_number: [0.55, 0.57, 0.6, 1.0],
+            gutter_rule: [0.8, 0.81, 0.83, 1.0],
+        }
+    }
+
+    pub fn color(&self, token: Token) -> Color {
+        match token {
+            Token::Plain => self.plain,
+            Token::Keyword => self.keyword,
+            Token::Literal => self.literal,
+            Token::String => self.string,
+            Token::Number => self.number,
+            Token::Comment => self.comment,
+            Token::Key => self.key,
+            Token::Section => self.section,
+            Token::Variable => self.variable,
+        }
+    }
+}
+
+/// Source text in DejaVu Sans Mono, highlighted and with line numbers in a gutter. Lines aren't
+/// wrapped, and tabs are expanded to spaces so they line up with the columns of the code. Only
+/// the lines on screen and a margin around them are laid out, by a TextView.
+pub struct CodeView {
+    view: TextView,
+    tokens: Vec<(Range<usize>, Token)>,
+    pub style: TextStyle,
+    pub theme: Theme,
+    /// Columns between tab stops
+    pub tab_width: usize,
+    pub line_numbers: bool,
+    /// The style, theme, tab width and line numbers the lines in the renderer were laid out
+    /// with, so changing them lays the lines out again
+    laid_out: Option<(TextStyle, Theme, usize, bool)>,
+}
+
+impl CodeView {
+    /// Creates a view of some text with a highlighter, in a font size and a screen size in pixels
+    pub fn new(
+        text: &str,
+        highlighter: &dyn Highlighter,
+        font_size: f32,
+        width: f32,
+        height: f32,
+    ) -> CodeView {
+        let style = TextStyle::new(font_size).with_family(MONO);
+        CodeView {
+            view: TextView::new(text, style.clone(), LayoutOptions::new(width), height),
+            tokens: highlighter.highlight(text),
+            style: style,
+            theme: Theme::dark(),
+            tab_width: 4,
+            line_numbers: true,
+            laid_out: None,
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> CodeView {
+        self.theme = theme;
+        self
+    }
+
+    /// Replaces the text, as after an edit. Only the lines on screen are laid out again.
+    pub fn set_text(&mut self, text: &str, highlighter: &dyn Highlighter) {
+        self.tokens = highlighter.highlight(text);
+        self.view.set_text(text);
+    }
+
+    pub fn text(&self) -> &str {
+        self.view.text()
+    }
+
+    /// The view that scrolls the code
+    pub fn view(&self) -> &TextView {
+        &self.view
+    }
+
+    /// The view that scrolls the code, to scroll it or change the size of the screen
+    pub fn view_mut(&mut self) -> &mut TextView {
+        &mut self.view
+    }
+
+    /// Loads DejaVu Sans Mono into a renderer, which has to be done once before update
+    pub fn load_font<'font>(renderer: &mut GlGlyphRenderer<'font>) -> Result<FaceId, String> {
+        let font_data = include_bytes!("../../fonts/dejavu/DejaVuSansMono.ttf");
+        renderer.load_font(MONO, font_data as &[u8])
+    }
+
+    /// Builds the whole text with the gutter and the highlighted code, one span per token
+    pub fn rich_text(&self) -> RichText {
+        let lines = self.lines();
+        let mut rich = RichText::new();
+        for n in 0..self.view.paragraph_count() {
+            if n > 0 {
+                rich.push("\n", &self.style);
+            }
+            lines.push_line(&mut rich, &self.view, n);
+        }
+        rich
+    }
+
+    /// Lays out the lines around the screen in a renderer, which needs the font from load_font,
+    /// and scrolls its view
+    pub fn update<'font>(&mut self, renderer: &mut GlGlyphRenderer<'font>) {
+        let settings = (
+            self.style.clone(),
+            self.theme.clone(),
+            self.tab_width,
+            self.line_numbers,
+        );
+        if self.laid_out.as_ref() != Some(&settings) {
+            self.view.relayout();
+            self.laid_out = Some(settings);
+        }
+        let lines = Lines {
+            tokens: &self.tokens,
+            style: &self.style,
+            theme: &self.theme,
+            tab_width: self.tab_width,
+            line_numbers: self.line_numbers,
+        };
+        self.view.update_with(renderer, |fonts, view, n| {
+            let mut rich = RichText::new();
+            lines.push_line(&mut rich, view, n);
+            layout::layout_rich(fonts, &rich, view.options())
+        });
+    }
+
+    fn lines(&self) -> Lines {
+        Lines {
+            tokens: &self.tokens,
+            style: &self.style,
+            theme: &self.theme,
+            tab_width: self.tab_width,
+            line_numbers: self.line_numbers,
+        }
+    }
+}
+
+/// What lines of a CodeView are built from, borrowed apart from its TextView
+struct Lines<'a> {
+    tokens: &'a [(Range<usize>, Token)],
+    style: &'a TextStyle,
+    theme: &'a Theme,
+    tab_width: usize,
+    line_numbers: bool,
+}
+
+impl<'a> Lines<'a> {
+    /// Adds a line to rich text, with its number in the gutter and a span per token
+    fn push_line(&self, rich: &mut RichText, view: &TextView, n: usize) {
+        let text = view.text();
+        let line = view.paragraph_range(n);
+        if self.line_numbers {
+            let digits = view.paragraph_count().to_string().len();
+            let number_style = self.style.clone().with_color(self.theme.line_number);
+            let rule_style = self.style.clone().with_color(self.theme.gutter_rule);
+            rich.push(&format!("{:>1$} ", n + 1, digits), &number_style);
+            rich.push(&format!("{} ", GUTTER_RULE), &rule_style);
+        }
+        // the first token that doesn't end before the line
+        let first = self
+            .tokens
+            .binary_search_by(|&(ref range, _)| {
+                if range.end <= line.start {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            })
+            .unwrap_or_else(|i| i);
+        let mut tokens = self.tokens[first..].iter().peekable();
+        let mut column = 0;
+        let mut i = line.start;
+        while i < line.end {
+            while tokens
+                .peek()
+                .map_or(false, |&&(ref range, _)| range.end <= i)
+            {
+                tokens.next();
+            }
+            // the rest of the line in one token
+            let (token, until) = match tokens.peek() {
+                Some(&&(ref range, token)) if range.start <= i => (token, range.end.min(line.end)),
+                Some(&&(ref range, _)) => (Token::Plain, range.start.min(line.end)),
+                None => (Token::Plain, line.end),
+            };
+            let piece = self.expand_tabs(&text[i..until], &mut column);
+            let style = self.style.clone().with_color(self.theme.color(token));
+            rich.push(&piece, &style);
+            i = until;
+        }
+    }
+
+    /// Replaces tabs with spaces up to the next tab stop, counting columns from the start of the
+    /// code on the line
+    fn expand_tabs(&self, text: &str, column: &mut usize) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        for c in text.chars() {
+            if c == '\t' {
+                let width = self.tab_width.max(1);
+                let spaces = width - *column % width;
+                for _ in 0..spaces {
+                    expanded.push(' ');
+                }
+                *column += spaces;
+            } else {
+                expanded.push(c);
+                *column += 1;
+            }
+        }
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell() {
+        let text = "echo ${#list} # done\nif [ $@ -gt -1 ]; then exit 2; fi";
+        assert_eq!(
+            Grammar::shell().highlight(text),
+            vec![
+                (5..13, Token::Variable),
+                (14..20, Token::Comment),
+                (21..23, Token::Keyword),
+                (26..28, Token::Variable),
+                (33..35, Token::Number),
+                (39..43, Token::Keyword),
+                (44..48, Token::Keyword),
+                (49..50, Token::Number),
+                (52..54, Token::Keyword),
+            ]
+        );
+    }
+
+    #[test]
+    fn ini() {
+        let text = "[server]\nhost = \"a;b\" ; note\nport=-80\nlist = [1]";
+        assert_eq!(
+            Grammar::ini().highlight(text),
+            vec![
+                (0..8, Token::Section),
+                (9..13, Token::Key),
+                (16..21, Token::String),
+                (22..28, Token::Comment),
+                (29..33, Token::Key),
+                (34..37, Token::Number),
+                (38..42, Token::Key),
+                (46..47, Token::Number),
+            ]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let text = "{\"a\": -1.5, \"b\": [1, \"c\"], \"d\": null}";
+        assert_eq!(
+            Grammar::json().highlight(text),
+            vec![
+                (1..4, Token::Key),
+                (6..10, Token::Number),
+                (12..15, Token::Key),
+                (18..19, Token::Number),
+                (21..24, Token::String),
+                (27..30, Token::Key),
+                (32..36, Token::Literal),
+            ]
+        );
+    }
+
+    #[test]
+    fn yaml() {
+        let text = "name: \"x\" # c\nlist: [yes, -2]";
+        assert_eq!(
+            Grammar::yaml().highlight(text),
+            vec![
+                (0..4, Token::Key),
+                (6..9, Token::String),
+                (10..13, Token::Comment),
+                (14..18, Token::Key),
+                (21..24, Token::Literal),
+                (26..28, Token::Number),
+            ]
+        );
+    }
+
+    /// The text of each span of the rich text a code view builds, with its color
+    fn pieces(code: &CodeView) -> Vec<(String, Color)> {
+        let rich = code.rich_text();
+        rich.spans
+            .iter()
+            .map(|&(ref range, ref style)| (rich.text[range.clone()].to_string(), style.color))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_across_lines() {
+        let text = "if true\n\tx=\"a\n\tb\" # c";
+        let mut code = CodeView::new(text, &Grammar::shell(), 14.0, 400.0, 300.0);
+        code.line_numbers = false;
+        let theme = Theme::dark();
+        let newline = code.style.color;
+        let expected = vec![
+            ("if", theme.keyword),
+            (" ", theme.plain),
+            ("true", theme.literal),
+            ("\n", newline),
+            ("    x=", theme.plain),
+            ("\"a", theme.string),
+            ("\n", newline),
+            ("    b\"", theme.string),
+            (" ", theme.plain),
+            ("# c", theme.comment),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(piece, color)| (piece.to_string(), color))
+            .collect();
+        assert_eq!(pieces(&code), expected);
+    }
+
+    #[test]
+    fn tabs_after_gutter() {
+        let text = "a\tb\n\t\tc";
+        let mut code = CodeView::new(text, &Grammar::new(), 14.0, 400.0, 300.0);
+        code.tab_width = 4;
+        assert_eq!(
+            code.rich_text().text,
+            "1 \u{2502} a   b\n2 \u{2502}         c"
+        );
+        code.tab_width = 3;
+        assert_eq!(code.rich_text().text, "1 \u{2502} a  b\n2 \u{2502}       c");
+    }
+}
@@ -0,0 +1,407 @@
+extern crate png;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use rusttype::{point, GlyphId, Rect};
+
+use text::sfnt;
+
+/// An RGBA color with components from 0 to 1
+pub type Color = [f32; 4];
+
+/// Palette index of layers that are drawn in the color of the text
+const FOREGROUND: u16 = 0xFFFF;
+
+/// Color glyphs from the COLR (version 0) and CPAL tables. Each one is a stack of ordinary glyph
+/// outlines, drawn bottom to top in colors from the palette. Bitmap color fonts are read by
+/// BitmapGlyphs.
+pub struct ColorGlyphs {
+    /// Base glyph ID, first layer and number of layers, sorted by glyph ID
+    bases: Vec<(u16, usize, usize)>,
+    /// Glyph ID and palette index of each layer
+    layers: Vec<(u16, u16)>,
+    /// The first palette
+    palette: Vec<Color>,
+}
+
+impl ColorGlyphs {
+    /// Reads the color glyphs of a font, if it has any
+    pub fn read(data: &[u8]) -> Option<ColorGlyphs> {
+        let colr = sfnt::find_table(data, *b"COLR")?;
+        let cpal = sfnt::find_table(data, *b"CPAL")?;
+        let base_count = sfnt::read_u16(colr, 2)? as usize;
+        let bases_offset = sfnt::read_u32(colr, 4)? as usize;
+        let layers_offset = sfnt::read_u32(colr, 8)? as usize;
+        let layer_count = sfnt::read_u16(colr, 12)? as usize;
+        let mut bases = Vec::with_capacity(base_count);
+        for i in 0..base_count {
+            let record = bases_offset + i * 6;
+            bases.push((
+                sfnt::read_u16(colr, record)?,
+                sfnt::read_u16(colr, record + 2)? as usize,
+                sfnt::read_u16(colr, record + 4)? as usize,
+            ));
+        }
+        bases.sort_by_key(|&(glyph, _, _)| glyph);
+        let mut layers = Vec::with_capacity(layer_count);
+        for i in 0..layer_count {
+            let record = layers_offset + i * 4;
+            layers.push((
+                sfnt::read_u16(colr, record)?,
+                sfnt::read_u16(colr, record + 2)?,
+            ));
+        }
+        let entry_count = sfnt::read_u16(cpal, 2)? as usize;
+        let records_offset = sfnt::read_u32(cpal, 8)? as usize;
+        let first_record = sfnt::read_u16(cpal, 12)? as usize;
+        let mut palette = Vec::with_capacity(entry_count);
+        for i in 0..entry_count {
+            // color records are stored as BGRA
+            let record = records_offset + (first_record + i) * 4;
+            let bgra = cpal.get(record..record + 4)?;
+            palette.push([
+                bgra[2] as f32 / 255.0,
+                bgra[1] as f32 / 255.0,
+                bgra[0] as f32 / 255.0,
+                bgra[3] as f32 / 255.0,
+            ]);
+        }
+        Some(ColorGlyphs {
+            bases: bases,
+            layers: layers,
+            palette: palette,
+        })
+    }
+
+    /// Returns the layers of a color glyph from bottom to top, each with its color, or None for
+    /// layers drawn in the text color
+    pub fn layers(&self, glyph: GlyphId) -> Option<Vec<(GlyphId, Option<Color>)>> {
+        let i = self
+            .bases
+            .binary_search_by_key(&(glyph.0 as u16), |&(g, _, _)| g)
+            .ok()?;
+        let (_, first, count) = self.bases[i];
+        let layers = self.layers.get(first..first + count)?;
+        Some(
+            layers
+                .iter()
+                .map(|&(g, index)| {
+                    let color = if index == FOREGROUND {
+                        None
+                    } else {
+                        self.palette.get(index as usize).cloned()
+                    };
+                    (GlyphId(g as u32), color)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A color bitmap of a glyph from a bitmap color font
+pub struct ColorBitmap {
+    pub glyph: u16,
+    /// Pixels per em of the strike the bitmap is from, which it was drawn for
+    pub ppem: u16,
+    pub width: u32,
+    pub height: u32,
+    /// Red, green, blue and alpha bytes, with alpha that isn't premultiplied, in rows from top to
+    /// bottom
+    pub pixels: Vec<u8>,
+    /// Where the bitmap goes relative to the glyph's origin, with y down, in multiples of the
+    /// size the text is set in
+    pub rect: Rect<f32>,
+}
+
+enum BitmapTables<'font> {
+    Cbdt {
+        cblc: &'font [u8],
+        cbdt: &'font [u8],
+    },
+    Sbix {
+        sbix: &'font [u8],
+        glyph_count: usize,
+    },
+}
+
+/// Color bitmaps from the CBLC and CBDT tables or the sbix table, which have strikes of bitmaps
+/// for a few sizes that are scaled to the size of the text. Only PNG bitmaps are read, which is
+/// what emoji fonts use.
+pub struct BitmapGlyphs<'font> {
+    tables: BitmapTables<'font>,
+    /// Pixels per em of each strike and where it is in its table, from smallest to largest
+    strikes: Vec<(u16, usize)>,
+    /// Font units per em over the font units in the size text is set in, which is from the
+    /// ascent to the descent
+    em: f32,
+    /// Bitmaps that have been decoded, by glyph and strike size
+    decoded: RefCell<HashMap<(u16, u16), Option<Rc<ColorBitmap>>>>,
+}
+
+impl<'font> BitmapGlyphs<'font> {
+    /// Reads the color bitmaps of a font, if it has any
+    pub fn read(data: &'font [u8]) -> Option<BitmapGlyphs<'font>> {
+        let head = sfnt::find_table(data, *b"head")?;
+        let hhea = sfnt::find_table(data, *b"hhea")?;
+        let units_per_em = sfnt::read_u16(head, 18)? as f32;
+        let height = sfnt::read_i16(hhea, 4)? as f32 - sfnt::read_i16(hhea, 6)? as f32;
+        let (tables, mut strikes): (_, Vec<(u16, usize)>) = match (
+            sfnt::find_table(data, *b"CBLC"),
+            sfnt::find_table(data, *b"CBDT"),
+            sfnt::find_table(data, *b"sbix"),
+        ) {
+            (Some(cblc), Some(cbdt), _) => {
+                let count = sfnt::read_u32(cblc, 4)? as usize;
+                // the y pixels per em of each bitmap size record
+                let strikes = (0..count)
+                    .map(|i| 8 + i * 48)
+                    .filter_map(|record| Some((cblc.get(record + 45).cloned()? as u16, record)))
+                    .collect();
+                (
+                    BitmapTables::Cbdt {
+                        cblc: cblc,
+                        cbdt: cbdt,
+                    },
+                    strikes,
+                )
+            }
+            (_, _, Some(sbix)) => {
+                let maxp = sfnt::find_table(data, *b"maxp")?;
+                let count = sfnt::read_u32(sbix, 4)? as usize;
+                let strikes = (0..count)
+                    .filter_map(|i| {
+                        let offset = sfnt::read_u32(sbix, 8 + i * 4)? as usize;
+                        Some((sfnt::read_u16(sbix, offset)?, offset))
+                    })
+                    .collect();
+                let glyph_count = sfnt::read_u16(maxp, 4)? as usize;
+                (
+                    BitmapTables::Sbix {
+                        sbix: sbix,
+                        glyph_count: glyph_count,
+                    },
+                    strikes,
+                )
+            }
+            _ => return None,
+        };
+        strikes.retain(|&(ppem, _)| ppem > 0);
+        strikes.sort();
+        if strikes.is_empty() || height <= 0.0 {
+            return None;
+        }
+        Some(BitmapGlyphs {
+            tables: tables,
+            strikes: strikes,
+            em: units_per_em / height,
+            decoded: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the bitmap of a glyph for text of a size, from the smallest strike that is at
+    /// least as big, or else the biggest one. Returns None if the glyph has no PNG bitmap.
+    pub fn bitmap(&self, glyph: u16, size: f32) -> Option<Rc<ColorBitmap>> {
+        let ppem = size * self.em;
+        let mut strikes: Vec<(u16, usize)> = self
+            .strikes
+            .iter()
+            .cloned()
+            .filter(|&(p, _)| p as f32 >= ppem)
+            .collect();
+        strikes.extend(
+            self.strikes
+                .iter()
+                .rev()
+                .filter(|&&(p, _)| (p as f32) < ppem),
+        );
+        for (strike_ppem, strike) in strikes {
+            let key = (glyph, strike_ppem);
+            if let Some(bitmap) = self.decoded.borrow().get(&key) {
+                return bitmap.clone();
+            }
+            let bitmap = match self.png(glyph, strike) {
+                Some((png, left, top)) => decode_png(png).map(|(width, height, pixels)| {
+                    // from pixels of the strike to multiples of the text size
+                    let unit = self.em / strike_ppem as f32;
+                    let (left, top) = (left as f32 * unit, -top as f32 * unit);
+                    Rc::new(ColorBitmap {
+                        glyph: glyph,
+                        ppem: strike_ppem,
+                        width: width,
+                        height: height,
+                        pixels: pixels,
+                        rect: Rect {
+                            min: point(left, top),
+                            max: point(left + width as f32 * unit, top + height as f32 * unit),
+                        },
+                    })
+                }),
+                // the strike doesn't have the glyph
+                None => continue,
+            };
+            self.decoded.borrow_mut().insert(key, bitmap.clone());
+            return bitmap;
+        }
+        None
+    }
+
+    /// Finds the PNG of a glyph in a strike, with its left bearing and the distance from the
+    /// baseline up to its top, in pixels
+    fn png(&self, glyph: u16, strike: usize) -> Option<(&'font [u8], i32, i32)> {
+        match self.tables {
+            BitmapTables::Cbdt { cblc, cbdt } => cbdt_png(cblc, cbdt, strike, glyph),
+            BitmapTables::Sbix { sbix, glyph_count } => sbix_png(sbix, glyph_count, strike, glyph),
+        }
+    }
+}
+
+/// Finds a glyph's PNG in a bitmap size record of CBLC
+fn cbdt_png<'a>(
+    cblc: &[u8],
+    cbdt: &'a [u8],
+    size_record: usize,
+    glyph: u16,
+) -> Option<(&'a [u8], i32, i32)> {
+    let array_offset = sfnt::read_u32(cblc, size_record)? as usize;
+    let subtable_count = sfnt::read_u32(cblc, size_record + 8)? as usize;
+    // index subtables cover ranges of glyphs
+    let (first, subtable) = (0..subtable_count)
+        .map(|i| array_offset + i * 8)
+        .filter_map(|record| {
+            let first = sfnt::read_u16(cblc, record)?;
+            let last = sfnt::read_u16(cblc, record + 2)?;
+            let offset = sfnt::read_u32(cblc, record + 4)? as usize;
+            if first <= glyph && glyph <= last {
+                Some((first, array_offset + offset))
+            } else {
+                None
+            }
+        })
+        .next()?;
+    let index = (glyph - first) as usize;
+    let index_format = sfnt::read_u16(cblc, subtable)?;
+    let image_format = sfnt::read_u16(cblc, subtable + 2)?;
+    let image_data = sfnt::read_u32(cblc, subtable + 4)? as usize;
+    // where the glyph's data is in CBDT, and its metrics if all glyphs of the subtable share them
+    let (start, end, metrics) = match index_format {
+        1 => {
+            let start = sfnt::read_u32(cblc, subtable + 8 + index * 4)? as usize;
+            let end = sfnt::read_u32(cblc, subtable + 8 + index * 4 + 4)? as usize;
+            (start, end, None)
+        }
+        2 => {
+            let size = sfnt::read_u32(cblc, subtable + 8)? as usize;
+            (index * size, (index + 1) * size, Some(subtable + 12))
+        }
+        3 => {
+            let start = sfnt::read_u16(cblc, subtable + 8 + index * 2)? as usize;
+            let end = sfnt::read_u16(cblc, subtable + 8 + index * 2 + 2)? as usize;
+            (start, end, None)
+        }
+        4 => {
+            let count = sfnt::read_u32(cblc, subtable + 8)? as usize;
+            let pair = (0..count)
+                .map(|i| subtable + 12 + i * 4)
+                .find(|&pair| sfnt::read_u16(cblc, pair) == Some(glyph))?;
+            let start = sfnt::read_u16(cblc, pair + 2)? as usize;
+            let end = sfnt::read_u16(cblc, pair + 6)? as usize;
+            (start, end, None)
+        }
+        5 => {
+            let size = sfnt::read_u32(cblc, subtable + 8)? as usize;
+            let count = sfnt::read_u32(cblc, subtable + 20)? as usize;
+            let i =
+                (0..count).find(|&i| sfnt::read_u16(cblc, subtable + 24 + i * 2) == Some(glyph))?;
+            (i * size, (i + 1) * size, Some(subtable + 12))
+        }
+        _ => return None,
+    };
+    if end <= start {
+        return None;
+    }
+    let data = cbdt.get(image_data + start..image_data + end)?;
+    let signed = |table: &[u8], offset: usize| table.get(offset).map(|&b| b as i8 as i32);
+    // small metrics are height, width, bearing x, bearing y and advance, and big metrics then
+    // have the vertical ones
+    let (left, top, png) = match (image_format, metrics) {
+        (17, _) => (signed(data, 2)?, signed(data, 3)?, 9),
+        (18, _) => (signed(data, 2)?, signed(data, 3)?, 12),
+        (19, Some(metrics)) => (signed(cblc, metrics + 2)?, signed(cblc, metrics + 3)?, 4),
+        _ => return None,
+    };
+    let length = sfnt::read_u32(data, png - 4)? as usize;
+    Some((data.get(png..png + length)?, left, top))
+}
+
+/// Finds a glyph's PNG in an sbix strike, following glyphs that reuse another glyph's bitmap
+fn sbix_png(
+    sbix: &[u8],
+    glyph_count: usize,
+    strike: usize,
+    glyph: u16,
+) -> Option<(&[u8], i32, i32)> {
+    let mut glyph = glyph as usize;
+    // a dupe can point to another dupe, but not forever
+    for _ in 0..4 {
+        if glyph >= glyph_count {
+            return None;
+        }
+        let start = sfnt::read_u32(sbix, strike + 4 + glyph * 4)? as usize;
+        let end = sfnt::read_u32(sbix, strike + 4 + glyph * 4 + 4)? as usize;
+        let data = sbix.get(strike + start..strike + end)?;
+        match &sfnt::read_tag(data, 4)? {
+            // the offsets are to the bottom left corner of the bitmap, with y up
+            b"png " => {
+                let png = data.get(8..)?;
+                let left = sfnt::read_i16(data, 0)? as i32;
+                let bottom = sfnt::read_i16(data, 2)? as i32;
+                let height = sfnt::read_u32(png, 20)? as i32;
+                return Some((png, left, bottom + height));
+            }
+            b"dupe" => glyph = sfnt::read_u16(data, 8)? as usize,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Decodes a PNG into its width, height and RGBA bytes
+fn decode_png(data: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(
+        png::Transformations::EXPAND | png::Transformations::STRIP_16 | png::Transformations::ALPHA,
+    );
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).ok()?;
+    buffer.truncate(info.buffer_size());
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        _ => return None,
+    };
+    Some((info.width, info.height, pixels))
+}
+
+/// Returns if a character is an emoji
+pub fn is_emoji(c: char) -> bool {
+    match c as u32 {
+        0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x1F000..=0x1FAFF => true,
+        _ => false,
+    }
+}
+
+/// Returns if a character has no appearance of its own, such as joiners, variation selectors and
+/// soft hyphens
+pub fn is_invisible(c: char) -> bool {
+    match c as u32 {
+        0xAD | 0x200B..=0x200F | 0x2060..=0x2064 | 0xFE00..=0xFE0F | 0xE0000..=0xE0FFF => true,
+        _ => false,
+    }
+}
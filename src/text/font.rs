@@ -0,0 +1,325 @@
+use rusttype::{Font, FontCollection};
+
+use text::color::{BitmapGlyphs, ColorGlyphs};
+use text::gsub::Gsub;
+use text::instance;
+use text::sfnt;
+use text::style::{Tag, TextStyle};
+
+/// An axis of a variable font, such as weight or width
+pub struct VariationAxis {
+    pub tag: Tag,
+    pub min: f32,
+    pub default: f32,
+    pub max: f32,
+}
+
+pub struct Face<'font> {
+    pub family: String,
+    pub font: Font<'font>,
+    pub bold: bool,
+    pub italic: bool,
+    pub axes: Vec<VariationAxis>,
+    pub colors: Option<ColorGlyphs>,
+    /// Color bitmaps, such as emoji, that are drawn instead of the outlines of their glyphs
+    pub bitmaps: Option<BitmapGlyphs<'font>>,
+    /// Substitutions for OpenType features such as liga, smcp or ss01
+    pub gsub: Option<Gsub<'font>>,
+    /// The font file, which instances are built from
+    data: &'font [u8],
+}
+
+impl<'font> Face<'font> {
+    /// Returns if the face has substitutions for an OpenType feature. Features it doesn't have
+    /// are ignored when set in a style, apart from kern, which uses the kern table, and tnum,
+    /// which is faked by giving digits the width of the widest one.
+    pub fn has_feature(&self, tag: Tag) -> bool {
+        self.gsub
+            .as_ref()
+            .map_or(false, |gsub| gsub.features().contains(&tag))
+    }
+}
+
+/// Bold and italic that are faked because the family has no face with them. They are baked into
+/// the outlines of the face's instances, so glyphs are drawn once like any other.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Synthesis {
+    /// How much thicker strokes are made, relative to the font size. Advances grow by as much.
+    pub embolden: f32,
+    /// Horizontal shear that slants glyphs, as x offset per unit above the baseline
+    pub skew: f32,
+}
+
+/// Slant of synthetic oblique text, about 12 degrees
+const OBLIQUE_SKEW: f32 = 0.21;
+
+/// Strengths of synthetic bold for text up to a size, and the strength for bigger text. Each
+/// strength is an instance that copies the whole font, so there are only a few, chosen to make
+/// strokes at least about a pixel thicker.
+const EMBOLDEN_STEPS: &[(f32, f32)] = &[(12.0, 1.0 / 12.0), (20.0, 1.0 / 20.0)];
+const EMBOLDEN: f32 = 1.0 / 32.0;
+
+/// Most instances that are built. After that, styles get the closest instance of their face that
+/// already exists.
+const MAX_INSTANCES: usize = 32;
+
+/// Index of a face in a FontSet
+pub type FaceId = usize;
+
+/// Identifies a face at a set of variation axis values. It is used as the glyph cache font ID so
+/// glyphs from different instances of a face are cached separately.
+pub type InstanceId = usize;
+
+/// A face at a set of variation axis values, with bold or oblique synthesized
+struct Instance<'font> {
+    face: FaceId,
+    coords: Vec<(Tag, f32)>,
+    synthesis: Synthesis,
+    /// The face's font with the outlines of the instance, or the face's own font at the default
+    /// axis values without synthesis
+    font: Font<'font>,
+}
+
+/// All the fonts text can be laid out with
+pub struct FontSet<'font> {
+    faces: Vec<Face<'font>>,
+    instances: Vec<Instance<'font>>,
+}
+
+impl<'font> FontSet<'font> {
+    pub fn new() -> FontSet<'font> {
+        FontSet {
+            faces: Vec::new(),
+            instances: Vec::new(),
+        }
+    }
+
+    /// Loads a font file, which may be a variable font. Whether the face is bold or italic is read
+    /// from the font.
+    pub fn load(&mut self, family: &str, data: &'font [u8]) -> Result<FaceId, String> {
+        let bitmaps = BitmapGlyphs::read(data);
+        let font = if bitmaps.is_some() && sfnt::find_table(data, *b"glyf").is_none() {
+            // rusttype can't load a font without outlines, so bitmap only fonts get empty ones
+            match empty_outlines(data).and_then(|data| Font::from_bytes(data).ok()) {
+                Some(f) => f,
+                None => return Err("error adding outlines to a bitmap font".to_string()),
+            }
+        } else {
+            let collection = match FontCollection::from_bytes(data) {
+                Ok(c) => c,
+                Err(e) => return Err(format!("error constructing a FontCollection: {}", e)),
+            };
+            // only succeeds if collection consists of one font
+            match collection.into_font() {
+                Ok(f) => f,
+                Err(e) => {
+                    return Err(format!(
+                        "collection.into_font failed, perhaps it has multiple fonts? error: {}",
+                        e
+                    ))
+                }
+            }
+        };
+        // bit 0 of macStyle is bold and bit 1 is italic
+        let mac_style = sfnt::find_table(data, *b"head")
+            .and_then(|head| sfnt::read_u16(head, 44))
+            .unwrap_or(0);
+        self.faces.push(Face {
+            family: family.to_string(),
+            font: font,
+            bold: mac_style & 1 != 0,
+            italic: mac_style & 2 != 0,
+            axes: read_axes(data),
+            colors: ColorGlyphs::read(data),
+            bitmaps: bitmaps,
+            gsub: Gsub::read(data),
+            data: data,
+        });
+        Ok(self.faces.len() - 1)
+    }
+
+    pub fn face(&self, id: FaceId) -> &Face<'font> {
+        &self.faces[id]
+    }
+
+    /// Returns the faces to look for a character in, with the face of the style first and then
+    /// the others in the order they were loaded
+    pub fn fallbacks(&self, face: FaceId) -> Vec<FaceId> {
+        let mut faces = vec![face];
+        faces.extend((0..self.faces.len()).filter(|&f| f != face));
+        faces
+    }
+
+    /// Returns the face a style should be drawn with, and what has to be synthesized because the
+    /// face doesn't match the style. Returns None if no fonts have been loaded.
+    pub fn select(&self, style: &TextStyle) -> Option<(FaceId, Synthesis)> {
+        if self.faces.is_empty() {
+            return None;
+        }
+        // a face missing bold or italic can be made up for, but one that has it when the style
+        // doesn't want it can't
+        let cost = |has: bool, wants: bool| match (has, wants) {
+            (false, true) => 1,
+            (true, false) => 2,
+            _ => 0,
+        };
+        let face = self
+            .faces
+            .iter()
+            .enumerate()
+            .filter(|&(_, face)| face.family == style.family)
+            .min_by_key(|&(_, face)| cost(face.bold, style.bold) + cost(face.italic, style.italic))
+            .map(|(id, _)| id)
+            .unwrap_or(0);
+        let synthesis = Synthesis {
+            embolden: if style.bold && !self.faces[face].bold {
+                EMBOLDEN_STEPS
+                    .iter()
+                    .find(|&&(size, _)| style.size <= size)
+                    .map_or(EMBOLDEN, |&(_, strength)| strength)
+            } else {
+                0.0
+            },
+            skew: if style.italic && !self.faces[face].italic {
+                OBLIQUE_SKEW
+            } else {
+                0.0
+            },
+        };
+        Some((face, synthesis))
+    }
+
+    /// Returns the instance of a face at the given axis values, with bold or oblique synthesized.
+    /// Values are clamped to the range of their axis, and ones for axes the face doesn't have are
+    /// ignored. Instances away from the default build a new font with the gvar deltas and the
+    /// synthesis applied to its outlines, which is kept for later calls. Once MAX_INSTANCES have
+    /// been built, the closest existing instance of the face is returned instead.
+    pub fn instance(
+        &mut self,
+        face: FaceId,
+        variations: &[(Tag, f32)],
+        synthesis: Synthesis,
+    ) -> InstanceId {
+        let coords: Vec<(Tag, f32)> = self.faces[face]
+            .axes
+            .iter()
+            .map(|axis| {
+                let value = variations
+                    .iter()
+                    .rev()
+                    .find(|&&(tag, _)| tag == axis.tag)
+                    .map(|&(_, value)| value.max(axis.min).min(axis.max))
+                    .unwrap_or(axis.default);
+                (axis.tag, value)
+            })
+            .collect();
+        if let Some(id) = self
+            .instances
+            .iter()
+            .position(|i| i.face == face && i.coords == coords && i.synthesis == synthesis)
+        {
+            return id;
+        }
+        if self.instances.len() >= MAX_INSTANCES {
+            // an instance with the same bold and oblique is closer than any at nearer axis values
+            let distance = |i: &Instance| {
+                let bold = (i.synthesis.embolden > 0.0) != (synthesis.embolden > 0.0);
+                let oblique = (i.synthesis.skew > 0.0) != (synthesis.skew > 0.0);
+                let coords: f32 = i
+                    .coords
+                    .iter()
+                    .zip(&coords)
+                    .map(|(&(_, a), &(_, b))| (a - b).abs())
+                    .sum();
+                (bold as u8 + oblique as u8, coords)
+            };
+            let closest = self
+                .instances
+                .iter()
+                .enumerate()
+                .filter(|&(_, i)| i.face == face)
+                .min_by(|&(_, a), &(_, b)| distance(a).partial_cmp(&distance(b)).unwrap())
+                .map(|(id, _)| id);
+            if let Some(id) = closest {
+                return id;
+            }
+        }
+        let font = {
+            let face = &self.faces[face];
+            let values: Vec<f32> = coords.iter().map(|&(_, value)| value).collect();
+            let normalized = instance::normalize(face.data, &face.axes, &values);
+            // synthesis is relative to the font size, which rusttype scales to the height from
+            // the ascender to the descender
+            let height = sfnt::find_table(face.data, *b"hhea")
+                .and_then(|hhea| {
+                    Some(sfnt::read_i16(hhea, 4)? as f32 - sfnt::read_i16(hhea, 6)? as f32)
+                })
+                .unwrap_or(0.0);
+            let embolden = synthesis.embolden * height;
+            if normalized.iter().all(|&c| c == 0.0) && synthesis == Synthesis::default() {
+                face.font.clone()
+            } else {
+                // a font that can't be instanced is drawn with its default outlines
+                instance::build(face.data, &normalized, embolden, synthesis.skew)
+                    .and_then(|data| Font::from_bytes(data).ok())
+                    .unwrap_or_else(|| face.font.clone())
+            }
+        };
+        self.instances.push(Instance {
+            face: face,
+            coords: coords,
+            synthesis: synthesis,
+            font: font,
+        });
+        self.instances.len() - 1
+    }
+
+    /// The font glyphs of an instance are shaped and drawn with
+    pub fn font(&self, id: InstanceId) -> &Font<'font> {
+        &self.instances[id].font
+    }
+}
+
+/// Copies a font that has no glyf table, such as a bitmap only font, with empty outlines for all
+/// of its glyphs
+fn empty_outlines(data: &[u8]) -> Option<Vec<u8>> {
+    let glyph_count = sfnt::read_u16(sfnt::find_table(data, *b"maxp")?, 4)? as usize;
+    let mut tables: Vec<(Tag, Vec<u8>)> = sfnt::tables(data)?
+        .into_iter()
+        .filter(|&(tag, _)| tag != *b"loca")
+        .map(|(tag, table)| (tag, table.to_vec()))
+        .collect();
+    for &mut (tag, ref mut table) in &mut tables {
+        if tag == *b"head" && table.len() >= 54 {
+            // no checkSumAdjustment yet, and long loca offsets
+            sfnt::set_u16(table, 8, 0);
+            sfnt::set_u16(table, 10, 0);
+            sfnt::set_u16(table, 50, 1);
+        }
+    }
+    tables.push((*b"loca", vec![0; (glyph_count + 1) * 4]));
+    tables.push((*b"glyf", Vec::new()));
+    Some(sfnt::write_font(tables))
+}
+
+/// Reads the variation axes from the fvar table. Fonts that aren't variable have none.
+fn read_axes(data: &[u8]) -> Vec<VariationAxis> {
+    let fvar = match sfnt::find_table(data, *b"fvar") {
+        Some(t) => t,
+        None => return Vec::new(),
+    };
+    let axes_offset = sfnt::read_u16(fvar, 4).unwrap_or(0) as usize;
+    let axis_count = sfnt::read_u16(fvar, 8).unwrap_or(0) as usize;
+    let axis_size = sfnt::read_u16(fvar, 10).unwrap_or(0) as usize;
+    (0..axis_count)
+        .filter_map(|i| {
+            let record = axes_offset + i * axis_size;
+            Some(VariationAxis {
+                tag: sfnt::read_tag(fvar, record)?,
+                min: sfnt::read_fixed(fvar, record + 4)?,
+                default: sfnt::read_fixed(fvar, record + 8)?,
+                max: sfnt::read_fixed(fvar, record + 12)?,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,758 @@
+//! Glyph substitution with the GSUB table, which fonts use for ligatures, stylistic sets, small
+//! capitals and most other features that change which glyphs are drawn. All eight lookup types
+//! are applied, and lookup flags skip glyphs by their GDEF class. The Arabic joining forms (init,
+//! medi, fina and isol) need the joining types of the text to pick between them, so they aren't
+//! applied.
+
+use std::ops::Range;
+
+use text::sfnt;
+use text::style::Tag;
+
+/// How deep contextual lookups can nest, so fonts with loops can't recurse forever
+const MAX_NESTING: usize = 8;
+
+/// Scripts looked for when a font has no lookups for the script of the text
+const DEFAULT_SCRIPTS: &[Tag] = &[*b"DFLT", *b"dflt", *b"latn"];
+
+/// A glyph being substituted, and the byte range of the text it stands for
+#[derive(Clone)]
+pub struct GlyphInfo {
+    pub id: u16,
+    pub range: Range<usize>,
+}
+
+struct Lookup<'a> {
+    kind: u16,
+    flag: u16,
+    /// With extension subtables replaced by the subtables they point to
+    subtables: Vec<&'a [u8]>,
+    /// Index of the mark glyph set in GDEF of marks that aren't skipped
+    mark_set: Option<u16>,
+}
+
+pub struct Gsub<'a> {
+    data: &'a [u8],
+    lookups: Vec<Lookup<'a>>,
+    gdef: Option<&'a [u8]>,
+}
+
+/// Returns the table at a 16 bit offset stored at a position in a table, or None for a null
+/// offset
+fn at(table: &[u8], position: usize) -> Option<&[u8]> {
+    match sfnt::read_u16(table, position)? as usize {
+        0 => None,
+        offset => table.get(offset..),
+    }
+}
+
+/// Returns the index of a glyph in a coverage table, or None if it isn't covered
+fn coverage(table: &[u8], glyph: u16) -> Option<usize> {
+    let count = sfnt::read_u16(table, 2)? as usize;
+    match sfnt::read_u16(table, 0)? {
+        1 => {
+            let (mut lo, mut hi) = (0, count);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let g = sfnt::read_u16(table, 4 + mid * 2)?;
+                if g == glyph {
+                    return Some(mid);
+                } else if g < glyph {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            None
+        }
+        2 => {
+            let (mut lo, mut hi) = (0, count);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let record = 4 + mid * 6;
+                let start = sfnt::read_u16(table, record)?;
+                let end = sfnt::read_u16(table, record + 2)?;
+                if glyph < start {
+                    hi = mid;
+                } else if glyph > end {
+                    lo = mid + 1;
+                } else {
+                    let first = sfnt::read_u16(table, record + 4)? as usize;
+                    return Some(first + (glyph - start) as usize);
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Returns the class of a glyph in a class definition table, which is 0 for glyphs it doesn't
+/// list
+fn class(table: &[u8], glyph: u16) -> u16 {
+    let class = match sfnt::read_u16(table, 0) {
+        Some(1) => sfnt::read_u16(table, 2).and_then(|start| {
+            let count = sfnt::read_u16(table, 4)?;
+            if glyph >= start && glyph - start < count {
+                sfnt::read_u16(table, 6 + (glyph - start) as usize * 2)
+            } else {
+                None
+            }
+        }),
+        Some(2) => sfnt::read_u16(table, 2).and_then(|count| {
+            (0..count as usize)
+                .map(|i| 4 + i * 6)
+                .find(|&record| {
+                    sfnt::read_u16(table, record).map_or(false, |start| start <= glyph)
+                        && sfnt::read_u16(table, record + 2).map_or(false, |end| glyph <= end)
+                })
+                .and_then(|record| sfnt::read_u16(table, record + 4))
+        }),
+        _ => None,
+    };
+    class.unwrap_or(0)
+}
+
+/// Ranges of characters in a script, and the tags fonts use for it
+const SCRIPTS: &[(u32, u32, &[Tag])] = &[
+    (0x41, 0x5A, &[*b"latn"]),
+    (0x61, 0x7A, &[*b"latn"]),
+    (0xC0, 0x24F, &[*b"latn"]),
+    (0x370, 0x3FF, &[*b"grek"]),
+    (0x400, 0x52F, &[*b"cyrl"]),
+    (0x530, 0x58F, &[*b"armn"]),
+    (0x590, 0x5FF, &[*b"hebr"]),
+    (0x600, 0x6FF, &[*b"arab"]),
+    (0x750, 0x77F, &[*b"arab"]),
+    (0x900, 0x97F, &[*b"dev2", *b"deva"]),
+    (0xE00, 0xE7F, &[*b"thai"]),
+    (0x1100, 0x11FF, &[*b"hang"]),
+    (0x1E00, 0x1EFF, &[*b"latn"]),
+    (0x1F00, 0x1FFF, &[*b"grek"]),
+    (0x3040, 0x30FF, &[*b"kana"]),
+    (0x3400, 0x4DBF, &[*b"hani"]),
+    (0x4E00, 0x9FFF, &[*b"hani"]),
+    (0xAC00, 0xD7AF, &[*b"hang"]),
+    (0xFB50, 0xFDFF, &[*b"arab"]),
+    (0xFE70, 0xFEFF, &[*b"arab"]),
+];
+
+/// Returns the OpenType tags of the script of text, from its first character that belongs to a
+/// script. Text of only digits, spaces and punctuation has none.
+pub fn script_tags<I>(chars: I) -> &'static [Tag]
+where
+    I: Iterator<Item = char>,
+{
+    for c in chars {
+        let c = c as u32;
+        if let Some(&(_, _, tags)) = SCRIPTS
+            .iter()
+            .find(|&&(start, end, _)| start <= c && c <= end)
+        {
+            return tags;
+        }
+    }
+    &[]
+}
+
+impl<'a> Gsub<'a> {
+    /// Reads the GSUB table of a font, if it has one
+    pub fn read(font: &'a [u8]) -> Option<Gsub<'a>> {
+        let data = sfnt::find_table(font, *b"GSUB")?;
+        let list = at(data, 8)?;
+        let count = sfnt::read_u16(list, 0)? as usize;
+        let lookups = (0..count)
+            .map(|i| {
+                read_lookup(at(list, 2 + i * 2)).unwrap_or(Lookup {
+                    kind: 0,
+                    flag: 0,
+                    subtables: Vec::new(),
+                    mark_set: None,
+                })
+            })
+            .collect();
+        Some(Gsub {
+            data: data,
+            lookups: lookups,
+            gdef: sfnt::find_table(font, *b"GDEF"),
+        })
+    }
+
+    /// Tags of the features the font has lookups for
+    pub fn features(&self) -> Vec<Tag> {
+        let list = match at(self.data, 6) {
+            Some(list) => list,
+            None => return Vec::new(),
+        };
+        let count = sfnt::read_u16(list, 0).unwrap_or(0) as usize;
+        let mut tags: Vec<Tag> = (0..count)
+            .filter_map(|i| sfnt::read_tag(list, 2 + i * 6))
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Tags of the scripts the font has lookups for
+    pub fn scripts(&self) -> Vec<Tag> {
+        let list = match at(self.data, 4) {
+            Some(list) => list,
+            None => return Vec::new(),
+        };
+        let count = sfnt::read_u16(list, 0).unwrap_or(0) as usize;
+        (0..count)
+            .filter_map(|i| sfnt::read_tag(list, 2 + i * 6))
+            .collect()
+    }
+
+    /// Indices of the lookups of features in the order they are applied, for the first of the
+    /// scripts the font has, or for its default script. The default language system of the
+    /// script is used.
+    pub fn lookups(&self, scripts: &[Tag], features: &[Tag]) -> Vec<u16> {
+        let mut lookups = self.find_lookups(scripts, features).unwrap_or_default();
+        lookups.sort();
+        lookups.dedup();
+        lookups
+    }
+
+    fn find_lookups(&self, scripts: &[Tag], features: &[Tag]) -> Option<Vec<u16>> {
+        let script_list = at(self.data, 4)?;
+        let feature_list = at(self.data, 6)?;
+        let script_count = sfnt::read_u16(script_list, 0)? as usize;
+        let script = scripts
+            .iter()
+            .chain(DEFAULT_SCRIPTS)
+            .filter_map(|&tag| {
+                (0..script_count)
+                    .find(|&i| sfnt::read_tag(script_list, 2 + i * 6) == Some(tag))
+                    .and_then(|i| at(script_list, 2 + i * 6 + 4))
+            })
+            .next()?;
+        let lang_sys = at(script, 0)?;
+        let required = sfnt::read_u16(lang_sys, 2)?;
+        let count = sfnt::read_u16(lang_sys, 4)? as usize;
+        let mut indices: Vec<u16> = (0..count)
+            .filter_map(|i| sfnt::read_u16(lang_sys, 6 + i * 2))
+            .collect();
+        let mut lookups = Vec::new();
+        if required != 0xFFFF {
+            indices.push(required);
+        }
+        for index in indices {
+            let record = 2 + index as usize * 6;
+            let tag = match sfnt::read_tag(feature_list, record) {
+                Some(tag) => tag,
+                None => continue,
+            };
+            if index != required && !features.contains(&tag) {
+                continue;
+            }
+            let feature = match at(feature_list, record + 4) {
+                Some(feature) => feature,
+                None => continue,
+            };
+            let lookup_count = sfnt::read_u16(feature, 2).unwrap_or(0) as usize;
+            lookups.extend((0..lookup_count).filter_map(|i| sfnt::read_u16(feature, 4 + i * 2)));
+        }
+        Some(lookups)
+    }
+
+    /// Applies lookups to glyphs in logical order, one lookup after another over all of them
+    pub fn apply(&self, lookups: &[u16], glyphs: &mut Vec<GlyphInfo>) {
+        for &index in lookups {
+            let lookup = match self.lookups.get(index as usize) {
+                Some(lookup) => lookup,
+                None => continue,
+            };
+            if lookup.kind == 8 {
+                // reverse chaining substitutions go from the end to the start
+                for i in (0..glyphs.len()).rev() {
+                    if !self.skipped(lookup, glyphs[i].id) {
+                        self.apply_at(lookup, glyphs, i, 0);
+                    }
+                }
+                continue;
+            }
+            let mut i = 0;
+            while i < glyphs.len() {
+                if !self.skipped(lookup, glyphs[i].id) {
+                    let count = glyphs.len();
+                    if let Some(next) = self.apply_at(lookup, glyphs, i, 0) {
+                        // a glyph that was deleted leaves the next one at i, which the lookup
+                        // still has to see
+                        let deleted = next == i && glyphs.len() < count;
+                        i = if deleted { i } else { next.max(i + 1) };
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns if a lookup passes over a glyph because of its class
+    fn skipped(&self, lookup: &Lookup, glyph: u16) -> bool {
+        if lookup.flag & 0xFF1E == 0 {
+            return false;
+        }
+        let gdef = match self.gdef {
+            Some(gdef) => gdef,
+            None => return false,
+        };
+        match at(gdef, 4).map_or(0, |classes| class(classes, glyph)) {
+            1 => lookup.flag & 0x2 != 0,
+            2 => lookup.flag & 0x4 != 0,
+            3 => {
+                if lookup.flag & 0x8 != 0 {
+                    return true;
+                }
+                if let Some(set) = lookup.mark_set {
+                    return !in_mark_set(gdef, set, glyph);
+                }
+                let attach_type = lookup.flag >> 8;
+                attach_type != 0 && at(gdef, 10).map_or(0, |c| class(c, glyph)) != attach_type
+            }
+            _ => false,
+        }
+    }
+
+    /// The next glyph after a position that the lookup doesn't skip
+    fn next(&self, lookup: &Lookup, glyphs: &[GlyphInfo], pos: usize) -> Option<usize> {
+        (pos + 1..glyphs.len()).find(|&p| !self.skipped(lookup, glyphs[p].id))
+    }
+
+    /// The last glyph before a position that the lookup doesn't skip
+    fn prev(&self, lookup: &Lookup, glyphs: &[GlyphInfo], pos: usize) -> Option<usize> {
+        (0..pos)
+            .rev()
+            .find(|&p| !self.skipped(lookup, glyphs[p].id))
+    }
+
+    /// Matches count glyphs after the one at start, returning the positions of start and them
+    fn match_input<F>(
+        &self,
+        lookup: &Lookup,
+        glyphs: &[GlyphInfo],
+        start: usize,
+        count: usize,
+        matches: F,
+    ) -> Option<Vec<usize>>
+    where
+        F: Fn(usize, u16) -> bool,
+    {
+        let mut positions = vec![start];
+        let mut pos = start;
+        for n in 0..count {
+            pos = self.next(lookup, glyphs, pos)?;
+            if !matches(n, glyphs[pos].id) {
+                return None;
+            }
+            positions.push(pos);
+        }
+        Some(positions)
+    }
+
+    /// Returns if count glyphs before a position match, going backwards from it
+    fn match_backtrack<F>(
+        &self,
+        lookup: &Lookup,
+        glyphs: &[GlyphInfo],
+        start: usize,
+        count: usize,
+        matches: F,
+    ) -> bool
+    where
+        F: Fn(usize, u16) -> bool,
+    {
+        let mut pos = start;
+        for n in 0..count {
+            pos = match self.prev(lookup, glyphs, pos) {
+                Some(p) => p,
+                None => return false,
+            };
+            if !matches(n, glyphs[pos].id) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns if count glyphs after a position match
+    fn match_lookahead<F>(
+        &self,
+        lookup: &Lookup,
+        glyphs: &[GlyphInfo],
+        last: usize,
+        count: usize,
+        matches: F,
+    ) -> bool
+    where
+        F: Fn(usize, u16) -> bool,
+    {
+        self.match_input(lookup, glyphs, last, count, matches)
+            .is_some()
+    }
+
+    /// Applies the first subtable of a lookup that matches at a glyph, and returns the position
+    /// to carry on from
+    fn apply_at(
+        &self,
+        lookup: &Lookup,
+        glyphs: &mut Vec<GlyphInfo>,
+        i: usize,
+        depth: usize,
+    ) -> Option<usize> {
+        for &subtable in &lookup.subtables {
+            let applied = match lookup.kind {
+                1 => single(subtable, glyphs, i),
+                2 => multiple(subtable, glyphs, i),
+                3 => alternate(subtable, glyphs, i),
+                4 => self.ligature(lookup, subtable, glyphs, i),
+                5 => self.context(lookup, subtable, glyphs, i, depth),
+                6 => self.chain_context(lookup, subtable, glyphs, i, depth),
+                8 => self.reverse_chain(lookup, subtable, glyphs, i),
+                _ => None,
+            };
+            if applied.is_some() {
+                return applied;
+            }
+        }
+        None
+    }
+
+    fn ligature(
+        &self,
+        lookup: &Lookup,
+        table: &[u8],
+        glyphs: &mut Vec<GlyphInfo>,
+        i: usize,
+    ) -> Option<usize> {
+        let index = coverage(at(table, 2)?, glyphs[i].id)?;
+        let set = at(table, 6 + index * 2)?;
+        for k in 0..sfnt::read_u16(set, 0)? as usize {
+            let ligature = at(set, 2 + k * 2)?;
+            let glyph = sfnt::read_u16(ligature, 0)?;
+            let count = (sfnt::read_u16(ligature, 2)? as usize).saturating_sub(1);
+            let components = |n: usize, id: u16| sfnt::read_u16(ligature, 4 + n * 2) == Some(id);
+            if let Some(positions) = self.match_input(lookup, glyphs, i, count, components) {
+                let start = positions.iter().map(|&p| glyphs[p].range.start).min()?;
+                let end = positions.iter().map(|&p| glyphs[p].range.end).max()?;
+                // skipped glyphs between the components, such as marks, stay after the ligature
+                for &p in positions[1..].iter().rev() {
+                    glyphs.remove(p);
+                }
+                glyphs[i] = GlyphInfo {
+                    id: glyph,
+                    range: start..end,
+                };
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
+    fn context(
+        &self,
+        lookup: &Lookup,
+        table: &[u8],
+        glyphs: &mut Vec<GlyphInfo>,
+        i: usize,
+        depth: usize,
+    ) -> Option<usize> {
+        let first = glyphs[i].id;
+        match sfnt::read_u16(table, 0)? {
+            1 | 2 => {
+                let format = sfnt::read_u16(table, 0)?;
+                let index = coverage(at(table, 2)?, first)?;
+                let classes = if format == 2 { at(table, 4) } else { None };
+                let set = match classes {
+                    Some(classes) => at(table, 8 + class(classes, first) as usize * 2)?,
+                    None => at(table, 6 + index * 2)?,
+                };
+                for k in 0..sfnt::read_u16(set, 0)? as usize {
+                    let rule = at(set, 2 + k * 2)?;
+                    let count = (sfnt::read_u16(rule, 0)? as usize).saturating_sub(1);
+                    let records = sfnt::read_u16(rule, 2)? as usize;
+                    let input = |n: usize, id: u16| {
+                        rule_matches(classes, sfnt::read_u16(rule, 4 + n * 2), id)
+                    };
+                    if let Some(positions) = self.match_input(lookup, glyphs, i, count, input) {
+                        let records = (rule, 4 + count * 2, records);
+                        return self.apply_records(glyphs, positions, records, depth);
+                    }
+                }
+                None
+            }
+            3 => {
+                let count = sfnt::read_u16(table, 2)? as usize;
+                let records = sfnt::read_u16(table, 4)? as usize;
+                coverage(at(table, 6)?, first)?;
+                let input = |n: usize, id: u16| {
+                    at(table, 6 + (n + 1) * 2).map_or(false, |c| coverage(c, id).is_some())
+                };
+                let positions =
+                    self.match_input(lookup, glyphs, i, count.saturating_sub(1), input)?;
+                self.apply_records(glyphs, positions, (table, 6 + count * 2, records), depth)
+            }
+            _ => None,
+        }
+    }
+
+    fn chain_context(
+        &self,
+        lookup: &Lookup,
+        table: &[u8],
+        glyphs: &mut Vec<GlyphInfo>,
+        i: usize,
+        depth: usize,
+    ) -> Option<usize> {
+        let first = glyphs[i].id;
+        match sfnt::read_u16(table, 0)? {
+            format @ 1 | format @ 2 => {
+                let index = coverage(at(table, 2)?, first)?;
+                // backtrack, input and lookahead class definitions
+                let classes = if format == 2 {
+                    Some((at(table, 4), at(table, 6)?, at(table, 8)))
+                } else {
+                    None
+                };
+                let set = match classes {
+                    Some((_, input, _)) => at(table, 12 + class(input, first) as usize * 2)?,
+                    None => at(table, 6 + index * 2)?,
+                };
+                // a class definition that is missing puts every glyph in class 0
+                let (backtrack_classes, input_classes, lookahead_classes) = match classes {
+                    Some((b, i, l)) => (Some(b.unwrap_or(&[])), Some(i), Some(l.unwrap_or(&[]))),
+                    None => (None, None, None),
+                };
+                for k in 0..sfnt::read_u16(set, 0)? as usize {
+                    let rule = at(set, 2 + k * 2)?;
+                    let backtrack = sfnt::read_u16(rule, 0)? as usize;
+                    let input_pos = 2 + backtrack * 2;
+                    let input = (sfnt::read_u16(rule, input_pos)? as usize).saturating_sub(1);
+                    let lookahead_pos = input_pos + 2 + input * 2;
+                    let lookahead = sfnt::read_u16(rule, lookahead_pos)? as usize;
+                    let records_pos = lookahead_pos + 2 + lookahead * 2;
+                    let records = sfnt::read_u16(rule, records_pos)? as usize;
+                    let value = |pos: usize, n: usize| sfnt::read_u16(rule, pos + n * 2);
+                    let positions = match self.match_input(lookup, glyphs, i, input, |n, id| {
+                        rule_matches(input_classes, value(input_pos + 2, n), id)
+                    }) {
+                        Some(positions) => positions,
+                        None => continue,
+                    };
+                    let last = *positions.last()?;
+                    if self.match_backtrack(lookup, glyphs, i, backtrack, |n, id| {
+                        rule_matches(backtrack_classes, value(2, n), id)
+                    }) && self.match_lookahead(lookup, glyphs, last, lookahead, |n, id| {
+                        rule_matches(lookahead_classes, value(lookahead_pos + 2, n), id)
+                    }) {
+                        let records = (rule, records_pos + 2, records);
+                        return self.apply_records(glyphs, positions, records, depth);
+                    }
+                }
+                None
+            }
+            3 => {
+                let covered = |pos: usize, n: usize, id: u16| {
+                    at(table, pos + n * 2).map_or(false, |c| coverage(c, id).is_some())
+                };
+                let backtrack = sfnt::read_u16(table, 2)? as usize;
+                let input_pos = 4 + backtrack * 2;
+                let input = sfnt::read_u16(table, input_pos)? as usize;
+                let lookahead_pos = input_pos + 2 + input * 2;
+                let lookahead = sfnt::read_u16(table, lookahead_pos)? as usize;
+                let records_pos = lookahead_pos + 2 + lookahead * 2;
+                let records = sfnt::read_u16(table, records_pos)? as usize;
+                if input == 0 || !covered(input_pos + 2, 0, first) {
+                    return None;
+                }
+                let positions = self.match_input(lookup, glyphs, i, input - 1, |n, id| {
+                    covered(input_pos + 2, n + 1, id)
+                })?;
+                let last = *positions.last()?;
+                if !self.match_backtrack(lookup, glyphs, i, backtrack, |n, id| covered(4, n, id))
+                    || !self.match_lookahead(lookup, glyphs, last, lookahead, |n, id| {
+                        covered(lookahead_pos + 2, n, id)
+                    })
+                {
+                    return None;
+                }
+                self.apply_records(glyphs, positions, (table, records_pos + 2, records), depth)
+            }
+            _ => None,
+        }
+    }
+
+    fn reverse_chain(
+        &self,
+        lookup: &Lookup,
+        table: &[u8],
+        glyphs: &mut Vec<GlyphInfo>,
+        i: usize,
+    ) -> Option<usize> {
+        let index = coverage(at(table, 2)?, glyphs[i].id)?;
+        let covered = |pos: usize, n: usize, id: u16| {
+            at(table, pos + n * 2).map_or(false, |c| coverage(c, id).is_some())
+        };
+        let backtrack = sfnt::read_u16(table, 4)? as usize;
+        let lookahead_pos = 6 + backtrack * 2;
+        let lookahead = sfnt::read_u16(table, lookahead_pos)? as usize;
+        let substitutes_pos = lookahead_pos + 2 + lookahead * 2;
+        if !self.match_backtrack(lookup, glyphs, i, backtrack, |n, id| covered(6, n, id))
+            || !self.match_lookahead(lookup, glyphs, i, lookahead, |n, id| {
+                covered(lookahead_pos + 2, n, id)
+            })
+        {
+            return None;
+        }
+        glyphs[i].id = sfnt::read_u16(table, substitutes_pos + 2 + index * 2)?;
+        Some(i + 1)
+    }
+
+    /// Applies the lookups a contextual rule gives for the glyphs it matched. records is the
+    /// table the rule is in, the position of its sequence lookup records and their count.
+    fn apply_records(
+        &self,
+        glyphs: &mut Vec<GlyphInfo>,
+        positions: Vec<usize>,
+        records: (&[u8], usize, usize),
+        depth: usize,
+    ) -> Option<usize> {
+        let (table, start, count) = records;
+        let mut positions = positions;
+        let mut end = *positions.last()? + 1;
+        if depth >= MAX_NESTING {
+            return Some(end);
+        }
+        for r in 0..count {
+            let sequence_index = sfnt::read_u16(table, start + r * 4)? as usize;
+            let lookup_index = sfnt::read_u16(table, start + r * 4 + 2)? as usize;
+            let (pos, lookup) = match (
+                positions.get(sequence_index),
+                self.lookups.get(lookup_index),
+            ) {
+                (Some(&pos), Some(lookup)) if pos < glyphs.len() => (pos, lookup),
+                _ => continue,
+            };
+            let before = glyphs.len();
+            self.apply_at(lookup, glyphs, pos, depth + 1);
+            // glyphs after a multiple or ligature substitution moved
+            let moved = glyphs.len() as isize - before as isize;
+            if moved != 0 {
+                for p in &mut positions[sequence_index + 1..] {
+                    *p = (*p as isize + moved).max(pos as isize + 1) as usize;
+                }
+                end = (end as isize + moved).max(pos as isize + 1) as usize;
+            }
+        }
+        Some(end.min(glyphs.len()))
+    }
+}
+
+fn read_lookup(table: Option<&[u8]>) -> Option<Lookup> {
+    let table = table?;
+    let mut kind = sfnt::read_u16(table, 0)?;
+    let flag = sfnt::read_u16(table, 2)?;
+    let count = sfnt::read_u16(table, 4)? as usize;
+    let mut subtables = Vec::with_capacity(count);
+    for i in 0..count {
+        let subtable = match at(table, 6 + i * 2) {
+            Some(subtable) => subtable,
+            None => continue,
+        };
+        if kind == 7 {
+            // an extension points to a subtable of another type with a 32 bit offset
+            let offset = sfnt::read_u32(subtable, 4)? as usize;
+            subtables.push(subtable.get(offset..)?);
+            if i == 0 {
+                kind = sfnt::read_u16(subtable, 2)?;
+            }
+        } else {
+            subtables.push(subtable);
+        }
+    }
+    Some(Lookup {
+        kind: kind,
+        flag: flag,
+        subtables: subtables,
+        mark_set: if flag & 0x10 != 0 {
+            sfnt::read_u16(table, 6 + count * 2)
+        } else {
+            None
+        },
+    })
+}
+
+/// Returns if a glyph matches a value in a rule, which is a class if there is a class definition
+/// and otherwise a glyph
+fn rule_matches(classes: Option<&[u8]>, value: Option<u16>, glyph: u16) -> bool {
+    match classes {
+        Some(classes) => value == Some(class(classes, glyph)),
+        None => value == Some(glyph),
+    }
+}
+
+/// Returns if a glyph is in a mark glyph set of GDEF
+fn in_mark_set(gdef: &[u8], set: u16, glyph: u16) -> bool {
+    let covered = || -> Option<bool> {
+        let sets = at(gdef, 12)?;
+        let offset = sfnt::read_u32(sets, 4 + set as usize * 4)? as usize;
+        Some(coverage(sets.get(offset..)?, glyph).is_some())
+    };
+    covered().unwrap_or(false)
+}
+
+fn single(table: &[u8], glyphs: &mut Vec<GlyphInfo>, i: usize) -> Option<usize> {
+    let id = glyphs[i].id;
+    let index = coverage(at(table, 2)?, id)?;
+    glyphs[i].id = match sfnt::read_u16(table, 0)? {
+        1 => (id as i32 + sfnt::read_i16(table, 4)? as i32) as u16,
+        2 => sfnt::read_u16(table, 6 + index * 2)?,
+        _ => return None,
+    };
+    Some(i + 1)
+}
+
+fn multiple(table: &[u8], glyphs: &mut Vec<GlyphInfo>, i: usize) -> Option<usize> {
+    let index = coverage(at(table, 2)?, glyphs[i].id)?;
+    let sequence = at(table, 6 + index * 2)?;
+    let count = sfnt::read_u16(sequence, 0)? as usize;
+    let ids = (0..count)
+        .map(|k| sfnt::read_u16(sequence, 2 + k * 2))
+        .collect::<Option<Vec<u16>>>()?;
+    let range = glyphs[i].range.clone();
+    if ids.is_empty() {
+        // the glyph is deleted, and the glyph before it, or else after it, stands for its text
+        glyphs.remove(i);
+        let neighbour = if i > 0 {
+            glyphs.get_mut(i - 1)
+        } else {
+            glyphs.get_mut(i)
+        };
+        if let Some(g) = neighbour {
+            g.range = g.range.start.min(range.start)..g.range.end.max(range.end);
+        }
+        return Some(i);
+    }
+    let replacement: Vec<GlyphInfo> = ids
+        .into_iter()
+        .map(|id| GlyphInfo {
+            id: id,
+            range: range.clone(),
+        })
+        .collect();
+    glyphs.splice(i..i + 1, replacement);
+    Some(i + count)
+}
+
+/// Picks the first alternate, as features that are simply turned on do
+fn alternate(table: &[u8], glyphs: &mut Vec<GlyphInfo>, i: usize) -> Option<usize> {
+    let index = coverage(at(table, 2)?, glyphs[i].id)?;
+    let set = at(table, 6 + index * 2)?;
+    if sfnt::read_u16(set, 0)? == 0 {
+        return None;
+    }
+    glyphs[i].id = sfnt::read_u16(set, 2)?;
+    Some(i + 1)
+}
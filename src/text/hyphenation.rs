@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+/// Finds where words can be hyphenated with Liang's algorithm, using TeX hyphenation patterns
+pub struct Hyphenator {
+    /// Language the patterns are for, which text styles pick the hyphenator by
+    pub language: String,
+    /// Fewest letters left before a hyphen
+    pub left_min: usize,
+    /// Fewest letters carried over after a hyphen
+    pub right_min: usize,
+    /// Letters of each pattern, and the values before, between and after them
+    patterns: HashMap<String, Vec<u8>>,
+    /// Words with hyphenation that doesn't follow the patterns, and the letters they can be
+    /// hyphenated before
+    exceptions: HashMap<String, Vec<usize>>,
+    longest_pattern: usize,
+}
+
+impl Hyphenator {
+    /// Reads TeX patterns such as those of the hyph-utf8 project, like `.ach4 a1b`. Exceptions
+    /// are words with their hyphenation marked by hyphens, like `ta-ble`. Both are whitespace
+    /// separated, and TeX comments and commands are skipped.
+    pub fn new(language: &str, patterns: &str, exceptions: &str) -> Hyphenator {
+        let mut table = HashMap::new();
+        let mut longest_pattern = 0;
+        for pattern in words(patterns) {
+            let mut letters = String::new();
+            let mut values = vec![0];
+            for c in pattern.chars() {
+                match c.to_digit(10) {
+                    Some(d) => *values.last_mut().unwrap() = d as u8,
+                    None => {
+                        letters.push(c);
+                        values.push(0);
+                    }
+                }
+            }
+            longest_pattern = longest_pattern.max(values.len() - 1);
+            table.insert(letters, values);
+        }
+        let mut exception_table = HashMap::new();
+        for word in words(exceptions) {
+            let mut letters = String::new();
+            let mut points = Vec::new();
+            for c in word.chars() {
+                if c == '-' {
+                    points.push(letters.chars().count());
+                } else {
+                    letters.push(c);
+                }
+            }
+            exception_table.insert(letters, points);
+        }
+        Hyphenator {
+            language: language.to_string(),
+            left_min: 2,
+            right_min: 3,
+            patterns: table,
+            exceptions: exception_table,
+            longest_pattern: longest_pattern,
+        }
+    }
+
+    /// Returns the byte indices in a word where it can be hyphenated
+    pub fn hyphenate(&self, word: &str) -> Vec<usize> {
+        let chars: Vec<char> = word
+            .chars()
+            .map(|c| c.to_lowercase().next().unwrap_or(c))
+            .collect();
+        let n = chars.len();
+        if n < self.left_min + self.right_min {
+            return Vec::new();
+        }
+        let lower: String = chars.iter().collect();
+        let points: Vec<usize> = match self.exceptions.get(&lower) {
+            Some(points) => points.clone(),
+            None => {
+                let mut dotted = vec!['.'];
+                dotted.extend(chars.iter().cloned());
+                dotted.push('.');
+                // values[k] is the highest value of any pattern between dotted[k - 1] and
+                // dotted[k]. Odd values allow a hyphen.
+                let mut values = vec![0; dotted.len() + 1];
+                for start in 0..dotted.len() {
+                    let longest = (dotted.len() - start).min(self.longest_pattern);
+                    for len in 1..longest + 1 {
+                        let key: String = dotted[start..start + len].iter().collect();
+                        if let Some(pattern) = self.patterns.get(&key) {
+                            for (k, &v) in pattern.iter().enumerate() {
+                                values[start + k] = values[start + k].max(v);
+                            }
+                        }
+                    }
+                }
+                (1..n).filter(|&i| values[i + 1] % 2 == 1).collect()
+            }
+        };
+        let indices: Vec<usize> = word.char_indices().map(|(i, _)| i).collect();
+        points
+            .into_iter()
+            .filter(|&i| i >= self.left_min && i + self.right_min <= n)
+            .map(|i| indices[i])
+            .collect()
+    }
+}
+
+/// Splits a TeX pattern or exception list into words
+fn words<'a>(text: &'a str) -> Vec<&'a str> {
+    text.lines()
+        .map(|line| line.split('%').next().unwrap_or(""))
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == '{' || c == '}'))
+        .filter(|word| !word.is_empty() && !word.starts_with('\\'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Patterns from Liang's thesis, enough to hyphenate "hyphenation"
+    const PATTERNS: &str = "hy3ph he2n hena4 hen5at 1na n2at 1tio 2io o2n % a comment";
+
+    fn hyphenator() -> Hyphenator {
+        Hyphenator::new("en", PATTERNS, "hyphena-tion ta-ble \\exceptions")
+    }
+
+    /// The word with a hyphen at each point
+    fn hyphenated(hyphenator: &Hyphenator, word: &str) -> String {
+        let mut result = word.to_string();
+        for &i in hyphenator.hyphenate(word).iter().rev() {
+            result.insert(i, '-');
+        }
+        result
+    }
+
+    #[test]
+    fn patterns() {
+        let h = Hyphenator::new("en", PATTERNS, "");
+        assert_eq!(hyphenated(&h, "hyphenation"), "hy-phen-ation");
+        assert_eq!(hyphenated(&h, "Hyphenation"), "Hy-phen-ation");
+    }
+
+    #[test]
+    fn left_and_right_min() {
+        let mut h = Hyphenator::new("en", PATTERNS, "");
+        h.left_min = 3;
+        assert_eq!(hyphenated(&h, "hyphenation"), "hyphen-ation");
+        h.left_min = 2;
+        h.right_min = 6;
+        assert_eq!(hyphenated(&h, "hyphenation"), "hy-phenation");
+        assert!(h.hyphenate("hyphen").is_empty());
+    }
+
+    #[test]
+    fn exceptions() {
+        let mut h = hyphenator();
+        // the exception is used instead of the patterns
+        assert_eq!(hyphenated(&h, "Hyphenation"), "Hyphena-tion");
+        assert_eq!(hyphenated(&h, "table"), "ta-ble");
+        h.right_min = 4;
+        assert_eq!(hyphenated(&h, "table"), "table");
+        h.right_min = 5;
+        assert_eq!(hyphenated(&h, "hyphenation"), "hyphenation");
+    }
+
+    #[test]
+    fn multibyte_letters() {
+        let h = Hyphenator::new("de", "1ß", "");
+        assert_eq!(hyphenated(&h, "straßen"), "stra-ßen");
+    }
+}
@@ -0,0 +1,686 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use rusttype::{point, Point, PositionedGlyph, Rect, Scale};
+
+use text::color::Color;
+use text::layout::{Layout, LayoutGlyph, WritingMode};
+use text::transform::GlyphTransform;
+use text::DEFAULT_GAMMA;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelFormat {
+    /// Red, green, blue and alpha bytes, with alpha that isn't premultiplied
+    Rgba,
+    /// One byte of coverage per pixel, as a mask that ignores the colors of the text
+    Gray,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match *self {
+            PixelFormat::Rgba => 4,
+            PixelFormat::Gray => 1,
+        }
+    }
+}
+
+/// An image in memory, with rows from top to bottom and no padding between them
+#[derive(Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// A transparent image, or a black one for grayscale
+    pub fn new(width: u32, height: u32, format: PixelFormat) -> Image {
+        Image {
+            width: width,
+            height: height,
+            format: format,
+            data: vec![0; width as usize * height as usize * format.bytes_per_pixel()],
+        }
+    }
+
+    /// An RGBA image filled with a color
+    pub fn filled(width: u32, height: u32, color: Color) -> Image {
+        let mut image = Image::new(width, height, PixelFormat::Rgba);
+        let pixel = [
+            to_byte(color[0]),
+            to_byte(color[1]),
+            to_byte(color[2]),
+            to_byte(color[3]),
+        ];
+        for chunk in image.data.chunks_mut(4) {
+            chunk.copy_from_slice(&pixel);
+        }
+        image
+    }
+
+    /// The bytes of the pixel at a column and row
+    pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        let size = self.format.bytes_per_pixel();
+        let start = (y as usize * self.width as usize + x as usize) * size;
+        &self.data[start..start + size]
+    }
+
+    /// Writes the image as a binary PGM for grayscale or a PAM for RGBA, which are simple enough
+    /// to compare in tests and that image tools can convert
+    pub fn write_pnm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self.format {
+            PixelFormat::Gray => write!(out, "P5\n{} {}\n255\n", self.width, self.height)?,
+            PixelFormat::Rgba => write!(
+                out,
+                "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+                self.width, self.height
+            )?,
+        }
+        out.write_all(&self.data)
+    }
+
+    /// Draws a color over a pixel with some coverage from 0 to 1
+    fn blend(&mut self, x: usize, y: usize, color: Color, coverage: f32) {
+        let alpha = color[3] * coverage;
+        if alpha <= 0.0 {
+            return;
+        }
+        let size = self.format.bytes_per_pixel();
+        let i = (y * self.width as usize + x) * size;
+        match self.format {
+            PixelFormat::Gray => {
+                let dst = self.data[i] as f32 / 255.0;
+                self.data[i] = to_byte(alpha + dst * (1.0 - alpha));
+            }
+            PixelFormat::Rgba => {
+                let dst_alpha = self.data[i + 3] as f32 / 255.0;
+                let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+                for c in 0..3 {
+                    let dst = self.data[i + c] as f32 / 255.0;
+                    let value = (color[c] * alpha + dst * dst_alpha * (1.0 - alpha)) / out_alpha;
+                    self.data[i + c] = to_byte(value);
+                }
+                self.data[i + 3] = to_byte(out_alpha);
+            }
+        }
+    }
+}
+
+fn to_byte(value: f32) -> u8 {
+    (value.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+/// Draws layouts into images on the CPU, with no GL context, as for thumbnails, notifications and
+/// reference images in tests. Glyphs are rasterized and placed the same way GlGlyphRenderer does
+/// with grayscale antialiasing. Subpixel antialiasing isn't done, since an image doesn't know the
+/// screen it will be shown on.
+#[derive(Clone, Copy, PartialEq)]
+pub struct ImageRenderer {
+    /// Physical pixels per layout pixel
+    pub scale_factor: f32,
+    /// If baselines are moved to the nearest physical pixel, which keeps horizontal stems sharp
+    pub snap_baselines: bool,
+    /// See GlGlyphRenderer::set_gamma
+    pub gamma: f32,
+    pub contrast: f32,
+}
+
+impl ImageRenderer {
+    pub fn new(scale_factor: f32) -> ImageRenderer {
+        ImageRenderer {
+            scale_factor: scale_factor,
+            snap_baselines: false,
+            gamma: DEFAULT_GAMMA,
+            contrast: 0.0,
+        }
+    }
+
+    /// Renders a layout into a new image that is just big enough for its lines and the ink of
+    /// its glyphs. The layout is moved so that ink that reaches left of or above its origin
+    /// isn't cut off.
+    pub fn render(&self, layout: &Layout, format: PixelFormat) -> Image {
+        let scale = self.scale_factor;
+        let mut points = Vec::new();
+        for line in &layout.lines {
+            let length = layout.clusters[line.clusters.clone()]
+                .iter()
+                .map(|c| c.pos + c.advance)
+                .fold(0.0, f32::max);
+            let (across, along) = (line.extent.clone(), 0.0..length);
+            let (x, y) = match layout.writing_mode {
+                WritingMode::Horizontal => (along, across),
+                WritingMode::VerticalRl => (across, along),
+            };
+            points.push(point(x.start, y.start));
+            points.push(point(x.end, y.end));
+        }
+        for b in &layout.boxes {
+            points.push(b.rect.min);
+            points.push(b.rect.max);
+        }
+        for g in &layout.glyphs {
+            if let Some((w, h, to_layout)) = self.glyph_rect(g) {
+                let map = to_layout.then(&placement(g, None, point(0.0, 0.0)));
+                for &(x, y) in &[
+                    (0.0, 0.0),
+                    (w as f32, 0.0),
+                    (0.0, h as f32),
+                    (w as f32, h as f32),
+                ] {
+                    points.push(map.apply(x, y));
+                }
+            }
+        }
+        if points.is_empty() {
+            return Image::new(0, 0, format);
+        }
+        let (mut min, mut max) = (points[0], points[0]);
+        for p in &points {
+            min = point(min.x.min(p.x), min.y.min(p.y));
+            max = point(max.x.max(p.x), max.y.max(p.y));
+        }
+        // whole image pixels, so baselines snapped to pixels stay snapped
+        let (left, top) = ((min.x * scale).floor(), (min.y * scale).floor());
+        let (right, bottom) = ((max.x * scale).ceil(), (max.y * scale).ceil());
+        let mut image = Image::new((right - left) as u32, (bottom - top) as u32, format);
+        let origin = point(-left / scale, -top / scale);
+        self.draw(&mut image, layout, &[], origin);
+        image
+    }
+
+    /// Draws a layout over an image with its origin at a point in layout pixels. Glyphs are
+    /// transformed as by GlGlyphRenderer::set_glyph_transforms, unless transforms is empty.
+    /// Links are drawn in their normal style.
+    pub fn draw(
+        &self,
+        image: &mut Image,
+        layout: &Layout,
+        transforms: &[GlyphTransform],
+        origin: Point<f32>,
+    ) {
+        let scale = self.scale_factor;
+        let to_image = Affine::scale(scale, scale)
+            .then(&Affine::translate(origin.x * scale, origin.y * scale));
+        let centers = layout.cluster_centers();
+        for g in &layout.glyphs {
+            let start = layout.clusters[g.cluster].range.start;
+            let link_color = layout
+                .links
+                .iter()
+                .find(|&&(ref range, _)| start >= range.start && start < range.end)
+                .and_then(|&(_, ref link)| link.style.color);
+            let transform = transforms.get(g.cluster).cloned();
+            let mut color = match transform.and_then(|t| t.color) {
+                Some(color) => color,
+                None => link_color.unwrap_or(g.color),
+            };
+            if let Some(transform) = transform {
+                color[3] *= transform.opacity;
+            }
+
+            let (w, h, to_layout) = match self.glyph_rect(g) {
+                Some(rect) => rect,
+                None => continue,
+            };
+            // pixels of the glyph, premultiplied
+            let texels: Vec<[f32; 4]> = match g.bitmap {
+                Some(ref bitmap) => {
+                    // bitmaps have their own colors, and only take the opacity of the text
+                    color = [1.0, 1.0, 1.0, color[3]];
+                    bitmap
+                        .pixels
+                        .chunks(4)
+                        .map(|p| {
+                            let alpha = p[3] as f32 / 255.0;
+                            let channel = |i: usize| p[i] as f32 / 255.0 * alpha;
+                            [channel(0), channel(1), channel(2), alpha]
+                        })
+                        .collect()
+                }
+                None => {
+                    let mut coverage = vec![0.0; w * h];
+                    self.scaled(g)
+                        .draw(|x, y, v| coverage[y as usize * w + x as usize] = v);
+                    self.adjust(&coverage, color)
+                        .into_iter()
+                        .map(|c| [color[0] * c, color[1] * c, color[2] * c, c])
+                        .collect()
+                }
+            };
+
+            // from the bitmap to layout pixels, and then to where the glyph is placed like a quad
+            // of the GL renderer
+            let placement = placement(g, transform, centers[g.cluster]);
+            let map = to_layout.then(&placement).then(&to_image);
+            draw_bitmap(image, &texels, w, h, &map, color[3]);
+        }
+
+        for &(ref range, ref link) in &layout.links {
+            if !link.style.underline {
+                continue;
+            }
+            let text_color = layout
+                .glyphs
+                .iter()
+                .find(|g| layout.clusters[g.cluster].range.start >= range.start)
+                .map_or([1.0; 4], |g| g.color);
+            let color = link.style.color.unwrap_or(text_color);
+            for rect in layout.underline_rects(range.clone()) {
+                fill_rect(image, &rect, &to_image, color);
+            }
+        }
+    }
+
+    /// A glyph's outline scaled to image pixels, with its baseline snapped if that is on
+    fn scaled<'font>(&self, g: &LayoutGlyph<'font>) -> PositionedGlyph<'font> {
+        let scale = self.scale_factor;
+        let position = g.glyph.position();
+        let mut y = position.y * scale;
+        if self.snap_baselines {
+            y = y.round();
+        }
+        let glyph_scale = g.glyph.scale();
+        g.glyph
+            .clone()
+            .into_unpositioned()
+            .into_unscaled()
+            .scaled(Scale {
+                x: glyph_scale.x * scale,
+                y: glyph_scale.y * scale,
+            })
+            .positioned(point(position.x * scale, y))
+    }
+
+    /// Size in pixels of the bitmap a glyph is drawn from, and the map from it to layout pixels
+    /// before the glyph is rotated or transformed. Returns None if the glyph has no pixels.
+    fn glyph_rect(&self, g: &LayoutGlyph) -> Option<(usize, usize, Affine)> {
+        match g.bitmap {
+            Some(ref bitmap) => {
+                let (position, size) = (g.glyph.position(), g.glyph.scale().y);
+                let (w, h) = (bitmap.width as usize, bitmap.height as usize);
+                let mut y = position.y;
+                if self.snap_baselines {
+                    y = (y * self.scale_factor).round() / self.scale_factor;
+                }
+                let to_layout = Affine::scale(
+                    bitmap.rect.width() * size / w as f32,
+                    bitmap.rect.height() * size / h as f32,
+                )
+                .then(&Affine::translate(
+                    position.x + bitmap.rect.min.x * size,
+                    y + bitmap.rect.min.y * size,
+                ));
+                Some((w, h, to_layout))
+            }
+            None => {
+                let bounds = self.scaled(g).pixel_bounding_box()?;
+                let (w, h) = (bounds.width() as usize, bounds.height() as usize);
+                let scale = self.scale_factor;
+                let to_layout = Affine::translate(bounds.min.x as f32, bounds.min.y as f32)
+                    .then(&Affine::scale(1.0 / scale, 1.0 / scale));
+                Some((w, h, to_layout))
+            }
+        }
+    }
+
+    /// Adjusts coverage for the brightness of the text, as the fragment shader does
+    fn adjust(&self, coverage: &[f32], color: Color) -> Vec<f32> {
+        let luminance = 0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2];
+        let power = self.gamma + (1.0 / self.gamma - self.gamma) * luminance;
+        coverage
+            .iter()
+            .map(|&c| {
+                let c = (c + self.contrast * c * (1.0 - c)).max(0.0).min(1.0);
+                c.powf(power)
+            })
+            .collect()
+    }
+}
+
+/// Where a glyph is placed in layout pixels: turned if it is sideways in vertical text, and then
+/// transformed about the center of its cluster
+fn placement(g: &LayoutGlyph, transform: Option<GlyphTransform>, center: Point<f32>) -> Affine {
+    let mut placement = Affine::translate(0.0, 0.0);
+    if let Some(pivot) = g.rotate_about {
+        // turn 90 degrees clockwise, y is down so this maps (x, y) to (-y, x)
+        placement = placement.then(&Affine {
+            a: 0.0,
+            b: -1.0,
+            c: 1.0,
+            d: 0.0,
+            e: pivot.x + pivot.y,
+            f: pivot.y - pivot.x,
+        });
+    }
+    if let Some(transform) = transform {
+        let (sin, cos) = transform.rotation.sin_cos();
+        let (s, o) = (transform.scale, transform.offset);
+        placement = placement
+            .then(&Affine::translate(-center.x, -center.y))
+            .then(&Affine {
+                a: cos * s,
+                b: -sin * s,
+                c: sin * s,
+                d: cos * s,
+                e: o.x,
+                f: o.y,
+            })
+            .then(&Affine::translate(center.x, center.y));
+    }
+    placement
+}
+
+/// Draws a bitmap of premultiplied colors that a map places on the image, sampling it
+/// bilinearly. Grayscale images only take its alpha.
+fn draw_bitmap(
+    image: &mut Image,
+    texels: &[[f32; 4]],
+    w: usize,
+    h: usize,
+    map: &Affine,
+    opacity: f32,
+) {
+    let inverse = match map.invert() {
+        Some(inverse) => inverse,
+        None => return,
+    };
+    let corners = [
+        map.apply(0.0, 0.0),
+        map.apply(w as f32, 0.0),
+        map.apply(0.0, h as f32),
+        map.apply(w as f32, h as f32),
+    ];
+    let (x_range, y_range) = match pixel_range(image, &corners) {
+        Some(ranges) => ranges,
+        None => return,
+    };
+    let sample = |x: isize, y: isize| {
+        if x < 0 || y < 0 || x >= w as isize || y >= h as isize {
+            [0.0; 4]
+        } else {
+            texels[y as usize * w + x as usize]
+        }
+    };
+    for y in y_range {
+        for x in x_range.clone() {
+            let p = inverse.apply(x as f32 + 0.5, y as f32 + 0.5);
+            // texel centers are at half pixels
+            let (u, v) = (p.x - 0.5, p.y - 0.5);
+            let (x0, y0) = (u.floor(), v.floor());
+            let (fx, fy) = (u - x0, v - y0);
+            let (x0, y0) = (x0 as isize, y0 as isize);
+            let mut value = [0.0; 4];
+            for &(dx, dy, weight) in &[
+                (0, 0, (1.0 - fx) * (1.0 - fy)),
+                (1, 0, fx * (1.0 - fy)),
+                (0, 1, (1.0 - fx) * fy),
+                (1, 1, fx * fy),
+            ] {
+                let texel = sample(x0 + dx, y0 + dy);
+                for c in 0..4 {
+                    value[c] += texel[c] * weight;
+                }
+            }
+            let alpha = value[3];
+            if alpha > 0.0 {
+                let color = [
+                    value[0] / alpha,
+                    value[1] / alpha,
+                    value[2] / alpha,
+                    opacity,
+                ];
+                image.blend(x, y, color, alpha);
+            }
+        }
+    }
+}
+
+/// Fills a rect in layout pixels, with edges that cover part of a pixel blended by how much they
+/// cover it
+fn fill_rect(image: &mut Image, rect: &Rect<f32>, map: &Affine, color: Color) {
+    let min = map.apply(rect.min.x, rect.min.y);
+    let max = map.apply(rect.max.x, rect.max.y);
+    let (x_range, y_range) = match pixel_range(image, &[min, max]) {
+        Some(ranges) => ranges,
+        None => return,
+    };
+    let overlap = |lo: f32, hi: f32, pixel: usize| {
+        let (start, end) = (pixel as f32, pixel as f32 + 1.0);
+        (hi.min(end) - lo.max(start)).max(0.0)
+    };
+    for y in y_range {
+        let cover_y = overlap(min.y, max.y, y);
+        for x in x_range.clone() {
+            image.blend(x, y, color, cover_y * overlap(min.x, max.x, x));
+        }
+    }
+}
+
+/// Columns and rows of the image that points span, or None if they are all off the image
+fn pixel_range(image: &Image, points: &[Point<f32>]) -> Option<(Range<usize>, Range<usize>)> {
+    let min_x = points
+        .iter()
+        .map(|p| p.x)
+        .fold(::std::f32::INFINITY, f32::min);
+    let max_x = points
+        .iter()
+        .map(|p| p.x)
+        .fold(::std::f32::NEG_INFINITY, f32::max);
+    let min_y = points
+        .iter()
+        .map(|p| p.y)
+        .fold(::std::f32::INFINITY, f32::min);
+    let max_y = points
+        .iter()
+        .map(|p| p.y)
+        .fold(::std::f32::NEG_INFINITY, f32::max);
+    // a pixel past each side, for the bilinear samples at the edges
+    let x0 = (min_x.floor() - 1.0).max(0.0) as usize;
+    let y0 = (min_y.floor() - 1.0).max(0.0) as usize;
+    let x1 = ((max_x.ceil() + 1.0).max(0.0) as usize).min(image.width as usize);
+    let y1 = ((max_y.ceil() + 1.0).max(0.0) as usize).min(image.height as usize);
+    if x0 >= x1 || y0 >= y1 {
+        None
+    } else {
+        Some((x0..x1, y0..y1))
+    }
+}
+
+/// A 2D affine map, from (x, y) to (ax + by + e, cx + dy + f)
+#[derive(Clone, Copy)]
+struct Affine {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+    f: f32,
+}
+
+impl Affine {
+    fn translate(x: f32, y: f32) -> Affine {
+        Affine {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            e: x,
+            f: y,
+        }
+    }
+
+    fn scale(x: f32, y: f32) -> Affine {
+        Affine {
+            a: x,
+            b: 0.0,
+            c: 0.0,
+            d: y,
+            e: 0.0,
+            f: 0.0,
+        }
+    }
+
+    /// This map followed by another
+    fn then(&self, next: &Affine) -> Affine {
+        Affine {
+            a: next.a * self.a + next.b * self.c,
+            b: next.a * self.b + next.b * self.d,
+            c: next.c * self.a + next.d * self.c,
+            d: next.c * self.b + next.d * self.d,
+            e: next.a * self.e + next.b * self.f + next.e,
+            f: next.c * self.e + next.d * self.f + next.f,
+        }
+    }
+
+    fn invert(&self) -> Option<Affine> {
+        let det = self.a * self.d - self.b * self.c;
+        if det.abs() < 1e-6 {
+            return None;
+        }
+        let (a, b, c, d) = (self.d / det, -self.b / det, -self.c / det, self.a / det);
+        Some(Affine {
+            a: a,
+            b: b,
+            c: c,
+            d: d,
+            e: -(a * self.e + b * self.f),
+            f: -(c * self.e + d * self.f),
+        })
+    }
+
+    fn apply(&self, x: f32, y: f32) -> Point<f32> {
+        point(
+            self.a * x + self.b * y + self.e,
+            self.c * x + self.d * y + self.f,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use text::layout::{layout_paragraph, layout_rich};
+    use text::{FontSet, LayoutOptions, RichText, TextStyle, WritingMode};
+
+    use super::*;
+
+    const MONO: &[u8] = include_bytes!("../../fonts/dejavu/DejaVuSansMono.ttf");
+    const ITALIC: &[u8] = include_bytes!("../../fonts/opensans/OpenSans-Italic.ttf");
+
+    /// Most a byte may differ from the reference, since rasterizer versions antialias edges a
+    /// little differently
+    const TOLERANCE: u8 = 8;
+
+    fn fonts() -> FontSet<'static> {
+        let mut fonts = FontSet::new();
+        fonts.load("mono", MONO).unwrap();
+        fonts.load("italic", ITALIC).unwrap();
+        fonts
+    }
+
+    /// Compares an image with its reference in tests/reference, or writes the reference when
+    /// UPDATE_REFERENCES is set
+    fn check(image: &Image, name: &str) {
+        let path = format!("{}/tests/reference/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let mut actual = Vec::new();
+        image.write_pnm(&mut actual).unwrap();
+        if env::var_os("UPDATE_REFERENCES").is_some() {
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected = fs::read(&path).unwrap();
+        let header = actual.len() - image.data.len();
+        assert_eq!(
+            &actual[..header],
+            expected.get(..header).unwrap_or(&[]),
+            "{} has a different size",
+            name
+        );
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{} has a different size",
+            name
+        );
+        let differences = actual[header..]
+            .iter()
+            .zip(&expected[header..])
+            .filter(|&(&a, &e)| (a as i32 - e as i32).abs() > TOLERANCE as i32)
+            .count();
+        assert_eq!(differences, 0, "{} differs from its reference", name);
+    }
+
+    #[test]
+    fn gray_lines() {
+        let mut fonts = fonts();
+        let style = TextStyle::new(16.0).with_family("mono");
+        let layout = layout_paragraph(
+            &mut fonts,
+            "Reference\nimage",
+            &style,
+            &LayoutOptions::new(200.0),
+        );
+        let image = ImageRenderer::new(1.0).render(&layout, PixelFormat::Gray);
+        check(&image, "gray_lines.pgm");
+    }
+
+    #[test]
+    fn rgba_spans() {
+        let mut fonts = fonts();
+        let mut text = RichText::new();
+        let red = TextStyle::new(24.0)
+            .with_family("italic")
+            .with_color([0.8, 0.1, 0.1, 1.0]);
+        let blue = TextStyle::new(24.0)
+            .with_family("mono")
+            .with_color([0.1, 0.2, 0.9, 0.5]);
+        text.push("fjord ", &red);
+        text.push("blue", &blue);
+        let layout = layout_rich(&mut fonts, &text, &LayoutOptions::new(300.0));
+        let image = ImageRenderer::new(2.0).render(&layout, PixelFormat::Rgba);
+        check(&image, "rgba_spans.pam");
+    }
+
+    #[test]
+    fn ink_overhang_is_kept() {
+        let mut fonts = fonts();
+        let style = TextStyle::new(40.0).with_family("italic");
+        let layout = layout_paragraph(&mut fonts, "fj", &style, &LayoutOptions::new(300.0));
+        let image = ImageRenderer::new(1.0).render(&layout, PixelFormat::Gray);
+        let advance = layout
+            .clusters
+            .iter()
+            .map(|c| c.pos + c.advance)
+            .fold(0.0, f32::max);
+        // the j descends left of the origin and the f reaches past its advance
+        assert!(image.width as f32 > advance);
+        let column = |x: u32| (0..image.height).any(|y| image.pixel(x, y)[0] > 0);
+        assert!(column(0) && column(image.width - 1));
+    }
+
+    #[test]
+    fn vertical_columns_are_not_transposed() {
+        let mut fonts = fonts();
+        let style = TextStyle::new(16.0).with_family("mono");
+        let mut options = LayoutOptions::new(100.0);
+        options.writing_mode = WritingMode::VerticalRl;
+        let layout = layout_paragraph(&mut fonts, "vertical\ntext", &style, &options);
+        let image = ImageRenderer::new(1.0).render(&layout, PixelFormat::Gray);
+        // two columns that are each a word long, and the first one is on the right
+        assert!(image.height > image.width);
+        let ink = |x: u32| {
+            (0..image.height)
+                .filter(|&y| image.pixel(x, y)[0] > 0)
+                .count()
+        };
+        let half = image.width / 2;
+        let right: usize = (half..image.width).map(&ink).sum();
+        let left: usize = (0..half).map(&ink).sum();
+        assert!(right > left);
+        check(&image, "vertical_columns.pgm");
+    }
+}
@@ -0,0 +1,913 @@
+//! Static instances of variable fonts, with synthetic bold and oblique. rusttype only draws the
+//! outlines in the glyf table, so an instance is a new font whose glyf table has the gvar deltas
+//! applied to every glyph, and then the outlines emboldened and sheared. Advances come from the
+//! phantom points, which gvar varies as well, so HVAR isn't read. Hinting is dropped since
+//! rusttype doesn't hint. Fonts with CFF outlines can't be instanced.
+
+use text::font::VariationAxis;
+use text::sfnt;
+use text::style::Tag;
+
+/// Tables that are left out of an instance, because they describe the variations or hinting of
+/// the outlines that were replaced
+const DROPPED: &[&Tag] = &[
+    b"avar", b"cvar", b"fvar", b"gvar", b"HVAR", b"MVAR", b"STAT", b"VVAR", b"cvt ", b"fpgm",
+    b"prep", b"hdmx", b"LTSH", b"VDMX", b"DSIG",
+];
+
+/// Composite glyphs nested deeper than this are left empty, so fonts with loops can't recurse
+/// forever
+const MAX_DEPTH: usize = 8;
+
+/// A point of an outline in font units
+#[derive(Clone, Copy)]
+struct OutlinePoint {
+    x: f32,
+    y: f32,
+    on_curve: bool,
+}
+
+/// A glyph with composites flattened into the contours of their components
+struct Outline {
+    contours: Vec<Vec<OutlinePoint>>,
+    /// x of the glyph origin and of the end of its advance, which are the first two phantom
+    /// points of gvar
+    origin: f32,
+    advance: f32,
+}
+
+/// A component of a composite glyph
+struct Component {
+    glyph: usize,
+    /// Offset of the component, or the points to match up for components placed by point numbers
+    offset: (f32, f32),
+    matched: Option<(usize, usize)>,
+    /// 2x2 matrix as [xx, xy, yx, yy], where x' = xx * x + yx * y and y' = xy * x + yy * y
+    transform: [f32; 4],
+}
+
+/// The tables glyph outlines are read from
+struct Glyphs<'a> {
+    glyf: &'a [u8],
+    loca: &'a [u8],
+    long_loca: bool,
+    hmtx: &'a [u8],
+    h_metric_count: usize,
+    variations: Option<Variations<'a>>,
+}
+
+/// The glyph variations of a font and the normalized coordinates of an instance
+struct Variations<'a> {
+    gvar: &'a [u8],
+    coords: &'a [f32],
+}
+
+/// Converts axis values to the normalized coordinates gvar uses, which are -1 at the minimum of an
+/// axis, 0 at its default and 1 at its maximum, then remapped by avar if the font has it
+pub fn normalize(data: &[u8], axes: &[VariationAxis], values: &[f32]) -> Vec<f32> {
+    let avar = sfnt::find_table(data, *b"avar");
+    let mut segments = 8;
+    axes.iter()
+        .zip(values)
+        .map(|(axis, &value)| {
+            let v = if value < axis.default && axis.min < axis.default {
+                (value - axis.default) / (axis.default - axis.min)
+            } else if value > axis.default && axis.max > axis.default {
+                (value - axis.default) / (axis.max - axis.default)
+            } else {
+                0.0
+            };
+            let map = avar.and_then(|avar| {
+                let count = sfnt::read_u16(avar, segments)? as usize;
+                let pairs = (0..count)
+                    .map(|i| {
+                        let pair = segments + 2 + i * 4;
+                        Some((
+                            sfnt::read_f2dot14(avar, pair)?,
+                            sfnt::read_f2dot14(avar, pair + 2)?,
+                        ))
+                    })
+                    .collect::<Option<Vec<(f32, f32)>>>();
+                segments += 2 + count * 4;
+                pairs
+            });
+            match map {
+                Some(map) => remap(&map, v),
+                None => v,
+            }
+        })
+        .collect()
+}
+
+/// Maps a coordinate through the segments of an avar axis
+fn remap(map: &[(f32, f32)], v: f32) -> f32 {
+    for pair in map.windows(2) {
+        let ((from0, to0), (from1, to1)) = (pair[0], pair[1]);
+        if v >= from0 && v <= from1 {
+            if from1 == from0 {
+                return to0;
+            }
+            return to0 + (v - from0) / (from1 - from0) * (to1 - to0);
+        }
+    }
+    v
+}
+
+/// Builds the font file of an instance at normalized coordinates, with strokes made thicker by
+/// embolden font units and slanted by skew, or returns None if the font has no glyf table or
+/// can't be read
+pub fn build(data: &[u8], coords: &[f32], embolden: f32, skew: f32) -> Option<Vec<u8>> {
+    let tables = sfnt::tables(data)?;
+    let table = |tag: &Tag| tables.iter().find(|&&(t, _)| t == *tag).map(|&(_, d)| d);
+    let head = table(b"head")?;
+    let hhea = table(b"hhea")?;
+    let maxp = table(b"maxp")?;
+    let glyph_count = sfnt::read_u16(maxp, 4)? as usize;
+    let glyphs = Glyphs {
+        glyf: table(b"glyf")?,
+        loca: table(b"loca")?,
+        long_loca: sfnt::read_u16(head, 50)? != 0,
+        hmtx: table(b"hmtx")?,
+        h_metric_count: sfnt::read_u16(hhea, 34)? as usize,
+        variations: match table(b"gvar") {
+            Some(gvar) if coords.iter().any(|&c| c != 0.0) => Some(Variations {
+                gvar: gvar,
+                coords: coords,
+            }),
+            _ => None,
+        },
+    };
+    let mut glyf = Vec::new();
+    let mut loca = Vec::new();
+    let mut hmtx = Vec::new();
+    let mut bounds = [
+        i16::max_value(),
+        i16::max_value(),
+        i16::min_value(),
+        i16::min_value(),
+    ];
+    let (mut max_points, mut max_contours, mut max_advance) = (0, 0, 0);
+    for glyph in 0..glyph_count {
+        sfnt::write_u32(&mut loca, glyf.len() as u32);
+        // a glyph that can't be read is left empty rather than failing the whole font
+        let mut outline = glyphs.outline(glyph, 0).unwrap_or(Outline {
+            contours: Vec::new(),
+            origin: 0.0,
+            advance: 0.0,
+        });
+        if embolden != 0.0 {
+            outline.embolden(embolden);
+        }
+        if skew != 0.0 {
+            outline.shear(skew);
+        }
+        let advance = (outline.advance - outline.origin).round().max(0.0) as u16;
+        let lsb = match outline.write(&mut glyf) {
+            Some(b) => {
+                bounds = [
+                    bounds[0].min(b[0]),
+                    bounds[1].min(b[1]),
+                    bounds[2].max(b[2]),
+                    bounds[3].max(b[3]),
+                ];
+                b[0]
+            }
+            None => 0,
+        };
+        sfnt::write_u16(&mut hmtx, advance);
+        sfnt::write_u16(&mut hmtx, lsb as u16);
+        max_points = max_points.max(outline.contours.iter().map(|c| c.len()).sum());
+        max_contours = max_contours.max(outline.contours.len());
+        max_advance = max_advance.max(advance);
+    }
+    sfnt::write_u32(&mut loca, glyf.len() as u32);
+    let mut head = head.to_vec();
+    // checkSumAdjustment is filled in when the font is written
+    sfnt::set_u16(&mut head, 8, 0);
+    sfnt::set_u16(&mut head, 10, 0);
+    if bounds[0] <= bounds[2] {
+        for (i, &b) in bounds.iter().enumerate() {
+            sfnt::set_u16(&mut head, 36 + i * 2, b as u16);
+        }
+    }
+    sfnt::set_u16(&mut head, 50, 1);
+    let mut hhea = hhea.to_vec();
+    sfnt::set_u16(&mut hhea, 10, max_advance);
+    sfnt::set_u16(&mut hhea, 34, glyph_count as u16);
+    let mut maxp = maxp.to_vec();
+    if maxp.len() >= 32 {
+        sfnt::set_u16(&mut maxp, 6, max_points.min(0xFFFF) as u16);
+        sfnt::set_u16(&mut maxp, 8, max_contours.min(0xFFFF) as u16);
+        // there are no composites or instructions left
+        for &offset in &[10, 12, 26, 28, 30] {
+            sfnt::set_u16(&mut maxp, offset, 0);
+        }
+    }
+    let mut out: Vec<(Tag, Vec<u8>)> = tables
+        .iter()
+        .filter(|&&(tag, _)| {
+            !DROPPED.contains(&&tag)
+                && ![b"glyf", b"loca", b"hmtx", b"head", b"hhea", b"maxp"].contains(&&tag)
+        })
+        .map(|&(tag, data)| (tag, data.to_vec()))
+        .collect();
+    out.push((*b"glyf", glyf));
+    out.push((*b"loca", loca));
+    out.push((*b"hmtx", hmtx));
+    out.push((*b"head", head));
+    out.push((*b"hhea", hhea));
+    out.push((*b"maxp", maxp));
+    Some(sfnt::write_font(out))
+}
+
+impl<'a> Glyphs<'a> {
+    fn data(&self, glyph: usize) -> Option<&'a [u8]> {
+        let (start, end) = if self.long_loca {
+            (
+                sfnt::read_u32(self.loca, glyph * 4)? as usize,
+                sfnt::read_u32(self.loca, glyph * 4 + 4)? as usize,
+            )
+        } else {
+            (
+                sfnt::read_u16(self.loca, glyph * 2)? as usize * 2,
+                sfnt::read_u16(self.loca, glyph * 2 + 2)? as usize * 2,
+            )
+        };
+        if end <= start {
+            return Some(&[]);
+        }
+        self.glyf.get(start..end)
+    }
+
+    /// Advance width and left side bearing of a glyph
+    fn h_metrics(&self, glyph: usize) -> Option<(f32, f32)> {
+        let count = self.h_metric_count;
+        let advance = sfnt::read_u16(self.hmtx, glyph.min(count.checked_sub(1)?) * 4)?;
+        let lsb = if glyph < count {
+            sfnt::read_i16(self.hmtx, glyph * 4 + 2)?
+        } else {
+            sfnt::read_i16(self.hmtx, count * 4 + (glyph - count) * 2)?
+        };
+        Some((advance as f32, lsb as f32))
+    }
+
+    /// Reads a glyph with the variations of the instance applied
+    fn outline(&self, glyph: usize, depth: usize) -> Option<Outline> {
+        let data = self.data(glyph)?;
+        let (advance, lsb) = self.h_metrics(glyph)?;
+        if data.is_empty() {
+            let mut phantom = [(-lsb, 0.0), (advance - lsb, 0.0)];
+            if let Some(ref v) = self.variations {
+                let deltas = v.deltas(
+                    glyph,
+                    &[phantom[0], phantom[1], (0.0, 0.0), (0.0, 0.0)],
+                    None,
+                );
+                for (p, d) in phantom.iter_mut().zip(deltas) {
+                    p.0 += d.0;
+                }
+            }
+            return Some(Outline {
+                contours: Vec::new(),
+                origin: phantom[0].0,
+                advance: phantom[1].0,
+            });
+        }
+        let contour_count = sfnt::read_i16(data, 0)?;
+        let origin = sfnt::read_i16(data, 2)? as f32 - lsb;
+        let phantom = [
+            (origin, 0.0),
+            (origin + advance, 0.0),
+            (0.0, 0.0),
+            (0.0, 0.0),
+        ];
+        if contour_count >= 0 {
+            let mut contours = read_simple(data, contour_count as usize)?;
+            let mut points: Vec<(f32, f32)> = contours
+                .iter()
+                .flat_map(|c| c.iter().map(|p| (p.x, p.y)))
+                .collect();
+            let ends: Vec<usize> = contours
+                .iter()
+                .scan(0, |end, c| {
+                    *end += c.len();
+                    Some(*end)
+                })
+                .collect();
+            points.extend_from_slice(&phantom);
+            if let Some(ref v) = self.variations {
+                let deltas = v.deltas(glyph, &points, Some(&ends));
+                for (p, d) in points.iter_mut().zip(deltas) {
+                    p.0 += d.0;
+                    p.1 += d.1;
+                }
+            }
+            let mut varied = points.iter();
+            for contour in &mut contours {
+                for (p, &(x, y)) in contour.iter_mut().zip(&mut varied) {
+                    p.x = x;
+                    p.y = y;
+                }
+            }
+            let n = points.len();
+            return Some(Outline {
+                contours: contours,
+                origin: points[n - 4].0,
+                advance: points[n - 3].0,
+            });
+        }
+        let mut components = read_composite(data)?;
+        let mut points: Vec<(f32, f32)> = components.iter().map(|c| c.offset).collect();
+        points.extend_from_slice(&phantom);
+        if let Some(ref v) = self.variations {
+            let deltas = v.deltas(glyph, &points, None);
+            for (p, d) in points.iter_mut().zip(deltas) {
+                p.0 += d.0;
+                p.1 += d.1;
+            }
+        }
+        for (c, &offset) in components.iter_mut().zip(&points) {
+            c.offset = offset;
+        }
+        let mut contours: Vec<Vec<OutlinePoint>> = Vec::new();
+        if depth < MAX_DEPTH {
+            for c in components {
+                let child = match self.outline(c.glyph, depth + 1) {
+                    Some(child) => child,
+                    None => continue,
+                };
+                let (xx, xy, yx, yy) = (
+                    c.transform[0],
+                    c.transform[1],
+                    c.transform[2],
+                    c.transform[3],
+                );
+                let mut placed: Vec<Vec<OutlinePoint>> = child
+                    .contours
+                    .into_iter()
+                    .map(|contour| {
+                        contour
+                            .into_iter()
+                            .map(|p| OutlinePoint {
+                                x: xx * p.x + yx * p.y,
+                                y: xy * p.x + yy * p.y,
+                                on_curve: p.on_curve,
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let offset = match c.matched {
+                    // the component point lands on the point of the glyph so far
+                    Some((ours, theirs)) => {
+                        let ours = contours.iter().flat_map(|c| c.iter()).nth(ours);
+                        let theirs = placed.iter().flat_map(|c| c.iter()).nth(theirs);
+                        match (ours, theirs) {
+                            (Some(a), Some(b)) => (a.x - b.x, a.y - b.y),
+                            _ => (0.0, 0.0),
+                        }
+                    }
+                    None => c.offset,
+                };
+                for p in placed.iter_mut().flat_map(|c| c.iter_mut()) {
+                    p.x += offset.0;
+                    p.y += offset.1;
+                }
+                contours.extend(placed);
+            }
+        }
+        let n = points.len();
+        Some(Outline {
+            contours: contours,
+            origin: points[n - 4].0,
+            advance: points[n - 3].0,
+        })
+    }
+}
+
+/// Reads the contours of a simple glyph
+fn read_simple(data: &[u8], contour_count: usize) -> Option<Vec<Vec<OutlinePoint>>> {
+    let ends = (0..contour_count)
+        .map(|i| sfnt::read_u16(data, 10 + i * 2).map(|e| e as usize))
+        .collect::<Option<Vec<usize>>>()?;
+    let point_count = ends.last().map_or(0, |&e| e + 1);
+    let instructions = 10 + contour_count * 2;
+    let mut pos = instructions + 2 + sfnt::read_u16(data, instructions)? as usize;
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = *data.get(pos)?;
+        pos += 1;
+        flags.push(flag);
+        if flag & 8 != 0 {
+            let repeat = *data.get(pos)?;
+            pos += 1;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+    // x coordinates are followed by y, each as deltas from the previous point that are a byte
+    // (with the sign in the flags), the same as before, or a word
+    let mut read = |short: u8, same: u8| -> Option<Vec<f32>> {
+        let mut value = 0i32;
+        let mut values = Vec::with_capacity(point_count);
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = *data.get(pos)? as i32;
+                pos += 1;
+                value += if flag & same != 0 { delta } else { -delta };
+            } else if flag & same == 0 {
+                value += sfnt::read_i16(data, pos)? as i32;
+                pos += 2;
+            }
+            values.push(value as f32);
+        }
+        Some(values)
+    };
+    let xs = read(0x02, 0x10)?;
+    let ys = read(0x04, 0x20)?;
+    let mut contours = Vec::with_capacity(contour_count);
+    let mut start = 0;
+    for end in ends {
+        let end = (end + 1).max(start).min(point_count);
+        contours.push(
+            (start..end)
+                .map(|i| OutlinePoint {
+                    x: xs[i],
+                    y: ys[i],
+                    on_curve: flags[i] & 1 != 0,
+                })
+                .collect(),
+        );
+        start = end;
+    }
+    Some(contours)
+}
+
+/// Reads the components of a composite glyph
+fn read_composite(data: &[u8]) -> Option<Vec<Component>> {
+    let mut components = Vec::new();
+    let mut pos = 10;
+    loop {
+        let flags = sfnt::read_u16(data, pos)?;
+        let glyph = sfnt::read_u16(data, pos + 2)? as usize;
+        pos += 4;
+        let words = flags & 0x0001 != 0;
+        let xy = flags & 0x0002 != 0;
+        let (arg1, arg2) = match (words, xy) {
+            (true, true) => (
+                sfnt::read_i16(data, pos)? as i32,
+                sfnt::read_i16(data, pos + 2)? as i32,
+            ),
+            (true, false) => (
+                sfnt::read_u16(data, pos)? as i32,
+                sfnt::read_u16(data, pos + 2)? as i32,
+            ),
+            (false, true) => (
+                *data.get(pos)? as i8 as i32,
+                *data.get(pos + 1)? as i8 as i32,
+            ),
+            (false, false) => (*data.get(pos)? as i32, *data.get(pos + 1)? as i32),
+        };
+        pos += if words { 4 } else { 2 };
+        let f2dot14 = |offset: usize| sfnt::read_f2dot14(data, offset);
+        let transform = if flags & 0x0008 != 0 {
+            let scale = f2dot14(pos)?;
+            pos += 2;
+            [scale, 0.0, 0.0, scale]
+        } else if flags & 0x0040 != 0 {
+            let t = [f2dot14(pos)?, 0.0, 0.0, f2dot14(pos + 2)?];
+            pos += 4;
+            t
+        } else if flags & 0x0080 != 0 {
+            let t = [
+                f2dot14(pos)?,
+                f2dot14(pos + 2)?,
+                f2dot14(pos + 4)?,
+                f2dot14(pos + 6)?,
+            ];
+            pos += 8;
+            t
+        } else {
+            [1.0, 0.0, 0.0, 1.0]
+        };
+        components.push(Component {
+            glyph: glyph,
+            offset: if xy {
+                (arg1 as f32, arg2 as f32)
+            } else {
+                (0.0, 0.0)
+            },
+            matched: if xy {
+                None
+            } else {
+                Some((arg1 as usize, arg2 as usize))
+            },
+            transform: transform,
+        });
+        if flags & 0x0020 == 0 {
+            return Some(components);
+        }
+    }
+}
+
+impl Outline {
+    /// Makes strokes thicker by moving each point out along the bisector of its edges, so every
+    /// edge moves out by half the strength, as FreeType's FT_Outline_Embolden does. The glyph is
+    /// then moved right by half the strength to keep its left side bearing, and its advance grows
+    /// by the strength.
+    fn embolden(&mut self, strength: f32) {
+        let half = strength / 2.0;
+        // the outside of a contour is on the left of its direction when outer contours are
+        // clockwise, as in TrueType fonts, and on the right when they are counterclockwise
+        let area: f32 = self.contours.iter().map(|c| signed_area(c)).sum();
+        let outside = if area <= 0.0 { 1.0 } else { -1.0 };
+        for contour in &mut self.contours {
+            let original = contour.clone();
+            let n = original.len();
+            for (i, p) in contour.iter_mut().enumerate() {
+                let cur = original[i];
+                // the nearest points before and after that aren't on top of this one
+                let apart = |q: &OutlinePoint| q.x != cur.x || q.y != cur.y;
+                let prev = (1..n).map(|k| original[(i + n - k) % n]).find(&apart);
+                let next = (1..n).map(|k| original[(i + k) % n]).find(&apart);
+                let (prev, next) = match (prev, next) {
+                    (Some(prev), Some(next)) => (prev, next),
+                    _ => continue,
+                };
+                let (in_x, in_y) = (cur.x - prev.x, cur.y - prev.y);
+                let (out_x, out_y) = (next.x - cur.x, next.y - cur.y);
+                let l_in = (in_x * in_x + in_y * in_y).sqrt();
+                let l_out = (out_x * out_x + out_y * out_y).sqrt();
+                let (in_x, in_y, out_x, out_y) =
+                    (in_x / l_in, in_y / l_in, out_x / l_out, out_y / l_out);
+                let d = in_x * out_x + in_y * out_y;
+                // points where the contour turns back on itself are left alone
+                if d <= -0.9375 {
+                    continue;
+                }
+                let d = d + 1.0;
+                // sum of the outward normals of the two edges
+                let shift_x = -(in_y + out_y) * outside;
+                let shift_y = (in_x + out_x) * outside;
+                // at inner corners the shift is limited so short edges don't cross over
+                let q = (in_x * out_y - in_y * out_x) * outside;
+                let l = l_in.min(l_out);
+                let factor = if half * q <= l * d { half / d } else { l / q };
+                p.x += shift_x * factor + half;
+                p.y += shift_y * factor;
+            }
+        }
+        self.advance += strength;
+    }
+
+    /// Slants the glyph by moving points right in proportion to their height above the baseline
+    fn shear(&mut self, skew: f32) {
+        for p in self.contours.iter_mut().flat_map(|c| c.iter_mut()) {
+            p.x += p.y * skew;
+        }
+    }
+
+    /// Appends the glyph to a glyf table as a simple glyph, with its origin moved to 0, and
+    /// returns its bounds as [x_min, y_min, x_max, y_max], or None if it has no contours
+    fn write(&self, glyf: &mut Vec<u8>) -> Option<[i16; 4]> {
+        let round = |v: f32| v.round().max(-32768.0).min(32767.0) as i16;
+        let points: Vec<(i16, i16, bool)> = self
+            .contours
+            .iter()
+            .flat_map(|c| c.iter())
+            .map(|p| (round(p.x - self.origin), round(p.y), p.on_curve))
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        let mut bounds = [
+            i16::max_value(),
+            i16::max_value(),
+            i16::min_value(),
+            i16::min_value(),
+        ];
+        for &(x, y, _) in &points {
+            bounds = [
+                bounds[0].min(x),
+                bounds[1].min(y),
+                bounds[2].max(x),
+                bounds[3].max(y),
+            ];
+        }
+        let contours: Vec<&Vec<OutlinePoint>> =
+            self.contours.iter().filter(|c| !c.is_empty()).collect();
+        sfnt::write_u16(glyf, contours.len() as u16);
+        for &b in &bounds {
+            sfnt::write_u16(glyf, b as u16);
+        }
+        let mut end = 0;
+        for c in contours {
+            end += c.len();
+            sfnt::write_u16(glyf, (end - 1) as u16);
+        }
+        // no instructions
+        sfnt::write_u16(glyf, 0);
+        let (mut xs, mut ys) = (Vec::new(), Vec::new());
+        let mut last = (0i32, 0i32);
+        for &(x, y, on_curve) in &points {
+            let (dx, dy) = (x as i32 - last.0, y as i32 - last.1);
+            let flag = on_curve as u8
+                | write_coordinate(dx, 0x02, 0x10, &mut xs)
+                | write_coordinate(dy, 0x04, 0x20, &mut ys);
+            glyf.push(flag);
+            last = (x as i32, y as i32);
+        }
+        glyf.extend(xs);
+        glyf.extend(ys);
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+        Some(bounds)
+    }
+}
+
+/// Twice the area a contour encloses, positive if it goes counterclockwise
+fn signed_area(contour: &[OutlinePoint]) -> f32 {
+    let n = contour.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum()
+}
+
+/// Writes a delta from the previous coordinate in the shortest form, and returns its flags
+fn write_coordinate(delta: i32, short: u8, same: u8, out: &mut Vec<u8>) -> u8 {
+    if delta == 0 {
+        same
+    } else if delta.abs() < 256 {
+        out.push(delta.abs() as u8);
+        short | if delta > 0 { same } else { 0 }
+    } else {
+        sfnt::write_u16(out, delta as u16);
+        0
+    }
+}
+
+impl<'a> Variations<'a> {
+    /// Sums the deltas of the tuples of a glyph that apply at the instance coordinates. points
+    /// are the original points, with the phantom points at the end. Simple glyphs pass the end of
+    /// each contour, so points a tuple leaves out get deltas interpolated from their neighbors.
+    fn deltas(
+        &self,
+        glyph: usize,
+        points: &[(f32, f32)],
+        ends: Option<&[usize]>,
+    ) -> Vec<(f32, f32)> {
+        let mut total = vec![(0.0, 0.0); points.len()];
+        self.add_deltas(glyph, points, ends, &mut total);
+        total
+    }
+
+    fn add_deltas(
+        &self,
+        glyph: usize,
+        points: &[(f32, f32)],
+        ends: Option<&[usize]>,
+        total: &mut [(f32, f32)],
+    ) -> Option<()> {
+        let gvar = self.gvar;
+        let axis_count = sfnt::read_u16(gvar, 4)? as usize;
+        let shared_tuples = sfnt::read_u32(gvar, 8)? as usize;
+        let glyph_count = sfnt::read_u16(gvar, 12)? as usize;
+        let long_offsets = sfnt::read_u16(gvar, 14)? & 1 != 0;
+        let array = sfnt::read_u32(gvar, 16)? as usize;
+        if glyph >= glyph_count {
+            return None;
+        }
+        let (start, end) = if long_offsets {
+            (
+                sfnt::read_u32(gvar, 20 + glyph * 4)? as usize,
+                sfnt::read_u32(gvar, 24 + glyph * 4)? as usize,
+            )
+        } else {
+            (
+                sfnt::read_u16(gvar, 20 + glyph * 2)? as usize * 2,
+                sfnt::read_u16(gvar, 22 + glyph * 2)? as usize * 2,
+            )
+        };
+        if end <= start {
+            return None;
+        }
+        let data = gvar.get(array + start..array + end)?;
+        let count = sfnt::read_u16(data, 0)?;
+        let mut serialized = sfnt::read_u16(data, 2)? as usize;
+        let shared_points = if count & 0x8000 != 0 {
+            let (p, next) = read_points(data, serialized)?;
+            serialized = next;
+            p
+        } else {
+            None
+        };
+        let mut header = 4;
+        for _ in 0..count & 0x0FFF {
+            let size = sfnt::read_u16(data, header)? as usize;
+            let index = sfnt::read_u16(data, header + 2)?;
+            header += 4;
+            let peak = if index & 0x8000 != 0 {
+                header += axis_count * 2;
+                read_tuple_at(data, header - axis_count * 2, axis_count)?
+            } else {
+                let shared = shared_tuples + (index & 0x0FFF) as usize * axis_count * 2;
+                read_tuple_at(gvar, shared, axis_count)?
+            };
+            let intermediate = if index & 0x4000 != 0 {
+                header += axis_count * 4;
+                let start = header - axis_count * 4;
+                Some((
+                    read_tuple_at(data, start, axis_count)?,
+                    read_tuple_at(data, start + axis_count * 2, axis_count)?,
+                ))
+            } else {
+                None
+            };
+            let tuple = data.get(serialized..serialized + size)?;
+            serialized += size;
+            let scalar = scalar(self.coords, &peak, intermediate.as_ref());
+            if scalar == 0.0 {
+                continue;
+            }
+            let (tuple_points, pos) = if index & 0x2000 != 0 {
+                read_points(tuple, 0)?
+            } else {
+                (shared_points.clone(), 0)
+            };
+            let n = tuple_points.as_ref().map_or(points.len(), |p| p.len());
+            let (xs, pos) = read_deltas(tuple, pos, n)?;
+            let (ys, _) = read_deltas(tuple, pos, n)?;
+            let deltas = match tuple_points {
+                None => xs.into_iter().zip(ys).map(Some).collect(),
+                Some(numbers) => {
+                    let mut deltas = vec![None; points.len()];
+                    for (&i, (&x, &y)) in numbers.iter().zip(xs.iter().zip(&ys)) {
+                        if let Some(d) = deltas.get_mut(i) {
+                            *d = Some((x, y));
+                        }
+                    }
+                    if let Some(ends) = ends {
+                        let mut start = 0;
+                        for &end in ends {
+                            interpolate(&points[start..end], &mut deltas[start..end]);
+                            start = end;
+                        }
+                    }
+                    deltas
+                }
+            };
+            for (t, d) in total.iter_mut().zip(deltas) {
+                if let Some((x, y)) = d {
+                    t.0 += x * scalar;
+                    t.1 += y * scalar;
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// Reads the coordinates of a tuple, one per axis
+fn read_tuple_at(data: &[u8], offset: usize, axis_count: usize) -> Option<Vec<f32>> {
+    (0..axis_count)
+        .map(|i| sfnt::read_f2dot14(data, offset + i * 2))
+        .collect()
+}
+
+/// How much of a tuple applies at the coordinates, from 0 to 1
+fn scalar(coords: &[f32], peak: &[f32], intermediate: Option<&(Vec<f32>, Vec<f32>)>) -> f32 {
+    let mut scalar = 1.0;
+    for (i, &peak) in peak.iter().enumerate() {
+        let v = coords.get(i).cloned().unwrap_or(0.0);
+        if peak == 0.0 || v == peak {
+            continue;
+        }
+        if v == 0.0 {
+            return 0.0;
+        }
+        match intermediate {
+            Some(&(ref start, ref end)) => {
+                let (start, end) = (start[i], end[i]);
+                if start > peak || peak > end || (start < 0.0 && end > 0.0) {
+                    continue;
+                }
+                if v < start || v > end {
+                    return 0.0;
+                }
+                scalar *= if v < peak {
+                    (v - start) / (peak - start)
+                } else {
+                    (end - v) / (end - peak)
+                };
+            }
+            None => {
+                if v < peak.min(0.0) || v > peak.max(0.0) {
+                    return 0.0;
+                }
+                scalar *= v / peak;
+            }
+        }
+    }
+    scalar
+}
+
+/// Reads packed point numbers, returning None for all points, and the offset after them
+fn read_points(data: &[u8], pos: usize) -> Option<(Option<Vec<usize>>, usize)> {
+    let mut pos = pos;
+    let mut count = *data.get(pos)? as usize;
+    pos += 1;
+    if count == 0 {
+        return Some((None, pos));
+    }
+    if count & 0x80 != 0 {
+        count = (count & 0x7F) << 8 | *data.get(pos)? as usize;
+        pos += 1;
+    }
+    let mut points = Vec::with_capacity(count);
+    let mut last = 0;
+    while points.len() < count {
+        let control = *data.get(pos)?;
+        pos += 1;
+        for _ in 0..(control & 0x7F) as usize + 1 {
+            if control & 0x80 != 0 {
+                last += sfnt::read_u16(data, pos)? as usize;
+                pos += 2;
+            } else {
+                last += *data.get(pos)? as usize;
+                pos += 1;
+            }
+            points.push(last);
+        }
+    }
+    points.truncate(count);
+    Some((Some(points), pos))
+}
+
+/// Reads count packed deltas, returning them and the offset after them
+fn read_deltas(data: &[u8], pos: usize, count: usize) -> Option<(Vec<f32>, usize)> {
+    let mut pos = pos;
+    let mut deltas = Vec::with_capacity(count);
+    while deltas.len() < count {
+        let control = *data.get(pos)?;
+        pos += 1;
+        for _ in 0..(control & 0x3F) as usize + 1 {
+            let delta = if control & 0x80 != 0 {
+                0
+            } else if control & 0x40 != 0 {
+                pos += 2;
+                sfnt::read_i16(data, pos - 2)?
+            } else {
+                pos += 1;
+                *data.get(pos - 1)? as i8 as i16
+            };
+            deltas.push(delta as f32);
+        }
+    }
+    deltas.truncate(count);
+    Some((deltas, pos))
+}
+
+/// Fills in the deltas of the points of a contour a tuple left out, from the nearest points
+/// before and after them that it has deltas for
+fn interpolate(points: &[(f32, f32)], deltas: &mut [Option<(f32, f32)>]) {
+    let touched: Vec<usize> = (0..deltas.len()).filter(|&i| deltas[i].is_some()).collect();
+    if touched.is_empty() {
+        return;
+    }
+    let n = deltas.len();
+    for (k, &a) in touched.iter().enumerate() {
+        let b = touched[(k + 1) % touched.len()];
+        let (da, db) = (deltas[a].unwrap(), deltas[b].unwrap());
+        let mut i = (a + 1) % n;
+        while i != b {
+            let infer = |coord: fn(&(f32, f32)) -> f32, delta: fn(&(f32, f32)) -> f32| {
+                let (o, oa, ob) = (coord(&points[i]), coord(&points[a]), coord(&points[b]));
+                let (da, db) = (delta(&da), delta(&db));
+                if oa == ob {
+                    return if da == db { da } else { 0.0 };
+                }
+                let ((lo, dlo), (hi, dhi)) = if oa < ob {
+                    ((oa, da), (ob, db))
+                } else {
+                    ((ob, db), (oa, da))
+                };
+                if o <= lo {
+                    dlo
+                } else if o >= hi {
+                    dhi
+                } else {
+                    dlo + (o - lo) / (hi - lo) * (dhi - dlo)
+                }
+            };
+            let x = infer(|p| p.0, |d| d.0);
+            let y = infer(|p| p.1, |d| d.1);
+            deltas[i] = Some((x, y));
+            i = (i + 1) % n;
+        }
+    }
+}
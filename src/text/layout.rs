@@ -0,0 +1,1824 @@
+extern crate unicode_bidi;
+extern crate unicode_normalization;
+extern crate unicode_segmentation;
+
+use std::ops::Range;
+use std::rc::Rc;
+
+use rusttype::{
+    point, vector, GlyphId, Point, PositionedGlyph, Rect, ScaledGlyph, VMetrics, Vector,
+};
+
+use self::unicode_bidi::BidiInfo;
+use self::unicode_normalization::char::is_combining_mark;
+use self::unicode_segmentation::UnicodeSegmentation;
+
+use text::bidi;
+use text::color::{self, BitmapGlyphs, Color, ColorBitmap, ColorGlyphs};
+use text::font::{FaceId, FontSet, InstanceId, Synthesis};
+use text::gsub::GlyphInfo;
+use text::hyphenation::Hyphenator;
+use text::linebreak;
+use text::link::Link;
+use text::rich::{self, InlineBox, RichText};
+use text::shaping::{ShapedGlyph, Shaper};
+use text::style::TextStyle;
+use text::vertical;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WritingMode {
+    Horizontal,
+    /// Top to bottom columns that flow from right to left, as CJK text is traditionally set
+    VerticalRl,
+}
+
+/// What happens to a line that is wider than the layout
+#[derive(Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// The line is left as it is
+    Visible,
+    /// Clusters that don't fit are removed
+    Clip,
+    /// The end of the line is replaced with an ellipsis
+    Ellipsis,
+    /// The middle of the line is replaced with an ellipsis, which keeps the end of file names
+    MiddleEllipsis,
+}
+
+/// Font size of rich text that has no spans
+const DEFAULT_SIZE: f32 = 16.0;
+
+/// Number of spaces between tab stops when no tab interval is set
+const TAB_SPACES: f32 = 8.0;
+
+/// Opacity of the markers drawn for visible whitespace, relative to the text
+const WHITESPACE_ALPHA: f32 = 0.5;
+
+#[derive(Clone)]
+pub struct LayoutOptions {
+    /// Width of the area the text is laid out in
+    pub width: f32,
+    /// Height of the area, which vertical columns are broken to fit when wrap is on. None lets
+    /// columns be as long as their text.
+    pub height: Option<f32>,
+    pub writing_mode: WritingMode,
+    /// Positions of tab stops from the start of the line, in increasing order
+    pub tab_stops: Vec<f32>,
+    /// Distance between the tab stops after the last one in tab_stops, or None for the width of
+    /// eight spaces
+    pub tab_interval: Option<f32>,
+    /// If spaces and tabs are drawn as faint dots and arrows, for code and logs
+    pub show_whitespace: bool,
+    /// Maximum number of lines (or columns of vertical text). With an ellipsis overflow, the
+    /// last line shown ends with an ellipsis if lines were cut off.
+    pub max_lines: Option<usize>,
+    /// Lines that are too wide are only shortened in horizontal text, but the ellipsis after
+    /// lines that max_lines cut off is also added to columns
+    pub overflow: Overflow,
+    /// If lines that are too long are broken between words, at the width for horizontal text
+    /// and at the height for vertical text
+    pub wrap: bool,
+    /// Hyphenation patterns that wrapping uses for text in their language
+    pub hyphenators: Vec<Rc<Hyphenator>>,
+}
+
+impl LayoutOptions {
+    pub fn new(width: f32) -> LayoutOptions {
+        LayoutOptions {
+            width: width,
+            height: None,
+            writing_mode: WritingMode::Horizontal,
+            tab_stops: Vec::new(),
+            tab_interval: None,
+            show_whitespace: false,
+            max_lines: None,
+            overflow: Overflow::Visible,
+            wrap: false,
+            hyphenators: Vec::new(),
+        }
+    }
+}
+
+/// A glyph placed by the layout
+pub struct LayoutGlyph<'font> {
+    pub glyph: PositionedGlyph<'font>,
+    /// The font instance the glyph is from, which is its font ID in the glyph cache
+    pub font_id: InstanceId,
+    pub color: Color,
+    /// Index in Layout::clusters of the cluster this glyph is part of
+    pub cluster: usize,
+    /// For sideways text in vertical columns, the point the glyph is turned 90 degrees clockwise
+    /// around
+    pub rotate_about: Option<Point<f32>>,
+    /// A color bitmap that is drawn instead of the glyph's outline, as emoji fonts have
+    pub bitmap: Option<Rc<ColorBitmap>>,
+}
+
+/// A grapheme cluster, which is the smallest unit the caret can move over. It is drawn as a base
+/// glyph followed by any number of zero advance marks.
+pub struct Cluster {
+    /// Byte range in Layout::text
+    pub range: Range<usize>,
+    pub rtl: bool,
+    /// Start and length of the space the cluster takes up along its line, which is horizontal for
+    /// horizontal text and vertical for vertical text
+    pub pos: f32,
+    pub advance: f32,
+}
+
+pub struct Line {
+    /// Byte range in Layout::text, not including the line or paragraph separator
+    pub range: Range<usize>,
+    /// Range in Layout::clusters, which are stored in visual (left to right or top to bottom)
+    /// order
+    pub clusters: Range<usize>,
+    /// y of the baseline of a horizontal line, or x of the center of a vertical column
+    pub baseline: f32,
+    /// The space the line takes up across lines, which is y for horizontal text and x for
+    /// vertical text. It includes the gap to the next line.
+    pub extent: Range<f32>,
+    pub rtl: bool,
+}
+
+/// Where an inline box was placed
+pub struct PlacedBox {
+    pub id: usize,
+    pub rect: Rect<f32>,
+}
+
+pub struct Layout<'font> {
+    /// The text, which all byte ranges refer to. Each span was NFC normalized on its own by
+    /// `RichText::push`, so the text as a whole may not be in NFC.
+    pub text: String,
+    pub glyphs: Vec<LayoutGlyph<'font>>,
+    pub clusters: Vec<Cluster>,
+    pub lines: Vec<Line>,
+    pub boxes: Vec<PlacedBox>,
+    /// Byte ranges of links and where they lead
+    pub links: Vec<(Range<usize>, Link)>,
+    pub width: f32,
+    pub writing_mode: WritingMode,
+}
+
+pub fn layout_paragraph<'font>(
+    fonts: &mut FontSet<'font>,
+    text: &str,
+    style: &TextStyle,
+    options: &LayoutOptions,
+) -> Layout<'font> {
+    layout_rich(fonts, &RichText::plain(text, style), options)
+}
+
+/// Lays out text with spans in different styles. Lines are as tall as the largest text and
+/// inline boxes on them.
+pub fn layout_rich<'font>(
+    fonts: &mut FontSet<'font>,
+    text: &RichText,
+    options: &LayoutOptions,
+) -> Layout<'font> {
+    let mut spans: Vec<(Range<usize>, &TextStyle)> = text
+        .spans
+        .iter()
+        .map(|&(ref range, ref style)| (range.clone(), style))
+        .collect();
+    let default_style = TextStyle::new(DEFAULT_SIZE);
+    if spans.is_empty() {
+        spans.push((0..text.text.len(), &default_style));
+    }
+    // spans with the same style share their faces
+    let mut styles: Vec<&TextStyle> = Vec::new();
+    let mut span_styles = Vec::new();
+    for &(ref range, style) in &spans {
+        let index = match styles.iter().position(|s| *s == style) {
+            Some(i) => i,
+            None => {
+                styles.push(style);
+                styles.len() - 1
+            }
+        };
+        span_styles.push((range.clone(), index));
+    }
+    let mut style_faces: Vec<Vec<(FaceId, InstanceId)>> = Vec::new();
+    for style in &styles {
+        let (face, synthesis) = match fonts.select(style) {
+            Some(selected) => selected,
+            // there is nothing to draw the text with
+            None => return Layout::empty(&text.text, options),
+        };
+        let faces = fonts
+            .fallbacks(face)
+            .into_iter()
+            .map(|f| (f, fonts.instance(f, &style.variations, synthesis)))
+            .collect();
+        style_faces.push(faces);
+    }
+    let mut faces = Vec::new();
+    let mut builder_styles = Vec::new();
+    for (style, face_ids) in styles.iter().zip(style_faces) {
+        let first = faces.len();
+        for (face, font_id) in face_ids {
+            faces.push(BuilderFace {
+                font_id: font_id,
+                shaper: Shaper::new(fonts.font(font_id), fonts.face(face).gsub.as_ref(), style),
+                colors: fonts.face(face).colors.as_ref(),
+                bitmaps: fonts.face(face).bitmaps.as_ref(),
+                color: style.color,
+            });
+        }
+        let v_metrics = faces[first]
+            .shaper
+            .font()
+            .v_metrics(faces[first].shaper.scale());
+        builder_styles.push(BuilderStyle {
+            faces: (first..faces.len()).collect(),
+            v_metrics: v_metrics,
+            hyphenator: match style.language {
+                Some(ref language) => options
+                    .hyphenators
+                    .iter()
+                    .find(|h| h.language == *language)
+                    .cloned(),
+                None => None,
+            },
+        });
+    }
+    let (glyphs, clusters, lines, boxes) = {
+        let mut builder = Builder {
+            text: &text.text,
+            faces: faces,
+            styles: builder_styles,
+            spans: span_styles,
+            inline_boxes: &text.boxes,
+            current: 0,
+            glyphs: Vec::new(),
+            clusters: Vec::new(),
+            lines: Vec::new(),
+        };
+        match options.writing_mode {
+            WritingMode::Horizontal => builder.horizontal(options),
+            WritingMode::VerticalRl => builder.vertical(options),
+        }
+        let boxes = builder.place_boxes(options.writing_mode);
+        (builder.glyphs, builder.clusters, builder.lines, boxes)
+    };
+    Layout {
+        text: text.text.clone(),
+        glyphs: glyphs,
+        clusters: clusters,
+        lines: lines,
+        boxes: boxes,
+        links: text.links.clone(),
+        width: options.width,
+        writing_mode: options.writing_mode,
+    }
+}
+
+/// A face the builder can draw characters with, in one of the styles of the text
+struct BuilderFace<'a, 'font: 'a> {
+    font_id: InstanceId,
+    shaper: Shaper<'a, 'font>,
+    colors: Option<&'a ColorGlyphs>,
+    bitmaps: Option<&'a BitmapGlyphs<'font>>,
+    color: Color,
+}
+
+struct BuilderStyle {
+    /// Indices in Builder::faces of the face of the style, followed by the faces to fall back to
+    /// for characters it lacks
+    faces: Vec<usize>,
+    v_metrics: VMetrics,
+    hyphenator: Option<Rc<Hyphenator>>,
+}
+
+/// Where the glyphs of a run go
+#[derive(Clone, Copy)]
+enum Placement {
+    /// Along a horizontal line, with this baseline
+    Horizontal(f32),
+    /// Turned sideways in a vertical column, with this center
+    Sideways(f32),
+}
+
+struct Builder<'a, 'font: 'a> {
+    text: &'a str,
+    faces: Vec<BuilderFace<'a, 'font>>,
+    styles: Vec<BuilderStyle>,
+    /// Byte ranges of the text and the index in styles they are set in
+    spans: Vec<(Range<usize>, usize)>,
+    inline_boxes: &'a [(usize, InlineBox)],
+    /// The style characters are being shaped in
+    current: usize,
+    glyphs: Vec<LayoutGlyph<'font>>,
+    clusters: Vec<Cluster>,
+    lines: Vec<Line>,
+}
+
+impl<'a, 'font> Builder<'a, 'font> {
+    /// Returns the index in self.styles of the style of a text index. Indices between spans get
+    /// the style of the span after them.
+    fn style_at(&self, index: usize) -> usize {
+        self.spans
+            .iter()
+            .find(|&&(ref range, _)| index < range.end)
+            .or(self.spans.last())
+            .map_or(0, |&(_, style)| style)
+    }
+
+    /// Makes the style of a text index the one characters are shaped in
+    fn set_style_at(&mut self, index: usize) {
+        self.current = self.style_at(index);
+    }
+
+    /// The inline box at a text index, if there is one
+    fn inline_box(&self, index: usize) -> Option<InlineBox> {
+        self.inline_boxes
+            .iter()
+            .find(|&&(i, _)| i == index)
+            .map(|&(_, b)| b)
+    }
+
+    /// The face of the current style
+    fn primary(&self) -> &BuilderFace<'a, 'font> {
+        &self.faces[self.styles[self.current].faces[0]]
+    }
+
+    /// Shapes a character with the first face of the current style that has a glyph for it,
+    /// returning the index of the face in self.faces
+    fn shape(&self, c: char) -> (usize, ShapedGlyph<'font>) {
+        let face = self.face_for(&[c]);
+        (face, self.faces[face].shaper.glyph(c))
+    }
+
+    /// The first face of the current style that has glyphs for all the visible characters of a
+    /// grapheme, or else the first that has its first visible character. Emoji are drawn with a
+    /// face that has color glyphs if there is one, unless followed by the text variation selector.
+    fn face_for(&self, chars: &[char]) -> usize {
+        let faces = &self.styles[self.current].faces;
+        let visible = || chars.iter().cloned().filter(|&c| !color::is_invisible(c));
+        let has_all = |f: usize| visible().all(|c| self.faces[f].shaper.has_glyph(c));
+        let has_first = |f: usize| match visible().next() {
+            Some(c) => self.faces[f].shaper.has_glyph(c),
+            None => false,
+        };
+        let emoji =
+            chars.first().map_or(false, |&c| color::is_emoji(c)) && !chars.contains(&'\u{FE0E}');
+        let is_color = |f: usize| self.faces[f].colors.is_some() || self.faces[f].bitmaps.is_some();
+        faces
+            .iter()
+            .cloned()
+            .filter(|_| emoji)
+            .find(|&f| is_color(f) && has_all(f))
+            .or_else(|| faces.iter().cloned().find(|&f| has_all(f)))
+            .or_else(|| faces.iter().cloned().find(|&f| has_first(f)))
+            .unwrap_or(faces[0])
+    }
+
+    /// The character drawn for c in the current style: mirrored in right to left text, or its
+    /// vertical form in upright text if one of the faces has it
+    fn display_form(&self, c: char, rtl: bool, upright: bool) -> char {
+        if rtl {
+            return bidi::mirror(c);
+        }
+        let faces = &self.styles[self.current].faces;
+        match vertical::vertical_form(c) {
+            Some(v) if upright && faces.iter().any(|&f| self.faces[f].shaper.has_glyph(v)) => v,
+            _ => c,
+        }
+    }
+
+    /// Shapes a range of text, returning its glyphs in logical order with the index of their
+    /// face in self.faces. Each grapheme is drawn with the first face of its style that has all
+    /// of its characters, and graphemes with the same face are shaped together so ligatures can
+    /// form across them. Control and invisible characters get no glyphs.
+    fn shape_run(
+        &mut self,
+        range: Range<usize>,
+        rtl: bool,
+        upright: bool,
+    ) -> Vec<(usize, GlyphInfo)> {
+        let text = self.text;
+        let mut segments: Vec<(usize, Vec<(char, Range<usize>)>)> = Vec::new();
+        for (index, grapheme) in text[range.clone()].grapheme_indices(true) {
+            let index = range.start + index;
+            self.set_style_at(index);
+            let chars: Vec<(char, Range<usize>)> = grapheme
+                .char_indices()
+                .filter(|&(_, c)| !c.is_control())
+                .map(|(i, c)| {
+                    let range = index + i..index + i + c.len_utf8();
+                    (self.display_form(c, rtl, upright), range)
+                })
+                .collect();
+            let face = self.face_for(&chars.iter().map(|&(c, _)| c).collect::<Vec<_>>());
+            if let Some(&mut (f, ref mut segment)) = segments.last_mut() {
+                if f == face {
+                    segment.extend(chars);
+                    continue;
+                }
+            }
+            segments.push((face, chars));
+        }
+        let mut glyphs = Vec::new();
+        for (face, chars) in segments {
+            let shaped = self.faces[face].shaper.substitute(&chars);
+            glyphs.extend(
+                shaped
+                    .into_iter()
+                    .filter(|g| {
+                        !text[g.range.clone()]
+                            .chars()
+                            .all(|c| c.is_control() || color::is_invisible(c))
+                    })
+                    .map(|g| (face, g)),
+            );
+        }
+        glyphs
+    }
+
+    /// Places the glyphs of a cluster from its left edge, in visual order with kerning between
+    /// them, and returns them with their face and x along with the advance of the cluster.
+    /// Combining marks after the first glyph take up no space. Zero width marks are drawn at the
+    /// pen position after the glyph they follow, which is on its left in right to left text, and
+    /// marks that have a width of their own are centered over it instead.
+    fn arrange(
+        &self,
+        glyphs: &[(usize, GlyphInfo)],
+        rtl: bool,
+        last_glyph: &mut Option<(usize, GlyphId)>,
+    ) -> (Vec<(usize, ShapedGlyph<'font>, f32)>, f32) {
+        let text = self.text;
+        let mut placed: Vec<(usize, ShapedGlyph<'font>, f32)> = glyphs
+            .iter()
+            .map(|&(face, ref g)| {
+                let source = &text[g.range.clone()];
+                let digit = source.len() == 1 && source.chars().all(|c| c.is_digit(10));
+                (face, self.faces[face].shaper.shaped(g.id, digit), 0.0)
+            })
+            .collect();
+        let marks: Vec<bool> = glyphs
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, ref g))| {
+                i > 0
+                    && text[g.range.clone()]
+                        .chars()
+                        .next()
+                        .map_or(false, is_combining_mark)
+            })
+            .collect();
+        let order: Vec<usize> = if rtl {
+            (0..placed.len()).rev().collect()
+        } else {
+            (0..placed.len()).collect()
+        };
+        let mut pen = 0.0;
+        for i in order {
+            if marks[i] {
+                continue;
+            }
+            let face = placed[i].0;
+            pen += self.kerning(*last_glyph, face, &placed[i].1);
+            placed[i].2 = pen;
+            pen += placed[i].1.advance;
+            *last_glyph = if placed[i].1.tabular {
+                None
+            } else {
+                Some((face, placed[i].1.glyph.id()))
+            };
+        }
+        let mut base = 0;
+        for i in 0..placed.len() {
+            if !marks[i] {
+                base = i;
+                continue;
+            }
+            let (base_x, base_advance) = (placed[base].2, placed[base].1.advance);
+            let advance = placed[i].1.advance;
+            placed[i].2 = if advance != 0.0 {
+                base_x + (base_advance - advance) / 2.0
+            } else if rtl {
+                base_x
+            } else {
+                base_x + base_advance
+            };
+        }
+        (placed, pen)
+    }
+
+    /// Position of a glyph at x along a run, in a cluster that starts at pos, and the point it is
+    /// rotated about
+    fn place(&self, placement: Placement, pos: f32, x: f32) -> (Point<f32>, Option<Point<f32>>) {
+        match placement {
+            Placement::Horizontal(baseline) => (point(x, baseline), None),
+            Placement::Sideways(center) => {
+                let (_, _, sideways_baseline) = self.vertical_metrics();
+                let position = point(center + x - pos, pos + sideways_baseline);
+                (position, Some(point(center, pos)))
+            }
+        }
+    }
+
+    /// Lays out a run of text in one direction starting at pen, and moves pen to its end. Tabs
+    /// and inline boxes split the run, and the text between them is shaped together.
+    fn push_run(
+        &mut self,
+        options: &LayoutOptions,
+        range: Range<usize>,
+        rtl: bool,
+        placement: Placement,
+        pen: &mut f32,
+        last_glyph: &mut Option<(usize, GlyphId)>,
+    ) {
+        let text = self.text;
+        let mut pieces = Vec::new();
+        let mut start = range.start;
+        for (index, grapheme) in text[range.clone()].grapheme_indices(true) {
+            let index = range.start + index;
+            if grapheme == "\t" || self.inline_box(index).is_some() {
+                if start < index {
+                    pieces.push(start..index);
+                }
+                pieces.push(index..index + grapheme.len());
+                start = index + grapheme.len();
+            }
+        }
+        if start < range.end {
+            pieces.push(start..range.end);
+        }
+        if rtl {
+            pieces.reverse();
+        }
+        for piece in pieces {
+            self.set_style_at(piece.start);
+            if let Some(b) = self.inline_box(piece.start) {
+                self.push_cluster(piece, rtl, *pen, b.width);
+                *pen += b.width;
+                *last_glyph = None;
+                continue;
+            }
+            if &text[piece.clone()] == "\t" {
+                let stop = self.tab_stop(options, *pen);
+                if options.show_whitespace {
+                    let (position, pivot) = self.place(placement, *pen, *pen);
+                    self.push_whitespace('→', position.x, stop - *pen, position.y, pivot);
+                }
+                self.push_cluster(piece, rtl, *pen, stop - *pen);
+                *pen = stop;
+                *last_glyph = None;
+                continue;
+            }
+            let glyphs = self.shape_run(piece.clone(), rtl, false);
+            let mut clusters = group_clusters(text, piece, glyphs);
+            if rtl {
+                clusters.reverse();
+            }
+            for (range, glyphs) in clusters {
+                self.set_style_at(range.start);
+                let (placed, advance) = self.arrange(&glyphs, rtl, last_glyph);
+                let start = *pen;
+                for (face, shaped, x) in placed {
+                    let (position, pivot) = self.place(placement, start, start + x + shaped.offset);
+                    self.push_glyph(face, shaped.glyph, position, pivot);
+                }
+                if options.show_whitespace
+                    && text[range.clone()].chars().next().map_or(false, is_space)
+                {
+                    let (position, pivot) = self.place(placement, start, start);
+                    self.push_whitespace('·', position.x, advance, position.y, pivot);
+                }
+                self.push_cluster(range, rtl, start, advance);
+                *pen += advance;
+            }
+        }
+    }
+
+    /// Distance above and below the baseline a range of text needs, and the gap after it. These
+    /// are the largest of the styles and inline boxes in the range.
+    fn line_metrics(&self, range: &Range<usize>) -> (f32, f32, f32) {
+        let mut styles: Vec<usize> = self
+            .spans
+            .iter()
+            .filter(|&&(ref r, _)| r.start < range.end && range.start < r.end)
+            .map(|&(_, style)| style)
+            .collect();
+        if styles.is_empty() {
+            styles.push(self.style_at(range.start));
+        }
+        let (mut ascent, mut descent, mut gap) = (0.0f32, 0.0f32, 0.0f32);
+        for style in styles {
+            let v_metrics = self.styles[style].v_metrics;
+            ascent = ascent.max(v_metrics.ascent);
+            descent = descent.max(-v_metrics.descent);
+            gap = gap.max(v_metrics.line_gap);
+        }
+        for &(index, b) in self.inline_boxes {
+            if index >= range.start && index < range.end {
+                ascent = ascent.max(b.baseline);
+                descent = descent.max(b.height - b.baseline);
+            }
+        }
+        (ascent, descent, gap)
+    }
+
+    /// Finds where the inline boxes ended up, from the clusters that stand for them
+    fn place_boxes(&self, writing_mode: WritingMode) -> Vec<PlacedBox> {
+        let mut placed = Vec::new();
+        for line in &self.lines {
+            for c in &self.clusters[line.clusters.clone()] {
+                if c.range.len() != rich::OBJECT_REPLACEMENT.len_utf8() {
+                    continue;
+                }
+                let b = match self.inline_box(c.range.start) {
+                    Some(b) => b,
+                    None => continue,
+                };
+                let min = match writing_mode {
+                    WritingMode::Horizontal => point(c.pos, line.baseline - b.baseline),
+                    WritingMode::VerticalRl => point(line.baseline - b.width / 2.0, c.pos),
+                };
+                placed.push(PlacedBox {
+                    id: b.id,
+                    rect: Rect {
+                        min: min,
+                        max: point(min.x + b.width, min.y + b.height),
+                    },
+                });
+            }
+        }
+        placed
+    }
+
+    /// Adds a glyph to the cluster that will be pushed next. Color glyphs are added as one glyph
+    /// per layer, and glyphs with a color bitmap as a glyph with the bitmap.
+    fn push_glyph(
+        &mut self,
+        face: usize,
+        glyph: ScaledGlyph<'font>,
+        position: Point<f32>,
+        rotate_about: Option<Point<f32>>,
+    ) {
+        let cluster = self.clusters.len();
+        let font_id = self.faces[face].font_id;
+        let text_color = self.faces[face].color;
+        let bitmap = match self.faces[face].bitmaps {
+            Some(bitmaps) => bitmaps.bitmap(glyph.id().0 as u16, glyph.scale().y),
+            None => None,
+        };
+        if bitmap.is_some() {
+            self.glyphs.push(LayoutGlyph {
+                glyph: glyph.positioned(position),
+                font_id: font_id,
+                color: text_color,
+                cluster: cluster,
+                rotate_about: rotate_about,
+                bitmap: bitmap,
+            });
+            return;
+        }
+        let layers = match self.faces[face].colors {
+            Some(colors) => colors.layers(glyph.id()),
+            None => None,
+        };
+        let layers = match layers {
+            Some(layers) => {
+                let font = self.faces[face].shaper.font();
+                layers
+                    .into_iter()
+                    .map(|(id, color)| {
+                        (
+                            font.glyph(id).scaled(glyph.scale()),
+                            color.unwrap_or(text_color),
+                        )
+                    })
+                    .collect()
+            }
+            None => vec![(glyph, text_color)],
+        };
+        for (layer, color) in layers {
+            self.glyphs.push(LayoutGlyph {
+                glyph: layer.positioned(position),
+                font_id: font_id,
+                color: color,
+                cluster: cluster,
+                rotate_about: rotate_about,
+                bitmap: None,
+            });
+        }
+    }
+
+    /// Width of a range of text set on a single line, ignoring ligatures between graphemes
+    fn measure(&mut self, range: Range<usize>) -> f32 {
+        let text = self.text;
+        let mut width = 0.0;
+        let mut last_glyph = None;
+        for (index, grapheme) in text[range.clone()].grapheme_indices(true) {
+            let index = range.start + index;
+            let base = match grapheme.chars().next() {
+                Some(c) if !color::is_invisible(c) => c,
+                _ => continue,
+            };
+            if let Some(b) = self.inline_box(index) {
+                width += b.width;
+                last_glyph = None;
+                continue;
+            }
+            if grapheme.chars().count() > 1 {
+                // emoji sequences are only as wide as the glyphs they are shaped into
+                let glyphs = self.shape_run(index..index + grapheme.len(), false, false);
+                width += self.arrange(&glyphs, false, &mut last_glyph).1;
+                continue;
+            }
+            self.set_style_at(index);
+            // tabs depend on where they are on the line, so are measured as spaces
+            let (face, shaped) = self.shape(if base == '\t' { ' ' } else { base });
+            width += self.kerning(last_glyph, face, &shaped) + shaped.advance;
+            last_glyph = Some((face, shaped.glyph.id()));
+        }
+        width
+    }
+
+    /// Length of a range of text along a line in the writing mode of the layout
+    fn line_length(&mut self, options: &LayoutOptions, range: Range<usize>) -> f32 {
+        match options.writing_mode {
+            WritingMode::Horizontal => self.measure(range),
+            WritingMode::VerticalRl => self.column_length(range),
+        }
+    }
+
+    /// Length of a range of text set in a single column, ignoring ligatures between graphemes
+    fn column_length(&mut self, range: Range<usize>) -> f32 {
+        let text = self.text;
+        let graphemes = column_graphemes(text, range);
+        let mut length = 0.0;
+        let mut next = 0;
+        while next < graphemes.len() {
+            let (index, grapheme) = graphemes[next];
+            if let Some(b) = self.inline_box(index) {
+                length += b.height;
+                next += 1;
+                continue;
+            }
+            self.set_style_at(index);
+            let (size, _, _) = self.vertical_metrics();
+            if grapheme.chars().next().map_or(true, vertical::is_upright) {
+                length += size;
+                next += 1;
+                continue;
+            }
+            let run_end = self.sideways_run_end(&graphemes, next);
+            let end = graphemes[run_end - 1].0 + graphemes[run_end - 1].1.len();
+            length += if vertical::is_tate_chu_yoko(&text[index..end]) {
+                size
+            } else {
+                self.measure(index..end)
+            };
+            next = run_end;
+        }
+        length
+    }
+
+    /// Breaks a line into lines that fit the layout width, or columns that fit its height, and
+    /// adds them to lines, each with if it ends in a hyphen. Spaces at the end of a line are left
+    /// out of it. A word that is too long for a line of its own overflows.
+    fn wrap(
+        &mut self,
+        options: &LayoutOptions,
+        line: Range<usize>,
+        lines: &mut Vec<(Range<usize>, bool)>,
+    ) {
+        let all = self.text;
+        let text = &all[line.clone()];
+        let trimmed = |range: Range<usize>| {
+            line.start + range.start..line.start + range.start + text[range].trim_end().len()
+        };
+        self.set_style_at(line.start);
+        let hyphen_width = self.shape('-').1.advance;
+        let hyphenator = self.styles[self.current].hyphenator.clone();
+        let mut breaks = linebreak::opportunities(text, hyphenator.as_ref().map(|h| &**h));
+        breaks.push(linebreak::Break {
+            index: text.len(),
+            hyphen: false,
+        });
+        let limit = match options.writing_mode {
+            WritingMode::Horizontal => options.width,
+            WritingMode::VerticalRl => options.height.unwrap_or(::std::f32::INFINITY),
+        };
+        let mut start = 0;
+        // the last break the line can end at, and the width of the line up to it
+        let mut fit: Option<linebreak::Break> = None;
+        let mut width = 0.0;
+        let mut i = 0;
+        while i < breaks.len() {
+            let b = breaks[i];
+            let from = fit.map_or(start, |f| f.index);
+            let extra = if b.hyphen { hyphen_width } else { 0.0 };
+            let content =
+                line.start + from..line.start + from + text[from..b.index].trim_end().len();
+            let fits = width + self.line_length(options, content) + extra <= limit;
+            match fit {
+                Some(f) if !fits => {
+                    lines.push((trimmed(start..f.index), f.hyphen));
+                    start = f.index;
+                    fit = None;
+                    width = 0.0;
+                }
+                _ => {
+                    width += self.line_length(options, line.start + from..line.start + b.index);
+                    fit = Some(b);
+                    i += 1;
+                }
+            }
+        }
+        lines.push((trimmed(start..text.len()), false));
+    }
+
+    /// Shortens a horizontal line that is wider than the layout according to the overflow mode,
+    /// and returns its new width. Whole clusters are removed so it is cut at grapheme boundaries.
+    /// cut_off is the end of the line when the lines after it were cut off by max_lines, which
+    /// puts an ellipsis at its end even if it fits.
+    fn truncate(
+        &mut self,
+        options: &LayoutOptions,
+        first_glyph: usize,
+        first_cluster: usize,
+        width: f32,
+        rtl: bool,
+        baseline: f32,
+        cut_off: Option<usize>,
+    ) -> f32 {
+        let overflow = match (options.overflow, cut_off) {
+            (Overflow::Visible, _) => return width,
+            (Overflow::Clip, _) if width <= options.width => return width,
+            (Overflow::MiddleEllipsis, Some(_)) => Overflow::Ellipsis,
+            (_, None) if width <= options.width => return width,
+            (overflow, _) => overflow,
+        };
+        let mut ellipsis = match overflow {
+            Overflow::Clip => None,
+            _ => Some(self.shape('…')),
+        };
+        let ellipsis_width = ellipsis.as_ref().map_or(0.0, |&(_, ref s)| s.advance);
+        let available = (options.width - ellipsis_width).max(0.0);
+        // how much is kept at the left and right edges. The end of a right to left line is on
+        // the left.
+        let (keep_left, keep_right) = match (overflow, rtl) {
+            (Overflow::MiddleEllipsis, _) => (available / 2.0, available / 2.0),
+            (_, false) => (available, 0.0),
+            (_, true) => (0.0, available),
+        };
+        let clusters = self.clusters.split_off(first_cluster);
+        let glyphs = self.glyphs.split_off(first_glyph);
+        let left_end = clusters
+            .iter()
+            .position(|c| c.pos + c.advance > keep_left)
+            .unwrap_or(clusters.len());
+        let right_start = clusters
+            .iter()
+            .position(|c| c.pos >= width - keep_right)
+            .unwrap_or(clusters.len())
+            .max(left_end);
+        let gap_start = clusters[..left_end]
+            .last()
+            .map_or(0.0, |c| c.pos + c.advance);
+        let gap_end = clusters.get(right_start).map_or(width, |c| c.pos);
+        let shift = gap_start + ellipsis_width - gap_end;
+        // the ellipsis stands for the removed text
+        let removed = &clusters[left_end..right_start];
+        let start = removed.iter().map(|c| c.range.start).min();
+        let end = removed.iter().map(|c| c.range.end).max();
+        let removed = match (start, end, cut_off) {
+            (Some(start), Some(end), _) => start..end,
+            (_, _, Some(end)) => end..end,
+            _ => 0..0,
+        };
+        let mut glyphs = glyphs.into_iter().peekable();
+        for (i, cluster) in clusters.into_iter().enumerate() {
+            if i == left_end {
+                if let Some((face, shaped)) = ellipsis.take() {
+                    let position = point(gap_start + shaped.offset, baseline);
+                    self.push_glyph(face, shaped.glyph, position, None);
+                    self.push_cluster(removed.clone(), rtl, gap_start, ellipsis_width);
+                }
+            }
+            let kept = i < left_end || i >= right_start;
+            let offset = if i >= right_start { shift } else { 0.0 };
+            while glyphs
+                .peek()
+                .map_or(false, |g| g.cluster == first_cluster + i)
+            {
+                let mut g = glyphs.next().unwrap();
+                if kept {
+                    g.cluster = self.clusters.len();
+                    shift_glyph(&mut g, offset);
+                    self.glyphs.push(g);
+                }
+            }
+            if kept {
+                self.clusters.push(Cluster {
+                    pos: cluster.pos + offset,
+                    ..cluster
+                });
+            }
+        }
+        if let Some((face, shaped)) = ellipsis {
+            // everything fit before the end of a line that was cut off
+            let position = point(gap_start + shaped.offset, baseline);
+            self.push_glyph(face, shaped.glyph, position, None);
+            self.push_cluster(removed, rtl, gap_start, ellipsis_width);
+        }
+        width + shift
+    }
+
+    /// Returns the first tab stop after a position along the line
+    fn tab_stop(&self, options: &LayoutOptions, pos: f32) -> f32 {
+        if let Some(&stop) = options.tab_stops.iter().find(|&&stop| stop > pos) {
+            return stop;
+        }
+        let interval = options
+            .tab_interval
+            .unwrap_or_else(|| TAB_SPACES * self.primary().shaper.glyph(' ').advance);
+        if interval <= 0.0 {
+            return pos;
+        }
+        let last = options.tab_stops.last().cloned().unwrap_or(0.0);
+        last + ((pos - last) / interval).floor() * interval + interval
+    }
+
+    /// Adds a faint marker for a space or tab, centered in the space it takes up
+    fn push_whitespace(
+        &mut self,
+        marker: char,
+        pos: f32,
+        advance: f32,
+        baseline: f32,
+        rotate_about: Option<Point<f32>>,
+    ) {
+        let (face, shaped) = self.shape(marker);
+        let x = pos + (advance - shaped.advance) / 2.0 + shaped.offset;
+        let first = self.glyphs.len();
+        self.push_glyph(face, shaped.glyph, point(x, baseline), rotate_about);
+        for g in &mut self.glyphs[first..] {
+            g.color[3] *= WHITESPACE_ALPHA;
+        }
+    }
+
+    /// Kerning between the previous glyph and the next, which only applies to glyphs from the
+    /// same face
+    fn kerning(&self, last: Option<(usize, GlyphId)>, face: usize, next: &ShapedGlyph) -> f32 {
+        match last {
+            Some((last_face, id)) if last_face == face && !next.tabular => {
+                self.faces[face].shaper.kerning(id, next.glyph.id())
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn push_cluster(&mut self, range: Range<usize>, rtl: bool, pos: f32, advance: f32) {
+        self.clusters.push(Cluster {
+            range: range,
+            rtl: rtl,
+            pos: pos,
+            advance: advance,
+        });
+    }
+
+    fn horizontal(&mut self, options: &LayoutOptions) {
+        let text = self.text;
+        let mut top = 0.0;
+        let bidi_info = BidiInfo::new(text, None);
+        let mut lines = Vec::new();
+        for line in hard_lines(text) {
+            if options.wrap {
+                self.wrap(options, line, &mut lines);
+            } else {
+                lines.push((line, false));
+            }
+        }
+        let clamped = options.max_lines.map_or(false, |max| lines.len() > max);
+        if let Some(max) = options.max_lines {
+            lines.truncate(max);
+        }
+        let line_count = lines.len();
+        for (line_number, (line, hyphen)) in lines.into_iter().enumerate() {
+            let para = bidi_info
+                .paragraphs
+                .iter()
+                .position(|p| p.range.start <= line.start && line.start < p.range.end);
+            let (ascent, descent, gap) = self.line_metrics(&line);
+            let baseline = top + ascent;
+            let first_glyph = self.glyphs.len();
+            let first_cluster = self.clusters.len();
+            let mut caret = point(0.0, baseline);
+            let mut last_glyph = None;
+            let runs = match para {
+                Some(i) if !line.is_empty() => bidi::visual_runs(&bidi_info, i, line.clone()),
+                _ => Vec::new(),
+            };
+            for run in runs {
+                let placement = Placement::Horizontal(baseline);
+                let pen = &mut caret.x;
+                self.push_run(options, run.range, run.rtl, placement, pen, &mut last_glyph);
+            }
+            // the hyphen and ellipsis are in the style of the end of the line
+            self.set_style_at(line.end.max(1) - 1);
+            if hyphen {
+                let (face, shaped) = self.shape('-');
+                let advance = shaped.advance;
+                let position = point(caret.x + shaped.offset, caret.y);
+                self.push_glyph(face, shaped.glyph, position, None);
+                self.push_cluster(line.end..line.end, false, caret.x, advance);
+                caret.x += advance;
+            }
+            let rtl = para.map_or(false, |i| bidi_info.paragraphs[i].level.is_rtl());
+            let cut_off = clamped && line_number + 1 == line_count;
+            let width = self.truncate(
+                options,
+                first_glyph,
+                first_cluster,
+                caret.x,
+                rtl,
+                baseline,
+                if cut_off { Some(line.end) } else { None },
+            );
+            if rtl {
+                // right to left paragraphs are aligned to the right edge
+                let offset = options.width - width;
+                for g in &mut self.glyphs[first_glyph..] {
+                    shift_glyph(g, offset);
+                }
+                for c in &mut self.clusters[first_cluster..] {
+                    c.pos += offset;
+                }
+            }
+            let bottom = baseline + descent + gap;
+            self.lines.push(Line {
+                range: line,
+                clusters: first_cluster..self.clusters.len(),
+                baseline: baseline,
+                extent: top..bottom,
+                rtl: rtl,
+            });
+            top = bottom;
+        }
+    }
+
+    /// Size of an em in the current style, the ascent of upright glyphs within it, and the
+    /// baseline of sideways glyphs relative to the column center
+    fn vertical_metrics(&self) -> (f32, f32, f32) {
+        let size = self.primary().shaper.scale().y;
+        let v_metrics = self.styles[self.current].v_metrics;
+        // upright glyphs each take up an em square, with the baseline at the same place in it as
+        // in horizontal text
+        let em_ascent = size * v_metrics.ascent / (v_metrics.ascent - v_metrics.descent);
+        // sideways glyphs are centered on the column
+        let sideways_baseline = (v_metrics.ascent + v_metrics.descent) / 2.0;
+        (size, em_ascent, sideways_baseline)
+    }
+
+    /// Index in graphemes of the end of the run of sideways text that starts at next
+    fn sideways_run_end(&self, graphemes: &[(usize, &str)], next: usize) -> usize {
+        graphemes[next..]
+            .iter()
+            .position(|&(i, g)| {
+                self.inline_box(i).is_some() || g.chars().next().map_or(true, vertical::is_upright)
+            })
+            .map_or(graphemes.len(), |n| next + n)
+    }
+
+    /// Length a character that isn't in the text takes up in a column, as a hyphen or ellipsis
+    /// added by push_column_mark
+    fn column_mark_length(&self, c: char) -> f32 {
+        if self.display_form(c, false, true) != c {
+            self.vertical_metrics().0
+        } else {
+            self.shape(c).1.advance
+        }
+    }
+
+    /// Adds a character that isn't in the text to a column at the pen, upright in its vertical
+    /// form if a face has one and turned sideways otherwise, as a cluster for a range of text
+    fn push_column_mark(&mut self, c: char, center: f32, pen: f32, range: Range<usize>) {
+        let (size, em_ascent, _) = self.vertical_metrics();
+        let upright = self.display_form(c, false, true);
+        if upright != c {
+            let (face, shaped) = self.shape(upright);
+            let x = center - shaped.advance / 2.0 + shaped.offset;
+            self.push_glyph(face, shaped.glyph, point(x, pen + em_ascent), None);
+            self.push_cluster(range, false, pen, size);
+        } else {
+            let (face, shaped) = self.shape(c);
+            let advance = shaped.advance;
+            let placement = Placement::Sideways(center);
+            let (position, pivot) = self.place(placement, pen, pen + shaped.offset);
+            self.push_glyph(face, shaped.glyph, position, pivot);
+            self.push_cluster(range, false, pen, advance);
+        }
+    }
+
+    /// Ends a column that lines after it were cut off from with an ellipsis, removing clusters
+    /// from its end until the ellipsis fits in the height of the layout
+    fn ellipsize_column(
+        &mut self,
+        options: &LayoutOptions,
+        first_cluster: usize,
+        center: f32,
+        cut_off: usize,
+    ) {
+        let length = self.column_mark_length('…');
+        let available = options.height.map_or(::std::f32::INFINITY, |h| h - length);
+        let kept = self.clusters[first_cluster..]
+            .iter()
+            .position(|c| c.pos + c.advance > available)
+            .map_or(self.clusters.len(), |n| first_cluster + n);
+        // the ellipsis stands for the removed text
+        let removed = self.clusters[kept..]
+            .iter()
+            .fold(cut_off..cut_off, |r, c| r.start.min(c.range.start)..r.end);
+        self.clusters.truncate(kept);
+        self.glyphs.retain(|g| g.cluster < kept);
+        let pen = self.clusters[first_cluster..]
+            .last()
+            .map_or(0.0, |c| c.pos + c.advance);
+        self.push_column_mark('…', center, pen, removed);
+    }
+
+    fn vertical(&mut self, options: &LayoutOptions) {
+        let text = self.text;
+        let mut right = options.width;
+        let mut lines = Vec::new();
+        for line in hard_lines(text) {
+            if options.wrap && options.height.is_some() {
+                self.wrap(options, line, &mut lines);
+            } else {
+                lines.push((line, false));
+            }
+        }
+        let clamped = options.max_lines.map_or(false, |max| lines.len() > max);
+        if let Some(max) = options.max_lines {
+            lines.truncate(max);
+        }
+        let line_count = lines.len();
+        for (line_number, (range, hyphen)) in lines.into_iter().enumerate() {
+            let (ascent, descent, gap) = self.line_metrics(&range);
+            let center = right - (ascent + descent + gap) / 2.0;
+            let first_cluster = self.clusters.len();
+            let mut pen = 0.0;
+            let graphemes = column_graphemes(text, range.clone());
+            let mut next = 0;
+            while next < graphemes.len() {
+                let (index, grapheme) = graphemes[next];
+                self.set_style_at(index);
+                let (size, em_ascent, _) = self.vertical_metrics();
+                if let Some(b) = self.inline_box(index) {
+                    // boxes stay upright
+                    next += 1;
+                    self.push_cluster(index..index + grapheme.len(), false, pen, b.height);
+                    pen += b.height;
+                    continue;
+                }
+                let base = grapheme.chars().next().unwrap_or(' ');
+                if vertical::is_upright(base) {
+                    next += 1;
+                    let range = index..index + grapheme.len();
+                    let glyphs = self.shape_run(range.clone(), false, true);
+                    self.set_style_at(index);
+                    let (placed, advance) = self.arrange(&glyphs, false, &mut None);
+                    let x = center - advance / 2.0;
+                    for (face, shaped, glyph_x) in placed {
+                        let position = point(x + glyph_x + shaped.offset, pen + em_ascent);
+                        self.push_glyph(face, shaped.glyph, position, None);
+                    }
+                    if options.show_whitespace && is_space(base) {
+                        self.push_whitespace('·', x, advance, pen + em_ascent, None);
+                    }
+                    self.push_cluster(range, false, pen, size);
+                    pen += size;
+                    continue;
+                }
+                // a run of text that isn't upright
+                let run_end = self.sideways_run_end(&graphemes, next);
+                let end = graphemes[run_end - 1].0 + graphemes[run_end - 1].1.len();
+                let run = &text[index..end];
+                if vertical::is_tate_chu_yoko(run) {
+                    // set upright and side by side in a single em
+                    let shaped: Vec<_> = run.chars().map(|c| self.shape(c)).collect();
+                    let width: f32 = shaped.iter().map(|&(_, ref s)| s.advance).sum();
+                    let mut x = center - width / 2.0;
+                    for (face, s) in shaped {
+                        let advance = s.advance;
+                        let position = point(x + s.offset, pen + em_ascent);
+                        self.push_glyph(face, s.glyph, position, None);
+                        x += advance;
+                    }
+                    self.push_cluster(index..end, false, pen, size);
+                    pen += size;
+                } else {
+                    // turned sideways, the run is laid out as horizontal text starting at the
+                    // pen, and each cluster is rotated around its start
+                    let placement = Placement::Sideways(center);
+                    let last_glyph = &mut None;
+                    self.push_run(options, index..end, false, placement, &mut pen, last_glyph);
+                }
+                next = run_end;
+            }
+            // the hyphen and ellipsis are in the style of the end of the column
+            self.set_style_at(range.end.max(1) - 1);
+            if hyphen {
+                let end = range.end..range.end;
+                self.push_column_mark('-', center, pen, end);
+            }
+            let cut_off = clamped && line_number + 1 == line_count;
+            match options.overflow {
+                Overflow::Ellipsis | Overflow::MiddleEllipsis if cut_off => {
+                    self.ellipsize_column(options, first_cluster, center, range.end)
+                }
+                _ => (),
+            }
+            self.lines.push(Line {
+                range: range,
+                clusters: first_cluster..self.clusters.len(),
+                baseline: center,
+                extent: right - (ascent + descent + gap)..right,
+                rtl: false,
+            });
+            right -= ascent + descent + gap;
+        }
+    }
+}
+
+/// Moves a glyph along the line
+fn shift_glyph(g: &mut LayoutGlyph, offset: f32) {
+    let position = g.glyph.position();
+    g.glyph = g
+        .glyph
+        .clone()
+        .into_unpositioned()
+        .positioned(point(position.x + offset, position.y));
+}
+
+/// Splits text into lines at line and paragraph separators. A CR LF pair is a single separator.
+pub fn hard_lines(text: &str) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\r' => {
+                if let Some(&(_, '\n')) = chars.peek() {
+                    chars.next();
+                }
+            }
+            '\n' | '\u{85}' | '\u{2028}' | '\u{2029}' => (),
+            _ => continue,
+        }
+        lines.push(start..i);
+        start = match chars.peek() {
+            Some(&(next, _)) => next,
+            None => text.len(),
+        };
+    }
+    lines.push(start..text.len());
+    lines
+}
+
+/// Graphemes of a range of text that take up space in a column, with their indices in the text
+fn column_graphemes(text: &str, range: Range<usize>) -> Vec<(usize, &str)> {
+    text[range.clone()]
+        .grapheme_indices(true)
+        .map(|(index, g)| (index + range.start, g))
+        .filter(|&(_, g)| g == "\t" || g.chars().any(|c| !c.is_control()))
+        .collect()
+}
+
+/// Returns if a character is a space that is marked when whitespace is shown
+fn is_space(c: char) -> bool {
+    c == ' ' || c == '\u{3000}'
+}
+
+/// Groups the glyphs of a range of text into the graphemes they were shaped from, in logical
+/// order. Graphemes that a ligature covers are merged into one cluster, and graphemes of only
+/// control characters are left out.
+fn group_clusters(
+    text: &str,
+    range: Range<usize>,
+    glyphs: Vec<(usize, GlyphInfo)>,
+) -> Vec<(Range<usize>, Vec<(usize, GlyphInfo)>)> {
+    let mut clusters: Vec<(Range<usize>, Vec<(usize, GlyphInfo)>)> = text[range.clone()]
+        .grapheme_indices(true)
+        .filter(|&(_, g)| g.chars().any(|c| !c.is_control()))
+        .map(|(i, g)| (range.start + i..range.start + i + g.len(), Vec::new()))
+        .collect();
+    for &(_, ref g) in &glyphs {
+        let first = clusters.iter().position(|c| c.0.end > g.range.start);
+        let last = clusters.iter().rposition(|c| c.0.start < g.range.end);
+        if let (Some(first), Some(last)) = (first, last) {
+            if last > first {
+                let end = clusters[last].0.end;
+                clusters.drain(first + 1..last + 1);
+                clusters[first].0.end = end;
+            }
+        }
+    }
+    for (face, g) in glyphs {
+        let start = g.range.start;
+        if let Some(c) = clusters
+            .iter_mut()
+            .find(|c| c.0.start <= start && start < c.0.end)
+        {
+            c.1.push((face, g));
+        }
+    }
+    clusters
+}
+
+impl Cluster {
+    /// Text index of the caret at the left (or top) edge of the cluster
+    fn left_index(&self) -> usize {
+        if self.rtl {
+            self.range.end
+        } else {
+            self.range.start
+        }
+    }
+
+    /// Text index of the caret at the right (or bottom) edge of the cluster
+    fn right_index(&self) -> usize {
+        if self.rtl {
+            self.range.start
+        } else {
+            self.range.end
+        }
+    }
+}
+
+impl<'font> Layout<'font> {
+    /// A layout of text that has no glyphs or lines, because there are no fonts to shape it with
+    fn empty(text: &str, options: &LayoutOptions) -> Layout<'font> {
+        Layout {
+            text: text.to_string(),
+            glyphs: Vec::new(),
+            clusters: Vec::new(),
+            lines: Vec::new(),
+            boxes: Vec::new(),
+            links: Vec::new(),
+            width: options.width,
+            writing_mode: options.writing_mode,
+        }
+    }
+
+    /// Adds the lines of another horizontal layout after a line break, moved down by a distance.
+    /// Text indices of the other layout are moved past the end of this one.
+    pub fn append(&mut self, other: Layout<'font>, dy: f32) {
+        self.append_at(other, vector(0.0, dy));
+    }
+
+    /// Adds the lines of another horizontal layout after a line break, moved by an offset, as for
+    /// an indented block
+    pub fn append_at(&mut self, other: Layout<'font>, offset: Vector<f32>) {
+        let (dx, dy) = (offset.x, offset.y);
+        if !self.text.is_empty() || !self.lines.is_empty() {
+            self.text.push('\n');
+        }
+        let text_offset = self.text.len();
+        let cluster_offset = self.clusters.len();
+        let shift = |range: Range<usize>| range.start + text_offset..range.end + text_offset;
+        self.text.push_str(&other.text);
+        self.glyphs.extend(other.glyphs.into_iter().map(|mut g| {
+            let position = g.glyph.position();
+            g.glyph = g
+                .glyph
+                .into_unpositioned()
+                .positioned(point(position.x + dx, position.y + dy));
+            g.rotate_about = g.rotate_about.map(|p| point(p.x + dx, p.y + dy));
+            g.cluster += cluster_offset;
+            g
+        }));
+        self.clusters
+            .extend(other.clusters.into_iter().map(|c| Cluster {
+                range: shift(c.range),
+                pos: c.pos + dx,
+                ..c
+            }));
+        self.lines.extend(other.lines.into_iter().map(|l| Line {
+            range: shift(l.range),
+            clusters: l.clusters.start + cluster_offset..l.clusters.end + cluster_offset,
+            baseline: l.baseline + dy,
+            extent: l.extent.start + dy..l.extent.end + dy,
+            ..l
+        }));
+        self.boxes
+            .extend(other.boxes.into_iter().map(|b| PlacedBox {
+                id: b.id,
+                rect: Rect {
+                    min: point(b.rect.min.x + dx, b.rect.min.y + dy),
+                    max: point(b.rect.max.x + dx, b.rect.max.y + dy),
+                },
+            }));
+        self.links.extend(
+            other
+                .links
+                .into_iter()
+                .map(|(range, link)| (shift(range), link)),
+        );
+    }
+
+    /// Splits a point into its position along a line and across lines
+    fn along_and_across(&self, p: Point<f32>) -> (f32, f32) {
+        match self.writing_mode {
+            WritingMode::Horizontal => (p.x, p.y),
+            WritingMode::VerticalRl => (p.y, p.x),
+        }
+    }
+
+    fn line_at(&self, across: f32) -> Option<&Line> {
+        let line = match self.writing_mode {
+            WritingMode::Horizontal => self.lines.iter().find(|l| across < l.extent.end),
+            WritingMode::VerticalRl => self.lines.iter().find(|l| across >= l.extent.start),
+        };
+        line.or(self.lines.last())
+    }
+
+    /// Returns the index in Layout::lines of the line a text index is on. Spaces that a line was
+    /// wrapped at are left out of its range, so indices inside them snap to the line before.
+    fn line_index_for(&self, index: usize) -> Option<usize> {
+        if index > self.text.len() {
+            return None;
+        }
+        self.lines
+            .iter()
+            .rposition(|line| index >= line.range.start)
+    }
+
+    /// Returns the caret stops of the line a text index is on, with the position of the index in
+    /// them. An index in the spaces after a wrapped line is at the end of the line, and an index
+    /// that isn't a caret stop is where the line starts.
+    fn caret_stop_for(&self, line: &Line, index: usize) -> (Vec<(usize, f32)>, usize) {
+        let stops = self.caret_stops(line);
+        let last = stops.len() - 1;
+        let current = if index > line.range.end {
+            if line.rtl {
+                0
+            } else {
+                last
+            }
+        } else {
+            match stops.iter().position(|&(i, _)| i == index) {
+                Some(current) => current,
+                None if line.rtl => last,
+                None => 0,
+            }
+        };
+        (stops, current)
+    }
+
+    /// Caret stops of a line from left to right (or top to bottom), as text indices and positions
+    /// along the line
+    fn caret_stops(&self, line: &Line) -> Vec<(usize, f32)> {
+        let clusters = &self.clusters[line.clusters.clone()];
+        let mut stops: Vec<(usize, f32)> = Vec::with_capacity(clusters.len() + 1);
+        for (k, c) in clusters.iter().enumerate() {
+            let index = match k.checked_sub(1).map(|k| &clusters[k]) {
+                // where runs of different directions meet, the stop is the edge of the run that
+                // goes against the line, so each end of the run gets a stop
+                Some(b) if b.rtl != c.rtl && b.rtl != line.rtl => b.right_index(),
+                _ => c.left_index(),
+            };
+            stops.push((index, c.pos));
+        }
+        match clusters.last() {
+            Some(c) => stops.push((c.right_index(), c.pos + c.advance)),
+            None if line.rtl => stops.push((line.range.start, self.width)),
+            None => stops.push((line.range.start, 0.0)),
+        }
+        stops
+    }
+
+    /// Returns the index in Layout::clusters of the cluster under a point
+    pub fn cluster_at(&self, p: Point<f32>) -> Option<usize> {
+        let (along, across) = self.along_and_across(p);
+        let line = self.line_at(across)?;
+        line.clusters.clone().find(|&i| {
+            let c = &self.clusters[i];
+            along >= c.pos && along < c.pos + c.advance
+        })
+    }
+
+    /// Returns the center of each cluster on its baseline, or on the center line of its column
+    pub fn cluster_centers(&self) -> Vec<Point<f32>> {
+        let mut centers = vec![point(0.0, 0.0); self.clusters.len()];
+        for line in &self.lines {
+            for i in line.clusters.clone() {
+                let c = &self.clusters[i];
+                let along = c.pos + c.advance / 2.0;
+                centers[i] = match self.writing_mode {
+                    WritingMode::Horizontal => point(along, line.baseline),
+                    WritingMode::VerticalRl => point(line.baseline, along),
+                };
+            }
+        }
+        centers
+    }
+
+    /// Returns the index in Layout::links of the link under a point
+    pub fn link_at(&self, p: Point<f32>) -> Option<usize> {
+        let (_, across) = self.along_and_across(p);
+        let line = self.line_at(across)?;
+        if across < line.extent.start || across >= line.extent.end {
+            return None;
+        }
+        let start = self.clusters[self.cluster_at(p)?].range.start;
+        self.links
+            .iter()
+            .position(|&(ref range, _)| start >= range.start && start < range.end)
+    }
+
+    /// Returns the rects of the underline of a range of text, one for each line it is on, or
+    /// more if bidi reordering splits it up. Vertical text is underlined on the right.
+    pub fn underline_rects(&self, range: Range<usize>) -> Vec<Rect<f32>> {
+        let mut sizes = vec![0.0f32; self.clusters.len()];
+        for g in &self.glyphs {
+            sizes[g.cluster] = sizes[g.cluster].max(g.glyph.scale().y);
+        }
+        let mut rects = Vec::new();
+        for line in &self.lines {
+            // runs of clusters in the range as (start, end, font size) along the line
+            let mut runs: Vec<(f32, f32, f32)> = Vec::new();
+            let mut in_run = false;
+            for i in line.clusters.clone() {
+                let c = &self.clusters[i];
+                if c.range.start < range.start || c.range.start >= range.end {
+                    in_run = false;
+                    continue;
+                }
+                match runs.last_mut() {
+                    Some(run) if in_run => {
+                        run.1 = c.pos + c.advance;
+                        run.2 = run.2.max(sizes[i]);
+                    }
+                    _ => runs.push((c.pos, c.pos + c.advance, sizes[i])),
+                }
+                in_run = true;
+            }
+            for (start, end, size) in runs {
+                let thickness = (size / 14.0).max(1.0);
+                rects.push(match self.writing_mode {
+                    WritingMode::Horizontal => {
+                        let top = line.baseline + size * 0.1;
+                        Rect {
+                            min: point(start, top),
+                            max: point(end, top + thickness),
+                        }
+                    }
+                    WritingMode::VerticalRl => {
+                        let left = line.baseline + size * 0.55;
+                        Rect {
+                            min: point(left, start),
+                            max: point(left + thickness, end),
+                        }
+                    }
+                });
+            }
+        }
+        rects
+    }
+
+    /// Returns the text index of the caret position closest to a point. This is always one of
+    /// the caret stops of the line, so the caret is drawn where the point is.
+    pub fn hit_test(&self, p: Point<f32>) -> Option<usize> {
+        let (along, across) = self.along_and_across(p);
+        let line = self.line_at(across)?;
+        let stops = self.caret_stops(line);
+        let k = self.clusters[line.clusters.clone()]
+            .iter()
+            .position(|c| along < c.pos + c.advance / 2.0)
+            .unwrap_or(stops.len() - 1);
+        Some(stops[k].0)
+    }
+
+    /// Returns the point where the caret for a text index is drawn. For horizontal text it is on
+    /// the baseline, and for vertical text it is on the center of the column.
+    pub fn caret_position(&self, index: usize) -> Option<Point<f32>> {
+        let line = &self.lines[self.line_index_for(index)?];
+        let (stops, current) = self.caret_stop_for(line, index);
+        let pos = stops[current].1;
+        Some(match self.writing_mode {
+            WritingMode::Horizontal => point(pos, line.baseline),
+            WritingMode::VerticalRl => point(line.baseline, pos),
+        })
+    }
+
+    /// Moves the caret one position to the left or right in visual order, and returns the new
+    /// text index. In vertical text right moves down the column.
+    pub fn move_caret(&self, index: usize, right: bool) -> usize {
+        let line_index = match self.line_index_for(index) {
+            Some(i) => i,
+            None => return index,
+        };
+        let (stops, current) = self.caret_stop_for(&self.lines[line_index], index);
+        if right {
+            if let Some(k) = (current + 1..stops.len()).find(|&k| first_stop(&stops, k)) {
+                return stops[k].0;
+            }
+            match self.lines.get(line_index + 1) {
+                Some(next) => self.caret_stops(next)[0].0,
+                None => index,
+            }
+        } else {
+            if let Some(k) = (0..current).rev().find(|&k| first_stop(&stops, k)) {
+                return stops[k].0;
+            }
+            if line_index == 0 {
+                return index;
+            }
+            let previous = self.caret_stops(&self.lines[line_index - 1]);
+            let k = (0..previous.len())
+                .rev()
+                .find(|&k| first_stop(&previous, k))
+                .unwrap();
+            previous[k].0
+        }
+    }
+}
+
+/// Returns if a caret stop is the first one with its text index. Where bidi runs meet an index
+/// can be a stop more than once, and its caret is drawn at the first, so the caret only moves to
+/// those.
+fn first_stop(stops: &[(usize, f32)], k: usize) -> bool {
+    stops.iter().position(|&(i, _)| i == stops[k].0) == Some(k)
+}
+
+#[cfg(test)]
+mod tests {
+    use text::{FontSet, LayoutOptions, TextStyle};
+
+    use super::*;
+
+    const MONO: &[u8] = include_bytes!("../../fonts/dejavu/DejaVuSansMono.ttf");
+
+    fn layout(text: &str, options: &LayoutOptions) -> Layout<'static> {
+        let mut fonts = FontSet::new();
+        fonts.load("mono", MONO).unwrap();
+        let style = TextStyle::new(20.0).with_family("mono");
+        layout_paragraph(&mut fonts, text, &style, options)
+    }
+
+    /// Text indices the caret goes through when moved one way until it stops
+    fn walk(layout: &Layout, mut index: usize, right: bool) -> Vec<usize> {
+        let mut indices = Vec::new();
+        loop {
+            let next = layout.move_caret(index, right);
+            if next == index || indices.len() > layout.text.len() {
+                return indices;
+            }
+            indices.push(next);
+            index = next;
+        }
+    }
+
+    fn caret_x(layout: &Layout, index: usize) -> f32 {
+        layout.caret_position(index).unwrap().x
+    }
+
+    #[test]
+    fn carets_on_rtl_line() {
+        // shin with qamats, lamed, vav, final mem, then a space and abc
+        let layout = layout(
+            "\u{5e9}\u{5b8}\u{5dc}\u{5d5}\u{5dd} abc",
+            &LayoutOptions::new(400.0),
+        );
+        assert!(layout.lines[0].rtl);
+        assert_eq!(walk(&layout, 0, false), vec![4, 6, 8, 10, 14, 13, 12, 11]);
+        assert_eq!(walk(&layout, 11, true), vec![12, 13, 14, 10, 8, 6, 4, 0]);
+        // inside the first cluster, which isn't a caret stop, so it is where the line starts
+        assert_eq!(caret_x(&layout, 2), caret_x(&layout, 0));
+        assert_eq!(layout.move_caret(2, false), 4);
+        assert_eq!(layout.move_caret(2, true), 2);
+    }
+
+    #[test]
+    fn carets_on_ltr_line() {
+        let layout = layout(
+            "abc \u{5e9}\u{5dc}\u{5d5}\u{5dd} def",
+            &LayoutOptions::new(400.0),
+        );
+        assert!(!layout.lines[0].rtl);
+        let stops = vec![1, 2, 3, 12, 10, 8, 6, 4, 13, 14, 15, 16];
+        assert_eq!(walk(&layout, 0, true), stops);
+        let mut back: Vec<usize> = stops.into_iter().rev().skip(1).collect();
+        back.push(0);
+        assert_eq!(walk(&layout, 16, false), back);
+    }
+
+    #[test]
+    fn hit_test_on_mixed_lines() {
+        for text in &[
+            "\u{5e9}\u{5b8}\u{5dc}\u{5d5}\u{5dd} abc",
+            "abc \u{5e9}\u{5dc}\u{5d5}\u{5dd} def",
+        ] {
+            let layout = layout(text, &LayoutOptions::new(400.0));
+            let baseline = layout.lines[0].baseline;
+            for c in &layout.clusters {
+                let left = layout
+                    .hit_test(point(c.pos + c.advance / 4.0, baseline))
+                    .unwrap();
+                let x = caret_x(&layout, left);
+                assert!((x - c.pos).abs() < 0.01, "{:?} in {:?}", c.range, text);
+                let right = layout
+                    .hit_test(point(c.pos + c.advance * 0.75, baseline))
+                    .unwrap();
+                let x = caret_x(&layout, right);
+                assert!(
+                    (x - c.pos - c.advance).abs() < 0.01,
+                    "{:?} in {:?}",
+                    c.range,
+                    text
+                );
+            }
+        }
+        let layout = layout(
+            "\u{5e9}\u{5b8}\u{5dc}\u{5d5}\u{5dd} abc",
+            &LayoutOptions::new(400.0),
+        );
+        let baseline = layout.lines[0].baseline;
+        assert_eq!(layout.hit_test(point(-10.0, baseline)), Some(11));
+        assert_eq!(layout.hit_test(point(410.0, baseline)), Some(0));
+    }
+
+    /// Advance of every character in the mono font at the test size
+    fn advance() -> f32 {
+        layout("a", &LayoutOptions::new(100.0)).clusters[0].advance
+    }
+
+    /// Byte ranges of the clusters of a layout in visual order
+    fn cluster_ranges(layout: &Layout) -> Vec<Range<usize>> {
+        layout.clusters.iter().map(|c| c.range.clone()).collect()
+    }
+
+    fn ellipsized(text: &str, overflow: Overflow, columns: f32) -> Layout<'static> {
+        let mut options = LayoutOptions::new(advance() * columns);
+        options.overflow = overflow;
+        layout(text, &options)
+    }
+
+    #[test]
+    fn end_ellipsis() {
+        // nine characters and the ellipsis fit in ten and a half columns
+        let layout = ellipsized("abcdefghijklmnop", Overflow::Ellipsis, 10.5);
+        let mut expected: Vec<_> = (0..9).map(|i| i..i + 1).collect();
+        expected.push(9..16);
+        assert_eq!(cluster_ranges(&layout), expected);
+        let ellipsis = &layout.clusters[9];
+        assert!((ellipsis.pos - 9.0 * advance()).abs() < 0.01);
+        assert_eq!(layout.glyphs.len(), 10);
+    }
+
+    #[test]
+    fn middle_ellipsis() {
+        let layout = ellipsized("abcdefghijklmnop", Overflow::MiddleEllipsis, 10.5);
+        let mut expected: Vec<_> = (0..4).map(|i| i..i + 1).collect();
+        expected.push(4..12);
+        expected.extend((12..16).map(|i| i..i + 1));
+        assert_eq!(cluster_ranges(&layout), expected);
+        // the kept end is moved up to the ellipsis
+        assert!((layout.clusters[5].pos - 5.0 * advance()).abs() < 0.01);
+    }
+
+    #[test]
+    fn rtl_ellipsis() {
+        // the end of a right to left line is on the left, so that is where the ellipsis goes
+        let text: String = (0x5d0..0x5e0)
+            .map(|c| ::std::char::from_u32(c).unwrap())
+            .collect();
+        let layout = ellipsized(&text, Overflow::Ellipsis, 10.5);
+        assert!(layout.lines[0].rtl);
+        let mut expected = vec![18..32];
+        expected.extend((0..9).rev().map(|i| i * 2..i * 2 + 2));
+        assert_eq!(cluster_ranges(&layout), expected);
+        // and the line is still aligned to the right edge
+        let last = &layout.clusters[9];
+        assert!((last.pos + last.advance - advance() * 10.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn cut_off_ellipsis() {
+        let mut options = LayoutOptions::new(advance() * 10.5);
+        options.wrap = true;
+        options.max_lines = Some(1);
+        // a middle ellipsis is at the end too when lines are cut off
+        for &overflow in &[Overflow::Ellipsis, Overflow::MiddleEllipsis] {
+            options.overflow = overflow;
+            // the first line fits, but still ends with an ellipsis that stands for nothing on it
+            let fits = layout("abc def ghi", &options);
+            assert_eq!(fits.lines.len(), 1);
+            let mut expected: Vec<_> = (0..7).map(|i| i..i + 1).collect();
+            expected.push(7..7);
+            assert_eq!(cluster_ranges(&fits), expected);
+            // a first line that doesn't fit is shortened, and the ellipsis stands for the end of
+            // it
+            let long = layout("abcdefghijklmnop qr", &options);
+            let mut expected: Vec<_> = (0..9).map(|i| i..i + 1).collect();
+            expected.push(9..16);
+            assert_eq!(cluster_ranges(&long), expected);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Line break opportunities, a simplified form of UAX #14
+
+use text::hyphenation::Hyphenator;
+
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// A place a line can be broken
+#[derive(Clone, Copy)]
+pub struct Break {
+    /// Byte index the next line starts at
+    pub index: usize,
+    /// If a hyphen is drawn at the end of the line when it is broken here
+    pub hyphen: bool,
+}
+
+/// Returns where text can be broken, in order. The start and end of the text are not included.
+pub fn opportunities(text: &str, hyphenator: Option<&Hyphenator>) -> Vec<Break> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut breaks = Vec::new();
+    for w in chars.windows(2) {
+        let ((_, a), (index, b)) = (w[0], w[1]);
+        let allowed = (is_breaking_space(a) && !is_breaking_space(b))
+            || (a == '-' && b.is_alphabetic())
+            || ((is_ideographic(a) || is_ideographic(b))
+                && !no_break_after(a)
+                && !no_break_before(b));
+        if allowed {
+            breaks.push(Break {
+                index: index,
+                hyphen: false,
+            });
+        } else if a == SOFT_HYPHEN {
+            breaks.push(Break {
+                index: index,
+                hyphen: true,
+            });
+        }
+    }
+    if let Some(hyphenator) = hyphenator {
+        for (start, word) in words(text) {
+            breaks.extend(hyphenator.hyphenate(word).into_iter().map(|i| Break {
+                index: start + i,
+                hyphen: true,
+            }));
+        }
+        breaks.sort_by_key(|b| b.index);
+        breaks.dedup_by_key(|b| b.index);
+    }
+    breaks
+}
+
+/// Words that can be hyphenated, which are runs of letters. Words next to a soft hyphen or a
+/// hyphen are already hyphenated by hand.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    text.split(|c: char| !c.is_alphabetic())
+        .map(|word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+        .filter(|&(start, word)| {
+            let before = text[..start].chars().next_back();
+            let after = text[start + word.len()..].chars().next();
+            !word.is_empty()
+                && before != Some(SOFT_HYPHEN)
+                && before != Some('-')
+                && after != Some(SOFT_HYPHEN)
+                && after != Some('-')
+        })
+        .collect()
+}
+
+/// Spaces that lines can be broken after, which excludes no-break spaces
+fn is_breaking_space(c: char) -> bool {
+    c.is_whitespace() && c != '\u{A0}' && c != '\u{2007}' && c != '\u{202F}'
+}
+
+/// Characters that lines can be broken between without a space
+fn is_ideographic(c: char) -> bool {
+    match c as u32 {
+        0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF => true,
+        0x20000..=0x3FFFF => true,
+        _ => false,
+    }
+}
+
+/// Closing punctuation and small kana, which shouldn't start a line
+fn no_break_before(c: char) -> bool {
+    "、。，．：；！？）」』】〕〉》ーゝゞ々ぁぃぅぇぉっゃゅょゎァィゥェォッャュョヮヵヶ".contains(c)
+}
+
+/// Opening punctuation, which shouldn't end a line
+fn no_break_after(c: char) -> bool {
+    "（「『【〔〈《".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaks(text: &str, hyphenator: Option<&Hyphenator>) -> Vec<(usize, bool)> {
+        opportunities(text, hyphenator)
+            .into_iter()
+            .map(|b| (b.index, b.hyphen))
+            .collect()
+    }
+
+    #[test]
+    fn spaces_and_hyphens() {
+        assert_eq!(breaks("a b  c", None), vec![(2, false), (5, false)]);
+        assert_eq!(breaks("well-known", None), vec![(5, false)]);
+        assert_eq!(breaks("a\u{A0}b", None), vec![]);
+    }
+
+    #[test]
+    fn soft_hyphen() {
+        // the break is after the soft hyphen, which takes two bytes
+        assert_eq!(breaks("hy\u{AD}phen", None), vec![(4, true)]);
+    }
+
+    #[test]
+    fn ideographs() {
+        assert_eq!(breaks("漢字。", None), vec![(3, false)]);
+    }
+
+    #[test]
+    fn hyphenated_words() {
+        let h = Hyphenator::new("en", "hy3ph he2n hena4 hen5at 1na n2at 1tio 2io o2n", "");
+        assert_eq!(
+            breaks("hyphenation hy\u{AD}phenation", Some(&h)),
+            vec![(2, true), (6, true), (12, false), (16, true)]
+        );
+    }
+}
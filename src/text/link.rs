@@ -0,0 +1,119 @@
+use rusttype::Point;
+
+use text::color::Color;
+use text::layout::Layout;
+
+/// How the text of a link is drawn, on top of the style of its span
+#[derive(Clone, Copy, PartialEq)]
+pub struct LinkStyle {
+    /// Color the text is drawn in instead of the color of its style
+    pub color: Option<Color>,
+    pub underline: bool,
+}
+
+#[derive(Clone)]
+pub struct Link {
+    /// The URL, or any other payload the application acts on when the link is clicked
+    pub target: String,
+    pub style: LinkStyle,
+    /// Style while the pointer is over the link
+    pub hover_style: LinkStyle,
+}
+
+impl Link {
+    /// A link that is underlined while the pointer is over it
+    pub fn new(target: &str) -> Link {
+        Link {
+            target: target.to_string(),
+            style: LinkStyle {
+                color: None,
+                underline: false,
+            },
+            hover_style: LinkStyle {
+                color: None,
+                underline: true,
+            },
+        }
+    }
+
+    pub fn with_style(mut self, style: LinkStyle) -> Link {
+        self.style = style;
+        self
+    }
+
+    pub fn with_hover_style(mut self, style: LinkStyle) -> Link {
+        self.hover_style = style;
+        self
+    }
+}
+
+/// Something that happened to a link, which is given by its index in Layout::links
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LinkEvent {
+    /// The pointer moved onto the link
+    Enter(usize),
+    /// The pointer moved off the link
+    Leave(usize),
+    /// The link was pressed, and the pointer was released over it
+    Click(usize),
+}
+
+/// Turns pointer input over a layout into link events. Points are in layout coordinates.
+///
+/// A touch is a press followed by a release, and a finger doesn't hover, so for touch input call
+/// pointer_left after released.
+pub struct LinkTracker {
+    /// The link the pointer is over
+    pub hovered: Option<usize>,
+    /// The link the pointer was pressed on, until it is released
+    pressed: Option<usize>,
+}
+
+impl LinkTracker {
+    pub fn new() -> LinkTracker {
+        LinkTracker {
+            hovered: None,
+            pressed: None,
+        }
+    }
+
+    pub fn pointer_moved(&mut self, layout: &Layout, p: Point<f32>) -> Vec<LinkEvent> {
+        self.hover(layout.link_at(p))
+    }
+
+    pub fn pointer_left(&mut self) -> Vec<LinkEvent> {
+        self.pressed = None;
+        self.hover(None)
+    }
+
+    pub fn pressed(&mut self, layout: &Layout, p: Point<f32>) -> Vec<LinkEvent> {
+        let events = self.pointer_moved(layout, p);
+        self.pressed = self.hovered;
+        events
+    }
+
+    pub fn released(&mut self, layout: &Layout, p: Point<f32>) -> Vec<LinkEvent> {
+        let mut events = self.pointer_moved(layout, p);
+        if let Some(link) = self.pressed.take() {
+            if self.hovered == Some(link) {
+                events.push(LinkEvent::Click(link));
+            }
+        }
+        events
+    }
+
+    /// Moves the hover to another link, or off all links
+    fn hover(&mut self, link: Option<usize>) -> Vec<LinkEvent> {
+        let mut events = Vec::new();
+        if link != self.hovered {
+            if let Some(old) = self.hovered {
+                events.push(LinkEvent::Leave(old));
+            }
+            if let Some(new) = link {
+                events.push(LinkEvent::Enter(new));
+            }
+            self.hovered = link;
+        }
+        events
+    }
+}
@@ -0,0 +1,1206 @@
+use rusttype::vector;
+
+use text::code::{CodeView, Grammar, Theme};
+use text::color::Color;
+use text::font::FontSet;
+use text::layout::{self, Layout, LayoutOptions, WritingMode};
+use text::link::{Link, LinkStyle};
+use text::rich::RichText;
+use text::style::TextStyle;
+
+/// Columns between tab stops when tabs are expanded at the start of a line
+const TAB_WIDTH: usize = 4;
+
+/// Markers of the items of bullet lists, by how deeply the list is nested
+const BULLETS: [&str; 3] = ["\u{2022}", "\u{25E6}", "\u{25AA}"];
+
+/// A block of a Markdown document
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Heading {
+        /// 1 to 6
+        level: usize,
+        content: Vec<Inline>,
+    },
+    Paragraph(Vec<Inline>),
+    CodeBlock {
+        /// The info string after the opening fence, which usually names the language
+        info: String,
+        code: String,
+    },
+    Quote(Vec<Block>),
+    List {
+        /// Number of the first item of an ordered list, or None for a bullet list
+        start: Option<usize>,
+        /// If the items are not separated by blank lines, so they are drawn without space between
+        /// their paragraphs
+        tight: bool,
+        items: Vec<Vec<Block>>,
+    },
+    /// A thematic break, such as `---`
+    Rule,
+}
+
+/// Text in a block
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Code(String),
+    Link {
+        target: String,
+        content: Vec<Inline>,
+    },
+    LineBreak,
+}
+
+/// Parses the CommonMark blocks and inlines that help text and release notes use. Link reference
+/// definitions, HTML and tables aren't supported and are read as text, and images are replaced
+/// with their descriptions.
+pub fn parse(text: &str) -> Vec<Block> {
+    let lines: Vec<String> = text.lines().map(expand_tabs).collect();
+    parse_blocks(&lines)
+}
+
+/// Replaces tabs at the start of a line with spaces, which is where they affect the blocks
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    for (i, c) in line.char_indices() {
+        match c {
+            '\t' => {
+                let spaces = TAB_WIDTH - expanded.len() % TAB_WIDTH;
+                for _ in 0..spaces {
+                    expanded.push(' ');
+                }
+            }
+            ' ' => expanded.push(' '),
+            _ => {
+                expanded.push_str(&line[i..]);
+                break;
+            }
+        }
+    }
+    expanded
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Removes up to a number of spaces from the start of a line
+fn strip_indent(line: &str, count: usize) -> &str {
+    &line[indent(line).min(count)..]
+}
+
+fn parse_blocks(lines: &[String]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = &lines[i];
+        let trimmed = line.trim_start();
+        if is_blank(line) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            i += 1;
+            continue;
+        }
+        if indent(line) >= 4 {
+            if !paragraph.is_empty() {
+                // a continuation of the paragraph, not code
+                paragraph.push(trimmed);
+                i += 1;
+                continue;
+            }
+            let mut code = Vec::new();
+            while i < lines.len() && (indent(&lines[i]) >= 4 || is_blank(&lines[i])) {
+                code.push(strip_indent(&lines[i], 4));
+                i += 1;
+            }
+            while code.last().map_or(false, |l| is_blank(l)) {
+                code.pop();
+            }
+            blocks.push(Block::CodeBlock {
+                info: String::new(),
+                code: code.join("\n"),
+            });
+            continue;
+        }
+
+        if let Some((fence, info)) = opening_fence(trimmed) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            let fence_indent = indent(line);
+            let mut code = Vec::new();
+            i += 1;
+            while i < lines.len() {
+                let l = lines[i].trim_start();
+                let closing = indent(&lines[i]) < 4
+                    && l.starts_with(fence.as_str())
+                    && l.trim_start_matches(fence.chars().next().unwrap())
+                        .trim()
+                        .is_empty();
+                i += 1;
+                if closing {
+                    break;
+                }
+                code.push(strip_indent(&lines[i - 1], fence_indent));
+            }
+            blocks.push(Block::CodeBlock {
+                info: info,
+                code: code.join("\n"),
+            });
+        } else if let Some((level, content)) = atx_heading(trimmed) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            blocks.push(Block::Heading {
+                level: level,
+                content: parse_inlines(content),
+            });
+            i += 1;
+        } else if let Some(level) = setext_underline(trimmed).filter(|_| !paragraph.is_empty()) {
+            let content = paragraph.join("\n");
+            paragraph.clear();
+            blocks.push(Block::Heading {
+                level: level,
+                content: parse_inlines(content.trim_end()),
+            });
+            i += 1;
+        } else if is_rule(trimmed) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            blocks.push(Block::Rule);
+            i += 1;
+        } else if trimmed.starts_with('>') {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            let mut quoted = Vec::new();
+            while i < lines.len() {
+                let l = lines[i].trim_start();
+                if indent(&lines[i]) < 4 && l.starts_with('>') {
+                    let l = if l.starts_with("> ") {
+                        &l[2..]
+                    } else {
+                        &l[1..]
+                    };
+                    quoted.push(l.to_string());
+                } else if !is_blank(&lines[i])
+                    && quoted.last().map_or(false, |l: &String| !is_blank(l))
+                    && !starts_block(&lines[i])
+                {
+                    // a lazy continuation of a quoted paragraph
+                    quoted.push(lines[i].clone());
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+            blocks.push(Block::Quote(parse_blocks(&quoted)));
+        } else if let Some(marker) = list_marker(line).filter(|m| {
+            // only lists that start at 1 interrupt a paragraph, so numbers in text aren't lists
+            paragraph.is_empty() || m.start.map_or(true, |n| n == 1) && !m.empty
+        }) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            i = parse_list(lines, i, marker, &mut blocks);
+        } else {
+            paragraph.push(trimmed);
+            i += 1;
+        }
+    }
+    flush_paragraph(&mut paragraph, &mut blocks);
+    blocks
+}
+
+fn flush_paragraph(paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>) {
+    if !paragraph.is_empty() {
+        let text = paragraph.join("\n");
+        blocks.push(Block::Paragraph(parse_inlines(text.trim_end())));
+        paragraph.clear();
+    }
+}
+
+/// The marker of a list item
+#[derive(Clone, Copy)]
+struct ListMarker {
+    /// The bullet character, or the delimiter after the number of an ordered item
+    delimiter: char,
+    start: Option<usize>,
+    /// Indent of the content of the item
+    content_indent: usize,
+    /// If nothing follows the marker on its line
+    empty: bool,
+}
+
+fn list_marker(line: &str) -> Option<ListMarker> {
+    let marker_indent = indent(line);
+    if marker_indent >= 4 {
+        return None;
+    }
+    let rest = &line[marker_indent..];
+    let (delimiter, start, width) = match rest.chars().next()? {
+        c @ '-' | c @ '*' | c @ '+' => (c, None, 1),
+        _ => {
+            let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+            if digits == 0 || digits > 9 {
+                return None;
+            }
+            let delimiter = rest[digits..].chars().next()?;
+            if delimiter != '.' && delimiter != ')' {
+                return None;
+            }
+            (delimiter, rest[..digits].parse().ok(), digits + 1)
+        }
+    };
+    let after = &rest[width..];
+    if !after.is_empty() && !after.starts_with(' ') {
+        return None;
+    }
+    let empty = is_blank(after);
+    let spaces = indent(after);
+    // content that is indented more than 4 past the marker is indented code in the item
+    let spaces = if empty || spaces > 4 { 1 } else { spaces };
+    Some(ListMarker {
+        delimiter: delimiter,
+        start: start,
+        content_indent: marker_indent + width + spaces,
+        empty: empty,
+    })
+}
+
+/// Parses the list that starts with a marker at a line, and returns the line after it
+fn parse_list(lines: &[String], mut i: usize, first: ListMarker, blocks: &mut Vec<Block>) -> usize {
+    let mut items = Vec::new();
+    let mut tight = true;
+    let mut marker = first;
+    loop {
+        let mut item: Vec<String> = vec![lines[i]
+            .get(marker.content_indent..)
+            .unwrap_or("")
+            .to_string()];
+        i += 1;
+        while i < lines.len() {
+            let line = &lines[i];
+            if is_blank(line) {
+                item.push(String::new());
+            } else if indent(line) >= marker.content_indent {
+                item.push(line[marker.content_indent..].to_string());
+            } else if !is_blank(&item[item.len() - 1]) && !starts_block(line) {
+                // a lazy continuation of the paragraph of the item
+                item.push(line.trim_start().to_string());
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        let mut trailing_blank = false;
+        while item.len() > 1 && is_blank(&item[item.len() - 1]) {
+            item.pop();
+            trailing_blank = true;
+        }
+        // blank lines between the blocks of an item make the list loose
+        let blocks = parse_blocks(&item);
+        let inner_blank = item
+            .iter()
+            .enumerate()
+            .any(|(n, l)| n > 0 && is_blank(l) && !is_blank(&item[n - 1]));
+        if inner_blank && blocks.len() > 1 {
+            tight = false;
+        }
+        items.push(blocks);
+
+        match lines.get(i).and_then(|l| list_marker(l)) {
+            Some(next)
+                if next.delimiter == first.delimiter
+                    && next.start.is_some() == first.start.is_some() =>
+            {
+                if trailing_blank {
+                    tight = false;
+                }
+                marker = next;
+            }
+            _ => break,
+        }
+    }
+    blocks.push(Block::List {
+        start: first.start,
+        tight: tight,
+        items: items,
+    });
+    i
+}
+
+/// If a line starts a block that ends a paragraph, and so can't continue one lazily
+fn starts_block(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    indent(line) < 4
+        && (opening_fence(trimmed).is_some()
+            || atx_heading(trimmed).is_some()
+            || is_rule(trimmed)
+            || trimmed.starts_with('>')
+            || list_marker(line).map_or(false, |m| !m.empty))
+}
+
+/// Returns the fence and info string of the opening fence of a code block
+fn opening_fence(line: &str) -> Option<(String, String)> {
+    let c = line.chars().next()?;
+    if c != '`' && c != '~' {
+        return None;
+    }
+    let len = line.len() - line.trim_start_matches(c).len();
+    let info = line[len..].trim();
+    if len < 3 || c == '`' && info.contains('`') {
+        return None;
+    }
+    Some((line[..len].to_string(), info.to_string()))
+}
+
+/// Returns the level and content of a heading such as `## Changes`
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+    if level == 0 || level > 6 || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    // a closing sequence of #s is removed if there is a space before it
+    let content = rest.trim();
+    let without_closing = content.trim_end_matches('#');
+    let content = if without_closing.is_empty() || without_closing.ends_with(' ') {
+        without_closing.trim_end()
+    } else {
+        content
+    };
+    Some((level, content))
+}
+
+/// Returns the level of the heading a line of `=` or `-` makes of the paragraph before it
+fn setext_underline(line: &str) -> Option<usize> {
+    let line = line.trim_end();
+    match line.chars().next()? {
+        '=' if line.chars().all(|c| c == '=') => Some(1),
+        '-' if line.chars().all(|c| c == '-') => Some(2),
+        _ => None,
+    }
+}
+
+/// If a line is a thematic break, which is three or more `-`, `*` or `_` and optional spaces
+fn is_rule(line: &str) -> bool {
+    let c = match line.chars().next() {
+        Some(c @ '-') | Some(c @ '*') | Some(c @ '_') => c,
+        _ => return false,
+    };
+    line.chars().all(|l| l == c || l == ' ') && line.chars().filter(|&l| l == c).count() >= 3
+}
+
+/// An inline, or a run of `*` or `_` that may become emphasis
+enum Node {
+    Inline(Inline),
+    Delimiter {
+        c: char,
+        count: usize,
+        can_open: bool,
+        can_close: bool,
+    },
+}
+
+fn parse_inlines(text: &str) -> Vec<Inline> {
+    let mut nodes = Vec::new();
+    let mut plain = String::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().unwrap();
+        let before = text[..i].chars().next_back();
+        let (node, len) = match c {
+            '\\' => match rest[1..].chars().next() {
+                Some('\n') => (Some(Node::Inline(Inline::LineBreak)), 2),
+                Some(p) if p.is_ascii_punctuation() => {
+                    plain.push(p);
+                    (None, 2)
+                }
+                _ => (None, 0),
+            },
+            '`' => code_span(rest)
+                .map(|(code, len)| (Some(Node::Inline(Inline::Code(code))), len))
+                .unwrap_or_else(|| {
+                    // an unmatched run of backticks is text
+                    let len = rest.len() - rest.trim_start_matches('`').len();
+                    plain.push_str(&rest[..len]);
+                    (None, len)
+                }),
+            '*' | '_' => {
+                let count = rest.len() - rest.trim_start_matches(c).len();
+                let after = rest[count..].chars().next();
+                let space_before = before.map_or(true, char::is_whitespace);
+                let space_after = after.map_or(true, char::is_whitespace);
+                let punct_before = before.map_or(false, is_punctuation);
+                let punct_after = after.map_or(false, is_punctuation);
+                let left = !space_after && (!punct_after || space_before || punct_before);
+                let right = !space_before && (!punct_before || space_after || punct_after);
+                // underscores inside words, as in snake_case, aren't emphasis
+                let (can_open, can_close) = if c == '_' {
+                    (
+                        left && (!right || punct_before),
+                        right && (!left || punct_after),
+                    )
+                } else {
+                    (left, right)
+                };
+                let node = Node::Delimiter {
+                    c: c,
+                    count: count,
+                    can_open: can_open,
+                    can_close: can_close,
+                };
+                (Some(node), count)
+            }
+            '[' => match link(rest) {
+                Some((label, target, len)) => {
+                    let content = parse_inlines(label);
+                    let node = Node::Inline(Inline::Link {
+                        target: target,
+                        content: content,
+                    });
+                    (Some(node), len)
+                }
+                None => (None, 0),
+            },
+            '!' if rest[1..].starts_with('[') => match link(&rest[1..]) {
+                Some((description, _, len)) => {
+                    plain.push_str(&text_of(&parse_inlines(description)));
+                    (None, len + 1)
+                }
+                None => (None, 0),
+            },
+            '<' => match autolink(rest) {
+                Some((target, len)) => {
+                    let node = Node::Inline(Inline::Link {
+                        content: vec![Inline::Text(rest[1..len - 1].to_string())],
+                        target: target,
+                    });
+                    (Some(node), len)
+                }
+                None => (None, 0),
+            },
+            '\n' => {
+                // two spaces at the end of a line make a hard line break
+                let hard = plain.ends_with("  ");
+                let trimmed_len = plain.trim_end_matches(' ').len();
+                plain.truncate(trimmed_len);
+                let skipped = rest[1..].len() - rest[1..].trim_start_matches(' ').len();
+                if hard {
+                    (Some(Node::Inline(Inline::LineBreak)), 1 + skipped)
+                } else {
+                    plain.push(' ');
+                    (None, 1 + skipped)
+                }
+            }
+            _ => (None, 0),
+        };
+        match node {
+            Some(node) => {
+                if !plain.is_empty() {
+                    nodes.push(Node::Inline(Inline::Text(plain.clone())));
+                    plain.clear();
+                }
+                nodes.push(node);
+            }
+            None if len == 0 => plain.push(c),
+            None => (),
+        }
+        i += if len == 0 { c.len_utf8() } else { len };
+    }
+    if !plain.is_empty() {
+        nodes.push(Node::Inline(Inline::Text(plain)));
+    }
+    resolve_emphasis(nodes)
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || !c.is_alphanumeric() && !c.is_whitespace() && !c.is_control()
+}
+
+/// Returns the content and length of the code span at the start of some text, which ends at the
+/// next run of as many backticks as it starts with
+fn code_span(text: &str) -> Option<(String, usize)> {
+    let ticks = text.len() - text.trim_start_matches('`').len();
+    let mut i = ticks;
+    while i < text.len() {
+        let start = i + text[i..].find('`')?;
+        let run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if run == ticks {
+            let code = text[ticks..start].replace('\n', " ");
+            // one space on each side is removed, so code can start or end with a backtick
+            let code = if code.len() > 2
+                && code.starts_with(' ')
+                && code.ends_with(' ')
+                && !code.trim().is_empty()
+            {
+                code[1..code.len() - 1].to_string()
+            } else {
+                code
+            };
+            return Some((code, start + run));
+        }
+        i = start + run;
+    }
+    None
+}
+
+/// Returns the label, destination and length of an inline link such as
+/// `[label](https://example.com "title")` at the start of some text
+fn link(text: &str) -> Option<(&str, String, usize)> {
+    // find the bracket that closes the label, skipping escapes, code spans and nested brackets
+    let mut depth = 0;
+    let mut label_end = None;
+    let mut i = 0;
+    while i < text.len() {
+        let c = text[i..].chars().next().unwrap();
+        match c {
+            '\\' => {
+                i += 1;
+                if let Some(next) = text[i..].chars().next() {
+                    i += next.len_utf8();
+                }
+                continue;
+            }
+            '`' => {
+                if let Some((_, len)) = code_span(&text[i..]) {
+                    i += len;
+                    continue;
+                }
+            }
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    label_end = Some(i);
+                    break;
+                }
+            }
+            _ => (),
+        }
+        i += c.len_utf8();
+    }
+    let label_end = label_end?;
+    let rest = &text[label_end + 1..];
+    if !rest.starts_with('(') {
+        return None;
+    }
+    let inside = &rest[1..];
+    let spaces = inside.len() - inside.trim_start().len();
+    let inside = &inside[spaces..];
+    let (target, target_len) = if inside.starts_with('<') {
+        let end = inside.find('>')?;
+        (inside[1..end].to_string(), end + 1)
+    } else {
+        // the destination ends at a space or at a parenthesis that isn't balanced
+        let mut depth = 0;
+        let mut end = inside.len();
+        for (i, c) in inside.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    end = i;
+                    break;
+                }
+                ')' => depth -= 1,
+                c if c.is_whitespace() => {
+                    end = i;
+                    break;
+                }
+                _ => (),
+            }
+        }
+        (inside[..end].to_string(), end)
+    };
+    let after = &inside[target_len..];
+    let after_trimmed = after.trim_start();
+    let mut title_len = 0;
+    if let Some(quote) = after_trimmed.chars().next() {
+        let close = match quote {
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            '(' => Some(')'),
+            _ => None,
+        };
+        if let Some(close) = close {
+            title_len = after_trimmed[1..].find(close)? + 2;
+        }
+    }
+    let after_title = after_trimmed[title_len..].trim_start();
+    if !after_title.starts_with(')') {
+        return None;
+    }
+    let len = text.len() - after_title.len() + 1;
+    Some((&text[1..label_end], target, len))
+}
+
+/// Returns the destination and length of an autolink such as `<https://example.com>` or
+/// `<help@example.com>` at the start of some text
+fn autolink(text: &str) -> Option<(String, usize)> {
+    let end = text.find('>')?;
+    let inside = &text[1..end];
+    if inside.is_empty() || inside.contains(|c: char| c.is_whitespace() || c == '<') {
+        return None;
+    }
+    let scheme = inside.find(':').map_or(false, |colon| {
+        colon >= 2
+            && inside[..colon]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '.' || c == '-')
+    });
+    if scheme {
+        Some((inside.to_string(), end + 1))
+    } else if inside.contains('@') {
+        Some((format!("mailto:{}", inside), end + 1))
+    } else {
+        None
+    }
+}
+
+/// Turns runs of `*` and `_` into emphasis where they match, and the rest into text
+fn resolve_emphasis(mut nodes: Vec<Node>) -> Vec<Inline> {
+    let mut i = 0;
+    while i < nodes.len() {
+        let (c, closer_count) = match nodes[i] {
+            Node::Delimiter {
+                c,
+                count,
+                can_close: true,
+                ..
+            } if count > 0 => (c, count),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let opener = (0..i).rev().find(|&j| match nodes[j] {
+            Node::Delimiter {
+                c: o,
+                count,
+                can_open: true,
+                ..
+            } => o == c && count > 0,
+            _ => false,
+        });
+        let j = match opener {
+            Some(j) => j,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let opener_count = match nodes[j] {
+            Node::Delimiter { count, .. } => count,
+            _ => unreachable!(),
+        };
+        let used = if opener_count >= 2 && closer_count >= 2 {
+            2
+        } else {
+            1
+        };
+        let content = to_inlines(nodes.drain(j + 1..i).collect());
+        let emphasis = if used == 2 {
+            Inline::Strong(content)
+        } else {
+            Inline::Emphasis(content)
+        };
+        nodes.insert(j + 1, Node::Inline(emphasis));
+        for &k in &[j, j + 2] {
+            if let Node::Delimiter { ref mut count, .. } = nodes[k] {
+                *count -= used;
+            }
+        }
+        // the closer may have delimiters left for an opener further back
+        i = j + 2;
+    }
+    to_inlines(nodes)
+}
+
+/// Turns delimiters that weren't matched into text, joining adjacent text
+fn to_inlines(nodes: Vec<Node>) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+    for node in nodes {
+        let inline = match node {
+            Node::Inline(inline) => inline,
+            Node::Delimiter { count: 0, .. } => continue,
+            Node::Delimiter { c, count, .. } => Inline::Text(c.to_string().repeat(count)),
+        };
+        match (inlines.last_mut(), inline) {
+            (Some(&mut Inline::Text(ref mut text)), Inline::Text(ref more)) => text.push_str(more),
+            (_, inline) => inlines.push(inline),
+        }
+    }
+    inlines
+}
+
+/// The text of some inlines without their styles, as for the description of an image
+fn text_of(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    for inline in inlines {
+        match *inline {
+            Inline::Text(ref t) | Inline::Code(ref t) => text.push_str(t),
+            Inline::Emphasis(ref content)
+            | Inline::Strong(ref content)
+            | Inline::Link { ref content, .. } => text.push_str(&text_of(content)),
+            Inline::LineBreak => text.push(' '),
+        }
+    }
+    text
+}
+
+/// How Markdown is drawn
+#[derive(Clone)]
+pub struct MarkdownStyle {
+    pub text: TextStyle,
+    /// Style of code spans and blocks, whose size is relative to the text around them. Its font
+    /// family has to be loaded, such as with CodeView::load_font.
+    pub code: TextStyle,
+    /// Colors of code blocks whose info string names a language there is a Grammar for
+    pub code_theme: Theme,
+    /// Font size of headings of each level, relative to the text
+    pub heading_scales: [f32; 6],
+    pub quote_color: Color,
+    pub rule_color: Color,
+    pub link_style: LinkStyle,
+    pub link_hover_style: LinkStyle,
+    /// Space between blocks, relative to the font size of the text
+    pub block_spacing: f32,
+    /// Distance list items and block quotes are indented by, relative to the font size of the
+    /// text
+    pub indent: f32,
+}
+
+impl MarkdownStyle {
+    pub fn new(text: TextStyle) -> MarkdownStyle {
+        MarkdownStyle {
+            code: text.clone().with_family("mono"),
+            code_theme: Theme::dark(),
+            heading_scales: [2.0, 1.5, 1.25, 1.1, 1.0, 0.9],
+            quote_color: [0.6, 0.6, 0.6, 1.0],
+            rule_color: [0.5, 0.5, 0.5, 1.0],
+            link_style: LinkStyle {
+                color: Some([0.35, 0.6, 1.0, 1.0]),
+                underline: false,
+            },
+            link_hover_style: LinkStyle {
+                color: Some([0.35, 0.6, 1.0, 1.0]),
+                underline: true,
+            },
+            block_spacing: 0.75,
+            indent: 1.5,
+            text: text,
+        }
+    }
+}
+
+/// Lays out Markdown blocks one under the other in a single layout, for
+/// GlGlyphRenderer::set_layout. Paragraphs are wrapped if the options wrap, and are always
+/// horizontal.
+pub fn layout<'font>(
+    fonts: &mut FontSet<'font>,
+    blocks: &[Block],
+    style: &MarkdownStyle,
+    options: &LayoutOptions,
+) -> Layout<'font> {
+    let mut writer = Writer {
+        layout: Layout {
+            text: String::new(),
+            glyphs: Vec::new(),
+            clusters: Vec::new(),
+            lines: Vec::new(),
+            boxes: Vec::new(),
+            links: Vec::new(),
+            width: options.width,
+            writing_mode: WritingMode::Horizontal,
+        },
+        fonts: fonts,
+        style: style,
+        options: LayoutOptions {
+            writing_mode: WritingMode::Horizontal,
+            ..options.clone()
+        },
+        y: 0.0,
+    };
+    writer.blocks(blocks, 0.0, 0, &style.text, false);
+    writer.layout
+}
+
+/// Lays out blocks into a layout, keeping track of how far down it has got
+struct Writer<'a, 'font: 'a> {
+    layout: Layout<'font>,
+    fonts: &'a mut FontSet<'font>,
+    style: &'a MarkdownStyle,
+    options: LayoutOptions,
+    y: f32,
+}
+
+impl<'a, 'font> Writer<'a, 'font> {
+    /// Adds blocks at an indent, inside a number of lists, in the style of the text around them
+    fn blocks(
+        &mut self,
+        blocks: &[Block],
+        indent: f32,
+        depth: usize,
+        text_style: &TextStyle,
+        tight: bool,
+    ) {
+        for block in blocks {
+            if !self.layout.lines.is_empty() && !tight {
+                self.y += self.style.block_spacing * self.style.text.size;
+            }
+            match *block {
+                Block::Heading { level, ref content } => {
+                    let scale = self.style.heading_scales[level.max(1).min(6) - 1];
+                    let mut heading = text_style.clone().with_bold(true);
+                    heading.size *= scale;
+                    let rich = self.rich_text(content, &heading);
+                    self.add(&rich, indent, true);
+                }
+                Block::Paragraph(ref content) => {
+                    let rich = self.rich_text(content, text_style);
+                    self.add(&rich, indent, true);
+                }
+                Block::CodeBlock { ref info, ref code } => {
+                    let rich = self.code_block(info, code, text_style.size);
+                    self.add(&rich, indent, false);
+                }
+                Block::Quote(ref content) => {
+                    let quote = text_style.clone().with_color(self.style.quote_color);
+                    let quote_indent = indent + self.style.indent * self.style.text.size;
+                    self.blocks(content, quote_indent, depth, &quote, false);
+                }
+                Block::List {
+                    start,
+                    tight,
+                    ref items,
+                } => self.list(start, tight, items, indent, depth, text_style),
+                Block::Rule => {
+                    let rule = text_style.clone().with_color(self.style.rule_color);
+                    let dash =
+                        layout::layout_paragraph(self.fonts, "\u{2500}", &rule, &self.options);
+                    let advance = dash.clusters.first().map_or(0.0, |c| c.advance);
+                    let count = if advance > 0.0 {
+                        ((self.options.width - indent) / advance).floor().max(1.0) as usize
+                    } else {
+                        1
+                    };
+                    let line = "\u{2500}".repeat(count);
+                    self.add(&RichText::plain(&line, &rule), indent, false);
+                }
+            }
+        }
+    }
+
+    fn list(
+        &mut self,
+        start: Option<usize>,
+        tight: bool,
+        items: &[Vec<Block>],
+        indent: f32,
+        depth: usize,
+        text_style: &TextStyle,
+    ) {
+        let item_indent = indent + self.style.indent * self.style.text.size;
+        let gap = text_style.size * 0.5;
+        for (n, item) in items.iter().enumerate() {
+            if n > 0 && !tight {
+                self.y += self.style.block_spacing * self.style.text.size;
+            }
+            let marker = match start {
+                Some(start) => format!("{}.", start + n),
+                None => BULLETS[depth % BULLETS.len()].to_string(),
+            };
+            let marker = layout::layout_rich(
+                self.fonts,
+                &RichText::plain(&marker, text_style),
+                &self.options,
+            );
+            let width = marker
+                .clusters
+                .iter()
+                .map(|c| c.pos + c.advance)
+                .fold(0.0, f32::max);
+            let top = self.y;
+            // the first block is placed at the top of the item even if the list is loose
+            let lines = self.layout.lines.len();
+            match item.split_first() {
+                Some((first, rest)) => {
+                    self.blocks(
+                        ::std::slice::from_ref(first),
+                        item_indent,
+                        depth + 1,
+                        text_style,
+                        true,
+                    );
+                    self.blocks(rest, item_indent, depth + 1, text_style, tight);
+                }
+                None => (),
+            }
+            // the marker sits on the baseline of the first line of the item, after it in the
+            // layout so hit testing finds the item's line first
+            let baseline = self.layout.lines.get(lines).map(|l| l.baseline - top);
+            let marker_height = height(&marker);
+            let marker_baseline = marker.lines.first().map_or(0.0, |l| l.baseline);
+            let dy = top + baseline.map_or(0.0, |b| b - marker_baseline);
+            let x = (item_indent - gap - width).max(indent);
+            self.layout.append_at(marker, vector(x, dy));
+            if item.is_empty() {
+                self.y = top + marker_height;
+            }
+        }
+    }
+
+    /// Lays out a block at an indent, below the blocks before it
+    fn add(&mut self, rich: &RichText, indent: f32, wrap: bool) {
+        let mut options = self.options.clone();
+        options.width = (self.options.width - indent).max(0.0);
+        options.wrap &= wrap;
+        let block = layout::layout_rich(self.fonts, rich, &options);
+        let block_height = height(&block);
+        let dy = self.y;
+        self.layout.append_at(block, vector(indent, dy));
+        self.y += block_height;
+    }
+
+    fn rich_text(&self, inlines: &[Inline], style: &TextStyle) -> RichText {
+        let mut rich = RichText::new();
+        self.push_inlines(&mut rich, inlines, style);
+        rich
+    }
+
+    fn push_inlines(&self, rich: &mut RichText, inlines: &[Inline], style: &TextStyle) {
+        for inline in inlines {
+            match *inline {
+                Inline::Text(ref text) => rich.push(text, style),
+                Inline::Emphasis(ref content) => {
+                    self.push_inlines(rich, content, &style.clone().with_italic(true))
+                }
+                Inline::Strong(ref content) => {
+                    self.push_inlines(rich, content, &style.clone().with_bold(true))
+                }
+                Inline::Code(ref code) => rich.push(code, &self.code_style(style)),
+                Inline::Link {
+                    ref target,
+                    ref content,
+                } => {
+                    let start = rich.text.len();
+                    self.push_inlines(rich, content, style);
+                    let link = Link::new(target)
+                        .with_style(self.style.link_style)
+                        .with_hover_style(self.style.link_hover_style);
+                    rich.links.push((start..rich.text.len(), link));
+                }
+                // a line separator breaks the line without starting a new paragraph
+                Inline::LineBreak => rich.push("\u{2028}", style),
+            }
+        }
+    }
+
+    fn code_style(&self, around: &TextStyle) -> TextStyle {
+        let mut code = self.style.code.clone();
+        code.size = around.size * self.style.code.size / self.style.text.size;
+        code
+    }
+
+    /// Highlights a code block if its info string names a language there is a Grammar for
+    fn code_block(&self, info: &str, code: &str, size: f32) -> RichText {
+        let style = self.code_style(&TextStyle::new(size));
+        let grammar = match info.split_whitespace().next().unwrap_or("") {
+            "sh" | "bash" | "shell" | "zsh" => Grammar::shell(),
+            "ini" | "toml" | "conf" | "cfg" => Grammar::ini(),
+            "json" => Grammar::json(),
+            "yaml" | "yml" => Grammar::yaml(),
+            _ => return RichText::plain(code, &style),
+        };
+        let mut view = CodeView::new(code, &grammar, style.size, self.options.width, 0.0)
+            .with_theme(self.style.code_theme.clone());
+        view.style = style;
+        view.line_numbers = false;
+        view.rich_text()
+    }
+}
+
+/// Height of the lines of a layout
+fn height(layout: &Layout) -> f32 {
+    match (layout.lines.first(), layout.lines.last()) {
+        (Some(first), Some(last)) => last.extent.end - first.extent.start,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Inline {
+        Inline::Text(s.to_string())
+    }
+
+    fn paragraph(s: &str) -> Block {
+        Block::Paragraph(vec![text(s)])
+    }
+
+    fn link(target: &str, content: Vec<Inline>) -> Inline {
+        Inline::Link {
+            target: target.to_string(),
+            content: content,
+        }
+    }
+
+    #[test]
+    fn headings() {
+        assert_eq!(
+            parse("# One\n### Three ###\n#no space"),
+            vec![
+                Block::Heading {
+                    level: 1,
+                    content: vec![text("One")],
+                },
+                Block::Heading {
+                    level: 3,
+                    content: vec![text("Three")],
+                },
+                paragraph("#no space"),
+            ]
+        );
+        assert_eq!(
+            parse("Title\n=====\nSub\ntitle\n---"),
+            vec![
+                Block::Heading {
+                    level: 1,
+                    content: vec![text("Title")],
+                },
+                Block::Heading {
+                    level: 2,
+                    content: vec![text("Sub title")],
+                },
+            ]
+        );
+        assert_eq!(parse("---"), vec![Block::Rule]);
+    }
+
+    #[test]
+    fn tight_and_loose_lists() {
+        assert_eq!(
+            parse("- a\n- b"),
+            vec![Block::List {
+                start: None,
+                tight: true,
+                items: vec![vec![paragraph("a")], vec![paragraph("b")]],
+            }]
+        );
+        assert_eq!(
+            parse("3. a\n\n4. b"),
+            vec![Block::List {
+                start: Some(3),
+                tight: false,
+                items: vec![vec![paragraph("a")], vec![paragraph("b")]],
+            }]
+        );
+    }
+
+    #[test]
+    fn lazy_continuation() {
+        assert_eq!(
+            parse("- item\nlazy line\n\nafter"),
+            vec![
+                Block::List {
+                    start: None,
+                    tight: true,
+                    items: vec![vec![paragraph("item lazy line")]],
+                },
+                paragraph("after"),
+            ]
+        );
+        assert_eq!(
+            parse("> quoted\nlazy"),
+            vec![Block::Quote(vec![paragraph("quoted lazy")])]
+        );
+    }
+
+    #[test]
+    fn nested_quotes_and_fences() {
+        assert_eq!(
+            parse("> a\n>> b\n> > c"),
+            vec![Block::Quote(vec![
+                paragraph("a"),
+                Block::Quote(vec![paragraph("b c")]),
+            ])]
+        );
+        assert_eq!(
+            parse("> ```sh\n> ls\n>\n> ```\n- ~~~\n  x\n  ~~~"),
+            vec![
+                Block::Quote(vec![Block::CodeBlock {
+                    info: "sh".to_string(),
+                    code: "ls\n".to_string(),
+                }]),
+                Block::List {
+                    start: None,
+                    tight: true,
+                    items: vec![vec![Block::CodeBlock {
+                        info: String::new(),
+                        code: "x".to_string(),
+                    }]],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(
+            parse_inlines("snake_case_name"),
+            vec![text("snake_case_name")]
+        );
+        assert_eq!(
+            parse_inlines("***a**b*"),
+            vec![Inline::Emphasis(vec![
+                Inline::Strong(vec![text("a")]),
+                text("b"),
+            ])]
+        );
+        assert_eq!(
+            parse_inlines("*a* __b__"),
+            vec![
+                Inline::Emphasis(vec![text("a")]),
+                text(" "),
+                Inline::Strong(vec![text("b")]),
+            ]
+        );
+        assert_eq!(parse_inlines("a * b *"), vec![text("a * b *")]);
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            parse_inlines("[a](http://x.org \"Title\")"),
+            vec![link("http://x.org", vec![text("a")])]
+        );
+        assert_eq!(
+            parse_inlines("[a](foo(bar)) and [b](<c d>)"),
+            vec![
+                link("foo(bar)", vec![text("a")]),
+                text(" and "),
+                link("c d", vec![text("b")]),
+            ]
+        );
+        assert_eq!(
+            parse_inlines("see <https://x.org/a> or <me@x.org>"),
+            vec![
+                text("see "),
+                link("https://x.org/a", vec![text("https://x.org/a")]),
+                text(" or "),
+                link("mailto:me@x.org", vec![text("me@x.org")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn backslash_before_multibyte_character() {
+        assert_eq!(
+            parse_inlines("[a\\é](x) \\* \\é"),
+            vec![link("x", vec![text("a\\é")]), text(" * \\é")]
+        );
+    }
+}
@@ -0,0 +1,1086 @@
+extern crate gl;
+extern crate rusttype;
+
+pub mod animation;
+mod bidi;
+pub mod code;
+pub mod color;
+pub mod font;
+mod gsub;
+pub mod hyphenation;
+pub mod image;
+mod instance;
+pub mod layout;
+mod linebreak;
+pub mod link;
+pub mod markdown;
+pub mod rich;
+mod sfnt;
+mod shaping;
+pub mod style;
+pub mod terminal;
+pub mod transform;
+mod vertical;
+pub mod view;
+pub mod vt;
+
+pub use self::animation::Effect;
+pub use self::code::{CodeView, Grammar, Highlighter, Theme, Token};
+pub use self::font::{FaceId, FontSet};
+pub use self::hyphenation::Hyphenator;
+pub use self::image::{Image, ImageRenderer, PixelFormat};
+pub use self::layout::{LayoutOptions, Overflow, WritingMode};
+pub use self::link::{Link, LinkEvent, LinkStyle, LinkTracker};
+pub use self::markdown::MarkdownStyle;
+pub use self::rich::{InlineBox, RichText};
+pub use self::style::TextStyle;
+pub use self::terminal::{Cell, GlTerminalRenderer, Grid};
+pub use self::transform::{GlyphTransform, Path};
+pub use self::view::TextView;
+pub use self::vt::Terminal;
+
+use self::color::{Color, ColorBitmap};
+
+use std;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rusttype::{point, vector, Font, FontCollection, PositionedGlyph, Rect, Scale, Vector};
+
+use gl_basic;
+use gl_basic::types::*;
+
+const MIN_CACHE_SIZE: u32 = 256;
+const MAX_CACHE_SIZE: u32 = 2048;
+
+/// Horizontal resolution of subpixel glyphs, one sample per color channel
+const SUBPIXELS: f32 = 3.0;
+
+/// Gamma that blending coverage is corrected for, which is about that of a typical screen
+const DEFAULT_GAMMA: f32 = 1.8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Antialiasing {
+    Grayscale,
+    /// Coverage per color channel for LCD screens with horizontal RGB subpixels. Glyphs are
+    /// rasterized at three times the horizontal resolution and filtered in the shader.
+    Subpixel,
+}
+
+/// How glyphs are turned into pixels. Changing these starts the glyph cache over.
+#[derive(Clone, Copy, PartialEq)]
+pub struct RasterOptions {
+    pub antialiasing: Antialiasing,
+    /// Physical pixels per layout pixel, such as 3 on a high density phone screen. Glyphs are
+    /// rasterized at their physical size.
+    pub scale_factor: f32,
+    /// If baselines are moved to the nearest physical pixel, which keeps horizontal stems sharp
+    pub snap_baselines: bool,
+}
+
+impl RasterOptions {
+    pub fn new(scale_factor: f32) -> RasterOptions {
+        RasterOptions {
+            antialiasing: Antialiasing::Grayscale,
+            scale_factor: scale_factor,
+            snap_baselines: false,
+        }
+    }
+}
+
+/// Where a cached glyph is in the texture and on screen
+pub struct CachedRect {
+    pub uv: Rect<f32>,
+    /// The part of uv the glyph was rasterized into. It is smaller than uv when the quad is
+    /// widened for the subpixel filter, which spreads coverage into neighbouring pixels.
+    pub uv_bounds: Rect<f32>,
+    pub screen: Rect<f32>,
+}
+
+pub struct GlGlyphCache<'font> {
+    cache: rusttype::gpu_cache::Cache<'font>,
+    texture: gl_basic::Texture,
+    options: RasterOptions,
+    /// RGBA page for color bitmaps, which the alpha-only texture can't hold. It is square, and
+    /// has no storage until the first bitmap is cached.
+    color_texture: gl_basic::Texture,
+    color_size: u32,
+    /// Where each color bitmap is on the color page, by font ID, glyph and strike size
+    color_rects: HashMap<(usize, u16, u16), Rect<u32>>,
+    /// Left and top of the free space on the row of bitmaps being filled, and the row's height
+    shelf: (u32, u32, u32),
+}
+
+impl<'font> GlGlyphCache<'font> {
+    pub fn new(options: RasterOptions) -> Result<GlGlyphCache<'font>, String> {
+        let mut texture = match gl_basic::Texture::new() {
+            Ok(p) => p,
+            Err(e) => return Err(format!("GlGlyphCache texture: {}", e)),
+        };
+        texture.bind_then(|| {
+            unsafe {
+                // set the texture wrapping parameters
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+                // set texture filtering parameters
+                //gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+                //gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            }
+        });
+        let mut cache = rusttype::gpu_cache::CacheBuilder {
+            width: 0,
+            height: 0,
+            ..rusttype::gpu_cache::CacheBuilder::default()
+        }.build();
+        let color_texture = match gl_basic::Texture::new() {
+            Ok(p) => p,
+            Err(e) => return Err(format!("GlGlyphCache color texture: {}", e)),
+        };
+        color_texture.bind_then(|| unsafe {
+            // bitmaps are drawn scaled, so they are filtered
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        });
+        Ok(GlGlyphCache {
+            cache: cache,
+            texture: texture,
+            options: options,
+            color_texture: color_texture,
+            color_size: 0,
+            color_rects: HashMap::new(),
+            shelf: (0, 0, 0),
+        })
+    }
+
+    fn resize_cache(&mut self, width: u32, height: u32) {
+        unsafe {
+            self.texture.bind_then(|| {
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::ALPHA as i32,
+                    width as i32,
+                    height as i32,
+                    0,
+                    gl::ALPHA,
+                    gl::UNSIGNED_BYTE,
+                    std::ptr::null() as *const _,
+                );
+            });
+        }
+        self.cache = rusttype::gpu_cache::CacheBuilder {
+            width: width,
+            height: height,
+            scale_tolerance: 0.25,
+            position_tolerance: 0.25,
+            pad_glyphs: true,
+        }.build();
+        println!("cached resized to {}, {}", width, height);
+    }
+
+    pub fn increase_cache_size(&mut self) -> Result<(), ()> {
+        let (old_x, old_y) = self.cache.dimensions();
+        if old_x >= MAX_CACHE_SIZE && old_y >= MAX_CACHE_SIZE {
+            return Err(());
+        }
+        let new_x = match old_x * 2 {
+            x if x < MIN_CACHE_SIZE => MIN_CACHE_SIZE,
+            x if x > MAX_CACHE_SIZE => MAX_CACHE_SIZE,
+            x => x,
+        };
+        let new_y = match old_y * 2 {
+            y if y < MIN_CACHE_SIZE => MIN_CACHE_SIZE,
+            y if y > MAX_CACHE_SIZE => MAX_CACHE_SIZE,
+            y => y,
+        };
+        self.resize_cache(new_x, new_y);
+        Ok(())
+    }
+
+    /// Size of the cache texture, which changes when the cache grows and every glyph in it moves
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.cache.dimensions()
+    }
+
+    /// Starts the color page over at a size
+    fn resize_color_page(&mut self, size: u32) {
+        self.color_texture.bind_then(|| unsafe {
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as i32,
+                size as i32,
+                size as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null() as *const _,
+            );
+        });
+        self.color_size = size;
+        self.color_rects.clear();
+        self.shelf = (0, 0, 0);
+    }
+
+    /// Finds space for a bitmap on the color page, filling it in rows, or returns None if it is
+    /// full
+    fn allocate_color(&mut self, width: u32, height: u32) -> Option<Rect<u32>> {
+        let (mut x, mut y, mut row_height) = self.shelf;
+        if x + width > self.color_size {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        if x + width > self.color_size || y + height > self.color_size {
+            return None;
+        }
+        self.shelf = (x + width, y, row_height.max(height));
+        Some(Rect {
+            min: point(x, y),
+            max: point(x + width, y + height),
+        })
+    }
+
+    /// Caches color bitmaps, each with the ID of the font they are from. When they don't all fit,
+    /// the color page is started over at twice the size, or at the same size once it is as big
+    /// as it gets.
+    pub fn cache_bitmaps(&mut self, bitmaps: &[(usize, Rc<ColorBitmap>)]) {
+        let mut started_over = false;
+        loop {
+            let mut full = false;
+            for &(font_id, ref bitmap) in bitmaps {
+                let key = (font_id, bitmap.glyph, bitmap.ppem);
+                if self.color_rects.contains_key(&key) {
+                    continue;
+                }
+                // a transparent border keeps filtering at the edges from reaching other bitmaps
+                let (width, height) = (bitmap.width + 2, bitmap.height + 2);
+                let rect = match self.allocate_color(width, height) {
+                    Some(rect) => rect,
+                    None => {
+                        full = true;
+                        break;
+                    }
+                };
+                let mut pixels = vec![0u8; width as usize * height as usize * 4];
+                let row = bitmap.width as usize * 4;
+                for (y, line) in bitmap.pixels.chunks(row).enumerate() {
+                    let start = ((y + 1) * width as usize + 1) * 4;
+                    pixels[start..start + row].copy_from_slice(line);
+                }
+                self.color_texture.bind_then(|| unsafe {
+                    gl::TexSubImage2D(
+                        gl::TEXTURE_2D,
+                        0,
+                        rect.min.x as i32,
+                        rect.min.y as i32,
+                        width as i32,
+                        height as i32,
+                        gl::RGBA,
+                        gl::UNSIGNED_BYTE,
+                        pixels.as_ptr() as *const _,
+                    );
+                });
+                self.color_rects.insert(
+                    key,
+                    Rect {
+                        min: point(rect.min.x + 1, rect.min.y + 1),
+                        max: point(rect.max.x - 1, rect.max.y - 1),
+                    },
+                );
+            }
+            if !full {
+                break;
+            }
+            let size = (self.color_size * 2)
+                .max(MIN_CACHE_SIZE)
+                .min(MAX_CACHE_SIZE);
+            if size == self.color_size {
+                if started_over {
+                    eprintln!("Failed to fit color glyphs in the GPU text cache");
+                    break;
+                }
+                started_over = true;
+            }
+            self.resize_color_page(size);
+        }
+    }
+
+    /// Returns where a bitmap cached by cache_bitmaps is on the color page
+    pub fn bitmap_uv(&self, font_id: usize, bitmap: &ColorBitmap) -> Option<Rect<f32>> {
+        let rect = self
+            .color_rects
+            .get(&(font_id, bitmap.glyph, bitmap.ppem))?;
+        let size = self.color_size as f32;
+        Some(Rect {
+            min: point(rect.min.x as f32 / size, rect.min.y as f32 / size),
+            max: point(rect.max.x as f32 / size, rect.max.y as f32 / size),
+        })
+    }
+
+    /// Draws an object made of quads of glyphs from this cache, with the text shaders
+    fn draw(
+        &self,
+        program: &gl_basic::Program,
+        object: &gl_basic::Object,
+        transform: &Matrix,
+        gamma: f32,
+        contrast: f32,
+    ) {
+        program.set_uniform_mat4("transform", transform);
+        program.set_uniform_f32("gamma", gamma);
+        program.set_uniform_f32("contrast", contrast);
+        program.set_uniform_i32("color_tex", 1);
+        let draw = || match self.options.antialiasing {
+            Antialiasing::Grayscale => {
+                program.set_uniform_i32("mode", 0);
+                object.draw();
+            }
+            Antialiasing::Subpixel => unsafe {
+                // GLES 3.0 has no dual source blending, so the background is first darkened by
+                // the coverage of each channel and then the text color is added on top
+                gl::BlendFunc(gl::ZERO, gl::ONE_MINUS_SRC_COLOR);
+                program.set_uniform_i32("mode", 1);
+                object.draw();
+                gl::BlendFunc(gl::ONE, gl::ONE);
+                program.set_uniform_i32("mode", 2);
+                object.draw();
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            },
+        };
+        self.color_texture
+            .bind_unit_then(1, || self.texture.bind_then(draw));
+    }
+
+    /// Physical pixels per layout pixel in each direction. Subpixel glyphs are stretched
+    /// horizontally to have a pixel per color channel.
+    fn pixel_scale(&self) -> (f32, f32) {
+        let factor = self.options.scale_factor;
+        match self.options.antialiasing {
+            Antialiasing::Grayscale => (factor, factor),
+            Antialiasing::Subpixel => (factor * SUBPIXELS, factor),
+        }
+    }
+
+    /// The glyph that is rasterized for a glyph of the layout, in physical pixels
+    fn raster_glyph(&self, glyph: &PositionedGlyph<'font>) -> PositionedGlyph<'font> {
+        let (x_scale, y_scale) = self.pixel_scale();
+        let scale = glyph.scale();
+        let position = glyph.position();
+        let mut y = position.y * y_scale;
+        if self.options.snap_baselines {
+            y = y.round();
+        }
+        glyph
+            .clone()
+            .into_unpositioned()
+            .into_unscaled()
+            .scaled(Scale {
+                x: scale.x * x_scale,
+                y: scale.y * y_scale,
+            })
+            .positioned(point(position.x * x_scale, y))
+    }
+
+    /// Caches glyphs, each with the ID of the font they are from
+    pub fn cache_glyphs(&mut self, glyphs: &[(usize, PositionedGlyph<'font>)]) {
+        loop {
+            for &(font_id, ref glyph) in glyphs {
+                let glyph = self.raster_glyph(glyph);
+                self.cache.queue_glyph(font_id, glyph);
+            }
+            let cache_queued_result;
+            {
+                let texture = &self.texture;
+                cache_queued_result = self.cache.cache_queued(|rect, data| {
+                    texture.bind_then(|| unsafe {
+                        gl::TexSubImage2D(
+                            gl::TEXTURE_2D,
+                            0,
+                            rect.min.x as i32,
+                            rect.min.y as i32,
+                            rect.width() as i32,
+                            rect.height() as i32,
+                            gl::ALPHA,
+                            gl::UNSIGNED_BYTE,
+                            data.as_ptr() as *const _,
+                        );
+                    })
+                })
+            }
+
+            match cache_queued_result {
+                Err(_) => match self.increase_cache_size() {
+                    Ok(_) => (),
+                    Err(_) => {
+                        eprintln!("Failed to increase GPU text cache size");
+                        break;
+                    }
+                },
+                Ok(_) => break,
+            }
+        }
+    }
+
+    /// Returns where a glyph cached by cache_glyphs is, or None if it has no pixels
+    pub fn rect_for(&self, font_id: usize, glyph: &PositionedGlyph<'font>) -> Option<CachedRect> {
+        let glyph = self.raster_glyph(glyph);
+        let (uv, screen) = match self.cache.rect_for(font_id, &glyph) {
+            Ok(Some(rects)) => rects,
+            _ => return None,
+        };
+        let (x_scale, y_scale) = self.pixel_scale();
+        let screen = Rect {
+            min: point(screen.min.x as f32 / x_scale, screen.min.y as f32 / y_scale),
+            max: point(screen.max.x as f32 / x_scale, screen.max.y as f32 / y_scale),
+        };
+        Some(match self.options.antialiasing {
+            Antialiasing::Grayscale => CachedRect {
+                uv: uv,
+                uv_bounds: uv,
+                screen: screen,
+            },
+            Antialiasing::Subpixel => {
+                // widen the quad by a physical pixel on each side for the filter to spread into
+                let texel = 1.0 / self.cache.dimensions().0 as f32;
+                let pixel = 1.0 / self.options.scale_factor;
+                CachedRect {
+                    uv: Rect {
+                        min: point(uv.min.x - SUBPIXELS * texel, uv.min.y),
+                        max: point(uv.max.x + SUBPIXELS * texel, uv.max.y),
+                    },
+                    uv_bounds: uv,
+                    screen: Rect {
+                        min: point(screen.min.x - pixel, screen.min.y),
+                        max: point(screen.max.x + pixel, screen.max.y),
+                    },
+                }
+            }
+        })
+    }
+}
+
+pub struct GlGlyphRenderer<'font> {
+    cache: GlGlyphCache<'font>,
+    fonts: FontSet<'font>,
+    program: std::rc::Rc<gl_basic::Program>,
+    object: gl_basic::Object,
+    size: (f32, f32),
+    /// Model-view matrix that moves the layout on the screen
+    view: Matrix,
+    gamma: f32,
+    contrast: f32,
+    style: TextStyle,
+    options: LayoutOptions,
+    layout: Option<layout::Layout<'font>>,
+    hovered_link: Option<usize>,
+    /// Transform of each cluster of the layout
+    transforms: Vec<GlyphTransform>,
+    /// Quads in the vertex buffer in the order they are drawn, followed by empty slots left by
+    /// quads that were removed
+    quads: Vec<[Vertex; 4]>,
+}
+
+impl<'font> GlGlyphRenderer<'font> {
+    /// Creates a renderer for a screen of the given size in layout pixels, which are physical
+    /// pixels divided by the scale factor of the raster options
+    pub fn new(screen_width: f32, screen_height: f32) -> Result<GlGlyphRenderer<'font>, String> {
+        let font_data = include_bytes!("../../fonts/wqy-microhei/WenQuanYiMicroHei.ttf");
+        let mut fonts = FontSet::new();
+        if let Err(e) = fonts.load("sans", font_data as &[u8]) {
+            return Err(format!("text font: {}", e));
+        }
+
+        let program = match gl_basic::Program::compile(VERT_SHADER_SRC, FRAG_SHADER_SOURCE) {
+            Ok(p) => std::rc::Rc::new(p),
+            Err(e) => return Err(format!("text shader: {}", e)),
+        };
+
+        let mut object = match Vertex::new_object(program.clone()) {
+            Ok(d) => d,
+            Err(e) => return Err(format!("text object: {}", e)),
+        };
+
+        let mut cache = match GlGlyphCache::new(RasterOptions::new(1.0)) {
+            Ok(d) => d,
+            Err(e) => return Err(format!("text object: {}", e)),
+        };
+
+        Ok(GlGlyphRenderer {
+            cache: cache,
+            fonts: fonts,
+            program: program,
+            object: object,
+            size: (screen_width, screen_height),
+            view: view_matrix(vector(0.0, 0.0), 1.0),
+            gamma: DEFAULT_GAMMA,
+            contrast: 0.0,
+            style: TextStyle::new(64.0),
+            options: LayoutOptions::new(screen_width),
+            layout: None,
+            hovered_link: None,
+            transforms: Vec::new(),
+            quads: Vec::new(),
+        })
+    }
+
+    /// Sets the size of the screen in layout pixels. Text isn't laid out again, so set the width
+    /// in the layout options as well if it should wrap to the new size.
+    pub fn set_screen_size(&mut self, screen_width: f32, screen_height: f32) {
+        self.size = (screen_width, screen_height);
+    }
+
+    /// Sets the matrix that moves the layout from its own pixels to screen pixels before it is
+    /// projected, such as from view_matrix. Scrolling, moving and zooming only change this, so
+    /// nothing is laid out or uploaded again. Snapped baselines stay snapped only when the
+    /// matrix moves by whole pixels and doesn't scale.
+    pub fn set_view(&mut self, view: Matrix) {
+        self.view = view;
+    }
+
+    /// Loads a font that text styles can then use by its family name
+    pub fn load_font(&mut self, family: &str, data: &'font [u8]) -> Result<FaceId, String> {
+        self.fonts.load(family, data)
+    }
+
+    /// The fonts text is laid out with, for laying out text to pass to set_layout
+    pub fn fonts_mut(&mut self) -> &mut FontSet<'font> {
+        &mut self.fonts
+    }
+
+    /// Sets the antialiasing and scale factor glyphs are rasterized with. The glyph cache is
+    /// started over, so this takes effect on the next call to set_text.
+    pub fn set_raster_options(&mut self, options: RasterOptions) -> Result<(), String> {
+        if options != self.cache.options {
+            self.cache = GlGlyphCache::new(options)?;
+        }
+        Ok(())
+    }
+
+    /// Sets how coverage is adjusted before blending. Blending is done on gamma encoded colors,
+    /// which makes light text on a dark background look thin and dark text on a light one look
+    /// bold, so coverage is raised to a power that depends on the brightness of the text. A gamma
+    /// of 1 turns this off. Contrast from 0 to 1 strengthens partly covered pixels, which
+    /// sharpens thin stems.
+    pub fn set_gamma(&mut self, gamma: f32, contrast: f32) {
+        self.gamma = gamma;
+        self.contrast = contrast;
+    }
+
+    /// Sets the style used by the next call to set_text
+    pub fn set_style(&mut self, style: TextStyle) {
+        self.style = style;
+    }
+
+    /// Sets the options used by the next call to set_text
+    pub fn set_layout_options(&mut self, options: LayoutOptions) {
+        self.options = options;
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        let text = RichText::plain(text, &self.style);
+        self.set_rich_text(&text);
+    }
+
+    /// Sets text with spans in different styles. Inline boxes are left empty for the caller to
+    /// draw, at the rects in the boxes of the layout.
+    pub fn set_rich_text(&mut self, text: &RichText) {
+        let layout = layout::layout_rich(&mut self.fonts, text, &self.options);
+        self.set_layout(layout);
+    }
+
+    /// Sets text written in Markdown, laid out with the layout options of the renderer
+    pub fn set_markdown(&mut self, text: &str, style: &MarkdownStyle) {
+        let blocks = markdown::parse(text);
+        let layout = markdown::layout(&mut self.fonts, &blocks, style, &self.options);
+        self.set_layout(layout);
+    }
+
+    /// Draws a layout that was made with the fonts of the renderer
+    pub fn set_layout(&mut self, layout: layout::Layout<'font>) {
+        let glyphs: Vec<(usize, PositionedGlyph)> = layout
+            .glyphs
+            .iter()
+            .map(|g| (g.font_id, g.glyph.clone()))
+            .collect();
+        self.cache.cache_glyphs(&glyphs);
+        let bitmaps: Vec<(usize, Rc<ColorBitmap>)> = layout
+            .glyphs
+            .iter()
+            .filter_map(|g| g.bitmap.as_ref().map(|b| (g.font_id, b.clone())))
+            .collect();
+        self.cache.cache_bitmaps(&bitmaps);
+        self.layout = Some(layout);
+        self.hovered_link = None;
+        self.transforms.clear();
+        self.update_vertices();
+    }
+
+    /// Sets the link that is drawn in its hover style, which is usually LinkTracker::hovered
+    pub fn set_hovered_link(&mut self, link: Option<usize>) {
+        if link != self.hovered_link {
+            self.hovered_link = link;
+            self.update_vertices();
+        }
+    }
+
+    /// Sets a transform for each cluster of the layout, such as from transform::along_path. It
+    /// lasts until the next call to set_text. Underlines aren't transformed.
+    pub fn set_glyph_transforms(&mut self, transforms: Vec<GlyphTransform>) {
+        self.transforms = transforms;
+        self.update_vertices();
+    }
+
+    /// Builds the vertices of the current layout, which is all that is needed when links change
+    /// style or glyphs are transformed
+    fn update_vertices(&mut self) {
+        let layout = match self.layout {
+            Some(ref layout) => layout,
+            None => return,
+        };
+        let link_styles: Vec<LinkStyle> = layout
+            .links
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, ref link))| {
+                if self.hovered_link == Some(i) {
+                    link.hover_style
+                } else {
+                    link.style
+                }
+            })
+            .collect();
+        let centers = layout.cluster_centers();
+        let mut quads: Vec<[Vertex; 4]> = Vec::new();
+        for g in &layout.glyphs {
+            let quad = match g.bitmap {
+                Some(ref bitmap) => self.cache.bitmap_uv(g.font_id, bitmap).map(|uv| {
+                    let (origin, size) = (g.glyph.position(), g.glyph.scale().y);
+                    let screen = Rect {
+                        min: origin + vector(bitmap.rect.min.x, bitmap.rect.min.y) * size,
+                        max: origin + vector(bitmap.rect.max.x, bitmap.rect.max.y) * size,
+                    };
+                    (uv, screen, COLOR_BITMAP)
+                }),
+                None => self.cache.rect_for(g.font_id, &g.glyph).map(|rect| {
+                    let tex_bounds = Vec4 {
+                        x: rect.uv_bounds.min.x,
+                        y: rect.uv_bounds.min.y,
+                        z: rect.uv_bounds.max.x,
+                        w: rect.uv_bounds.max.y,
+                    };
+                    (rect.uv, rect.screen, tex_bounds)
+                }),
+            };
+            if let Some((uv_rect, screen_rect, tex_bounds)) = quad {
+                let start = layout.clusters[g.cluster].range.start;
+                let link_color = layout
+                    .links
+                    .iter()
+                    .position(|&(ref range, _)| start >= range.start && start < range.end)
+                    .and_then(|i| link_styles[i].color);
+                let transform = self.transforms.get(g.cluster).cloned();
+                let mut color = match transform.and_then(|t| t.color) {
+                    Some(color) => vec4(color),
+                    None => vec4(link_color.unwrap_or(g.color)),
+                };
+                if g.bitmap.is_some() {
+                    // bitmaps have their own colors, and only take the opacity of the text
+                    color = Vec4 {
+                        x: 1.0,
+                        y: 1.0,
+                        z: 1.0,
+                        w: g.color[3],
+                    };
+                }
+                if let Some(transform) = transform {
+                    color.w *= transform.opacity;
+                }
+                let corner = |x: f32, y: f32| {
+                    let (mut x, mut y) = (x, y);
+                    if let Some(pivot) = g.rotate_about {
+                        // turn 90 degrees clockwise, y is down so this maps (x, y) to (-y, x)
+                        let (dx, dy) = (x - pivot.x, y - pivot.y);
+                        x = pivot.x - dy;
+                        y = pivot.y + dx;
+                    }
+                    if let Some(transform) = transform {
+                        let center = centers[g.cluster];
+                        let p = transform.apply(point(x, y) - center);
+                        x = center.x + p.x;
+                        y = center.y + p.y;
+                    }
+                    Vec2 { x: x, y: y }
+                };
+                push_quad(
+                    &mut quads,
+                    [
+                        corner(screen_rect.min.x, screen_rect.max.y),
+                        corner(screen_rect.min.x, screen_rect.min.y),
+                        corner(screen_rect.max.x, screen_rect.min.y),
+                        corner(screen_rect.max.x, screen_rect.max.y),
+                    ],
+                    uv_rect,
+                    tex_bounds,
+                    color,
+                );
+            }
+        }
+        for (i, &(ref range, _)) in layout.links.iter().enumerate() {
+            if !link_styles[i].underline {
+                continue;
+            }
+            let text_color = layout
+                .glyphs
+                .iter()
+                .find(|g| layout.clusters[g.cluster].range.start >= range.start)
+                .map_or([1.0; 4], |g| g.color);
+            let color = vec4(link_styles[i].color.unwrap_or(text_color));
+            for rect in layout.underline_rects(range.clone()) {
+                push_quad(
+                    &mut quads,
+                    [
+                        Vec2 {
+                            x: rect.min.x,
+                            y: rect.max.y,
+                        },
+                        Vec2 {
+                            x: rect.min.x,
+                            y: rect.min.y,
+                        },
+                        Vec2 {
+                            x: rect.max.x,
+                            y: rect.min.y,
+                        },
+                        Vec2 {
+                            x: rect.max.x,
+                            y: rect.max.y,
+                        },
+                    ],
+                    Rect {
+                        min: point(0.0, 0.0),
+                        max: point(0.0, 0.0),
+                    },
+                    SOLID,
+                    color,
+                );
+            }
+        }
+
+        self.upload(quads);
+    }
+
+    /// Uploads quads in the order they are drawn, which is the order of the layout: the COLR
+    /// layers of a glyph go from bottom to top, and underlines go over their glyphs. Only the
+    /// slots between the quads that are unchanged at the start and at the end are written, so
+    /// fading in a few clusters or a small edit uploads little. The buffer is only reallocated
+    /// when it has to grow or is mostly empty slots, which are quads with no area.
+    fn upload(&mut self, quads: Vec<[Vertex; 4]>) {
+        if quads.len() > self.quads.len() || quads.len() * 2 < self.quads.len() {
+            let vertices: Vec<Vertex> = quads.iter().flat_map(|q| q.iter().cloned()).collect();
+            let indices: Vec<[gl::types::GLuint; 3]> = (0..quads.len() as gl::types::GLuint)
+                .flat_map(|i| {
+                    let i = i * 4;
+                    vec![[i + 0, i + 1, i + 2], [i + 0, i + 2, i + 3]]
+                })
+                .collect();
+            Vertex::set_vertices(&mut self.object, vertices);
+            self.object.set_indices(indices);
+            self.quads = quads;
+            return;
+        }
+        let mut slots = quads;
+        slots.resize(self.quads.len(), empty_quad());
+        let same = |&(a, b): &(&[Vertex; 4], &[Vertex; 4])| quad_bytes(a) == quad_bytes(b);
+        let start = slots
+            .iter()
+            .zip(&self.quads)
+            .take_while(|pair| same(pair))
+            .count();
+        if start == slots.len() {
+            return;
+        }
+        let end = slots.len()
+            - slots
+                .iter()
+                .rev()
+                .zip(self.quads.iter().rev())
+                .take_while(|pair| same(pair))
+                .count();
+        let vertices: Vec<Vertex> = slots[start..end]
+            .iter()
+            .flat_map(|q| q.iter().cloned())
+            .collect();
+        Vertex::update_vertices(&mut self.object, start * 4, &vertices);
+        self.quads = slots;
+    }
+
+    /// The layout of the current text, for caret placement and hit testing
+    pub fn layout(&self) -> Option<&layout::Layout<'font>> {
+        self.layout.as_ref()
+    }
+
+    pub fn draw(&self) {
+        let transform = multiply(&projection_matrix(self.size), &self.view);
+        self.cache.draw(
+            &self.program,
+            &self.object,
+            &transform,
+            self.gamma,
+            self.contrast,
+        );
+    }
+
+    /*
+    pub fn draw_cache(&mut self) {
+        if let None = self.cache_object {
+            let cache_object = match Vertex::new_object(program) {
+                Ok(o) => o,
+                Err(e) => {
+                    eprintln!("cache object: {}", e);
+                    return;
+                }
+            };
+                let vertices = vec![Vertex {
+            position: Vec2 { x: 0f32, y: 1f32 },
+            tex_coords: Vec2 { x: 0f32, y: 0f32 },
+        }, Vertex {
+            position: Vec2 { x: 0f32, y: 0f32 },
+            tex_coords: Vec2 { x: 0f32, y: 1f32 },
+        },Vertex {
+            position: Vec2 { x: 1f32, y: 0f32 },
+            tex_coords: Vec2 { x: 1f32, y: 1f32 },
+        },Vertex {
+            position: Vec2 { x: 1f32, y: 1f32 },
+            tex_coords: Vec2 { x: 1f32, y: 0f32 },
+        }];
+        let indices = vec![[0, 1, 2],[0, 2, 3]];
+
+        Vertex::set_vertices(&mut cache_object, vertices);
+        cache_object.set_indices(indices);
+        self.cache_object = cache_object;
+        }
+        self.cache.texture.bind_then(|| {
+            self.cache_object.draw();
+        });
+    }*/
+}
+
+/// Texture bounds that mark a quad as a solid rect, such as an underline, rather than a glyph
+const SOLID: Vec4 = Vec4 {
+    x: -1.0,
+    y: -1.0,
+    z: -1.0,
+    w: -1.0,
+};
+
+/// Texture bounds that mark a quad as a bitmap from the color page of GlGlyphCache
+const COLOR_BITMAP: Vec4 = Vec4 {
+    x: -2.0,
+    y: -2.0,
+    z: -2.0,
+    w: -2.0,
+};
+
+/// A 4x4 matrix in column-major order, which is how OpenGL takes it
+pub type Matrix = [f32; 16];
+
+/// Returns a matrix that scales about the origin by zoom and then moves by offset
+#[rustfmt::skip]
+pub fn view_matrix(offset: Vector<f32>, zoom: f32) -> Matrix {
+    [
+        zoom, 0.0, 0.0, 0.0,
+        0.0, zoom, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        offset.x, offset.y, 0.0, 1.0,
+    ]
+}
+
+/// Projects screen pixels, with y down, to clip space
+#[rustfmt::skip]
+fn projection_matrix(size: (f32, f32)) -> Matrix {
+    [
+        2.0 / size.0, 0.0, 0.0, 0.0,
+        0.0, -2.0 / size.1, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        -1.0, 1.0, 0.0, 1.0,
+    ]
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [0.0; 16];
+    for column in 0..4 {
+        for row in 0..4 {
+            product[column * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[column * 4 + k]).sum();
+        }
+    }
+    product
+}
+
+fn vec4(color: Color) -> Vec4 {
+    Vec4 {
+        x: color[0],
+        y: color[1],
+        z: color[2],
+        w: color[3],
+    }
+}
+
+/// Adds a quad with corners in the order bottom left, top left, top right, bottom right
+fn push_quad(
+    quads: &mut Vec<[Vertex; 4]>,
+    corners: [Vec2; 4],
+    uv: Rect<f32>,
+    tex_bounds: Vec4,
+    color: Vec4,
+) {
+    let tex_coords = [
+        (uv.min.x, uv.max.y),
+        (uv.min.x, uv.min.y),
+        (uv.max.x, uv.min.y),
+        (uv.max.x, uv.max.y),
+    ];
+    let vertex = |i: usize| Vertex {
+        position: corners[i],
+        tex_coords: Vec2 {
+            x: tex_coords[i].0,
+            y: tex_coords[i].1,
+        },
+        tex_bounds: tex_bounds,
+        color: color,
+    };
+    quads.push([vertex(0), vertex(1), vertex(2), vertex(3)]);
+}
+
+/// A quad with no area, which fills slots in the vertex buffer that nothing is drawn in
+fn empty_quad() -> [Vertex; 4] {
+    let zero = Vertex {
+        position: Vec2 { x: 0.0, y: 0.0 },
+        tex_coords: Vec2 { x: 0.0, y: 0.0 },
+        tex_bounds: SOLID,
+        color: Vec4 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 0.0,
+        },
+    };
+    [zero; 4]
+}
+
+fn quad_bytes(quad: &[Vertex; 4]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            quad.as_ptr() as *const u8,
+            std::mem::size_of::<[Vertex; 4]>(),
+        )
+    }
+}
+
+attribs!(pub struct Vertex {
+    pub position: Vec2,
+    pub tex_coords: Vec2,
+    pub tex_bounds: Vec4,
+    pub color: Vec4,
+});
+
+const VERT_SHADER_SRC: &str = "
+#version 300 es
+precision mediump float;
+
+// projects positions in layout pixels, see GlGlyphRenderer::set_view
+uniform mat4 transform;
+
+in vec2 position;
+in vec2 tex_coords;
+in vec4 tex_bounds;
+in vec4 color;
+
+out vec2 frag_tex_coords;
+out vec4 frag_tex_bounds;
+out vec4 frag_color;
+
+void main() {
+    gl_Position = transform * vec4(position, 0.0, 1.0);
+    frag_tex_coords = tex_coords;
+    frag_tex_bounds = tex_bounds;
+    frag_color = color;
+}";
+
+const FRAG_SHADER_SOURCE: &str = "
+#version 300 es
+precision mediump float;
+
+uniform sampler2D tex;
+// RGBA bitmaps with straight alpha, such as emoji
+uniform sampler2D color_tex;
+// 0 for grayscale text, 1 and 2 for the two passes of subpixel text
+uniform int mode;
+uniform float gamma;
+uniform float contrast;
+
+in vec2 frag_tex_coords;
+in vec4 frag_tex_bounds;
+in vec4 frag_color;
+
+out vec4 fragColor;
+
+// coverage of the subpixel a number of texels to the right, which is zero outside the glyph
+float subpixel(int offset) {
+    if (frag_tex_bounds.x < 0.0) {
+        return 1.0;
+    }
+    float x = frag_tex_coords.x + float(offset) / float(textureSize(tex, 0).x);
+    if (x < frag_tex_bounds.x || x > frag_tex_bounds.z) {
+        return 0.0;
+    }
+    return texture(tex, vec2(x, frag_tex_coords.y)).a;
+}
+
+// makes text the same apparent weight whatever its color, see GlGlyphRenderer::set_gamma
+vec3 adjust(vec3 coverage) {
+    coverage = clamp(coverage + contrast * coverage * (1.0 - coverage), 0.0, 1.0);
+    float luminance = dot(frag_color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    return pow(coverage, vec3(mix(gamma, 1.0 / gamma, luminance)));
+}
+
+void main() {
+    if (frag_tex_bounds.x < -1.5) {
+        vec4 texel = texture(color_tex, frag_tex_coords);
+        float alpha = texel.a * frag_color.a;
+        if (mode == 0) {
+            fragColor = vec4(texel.rgb, alpha);
+        } else if (mode == 1) {
+            fragColor = vec4(vec3(alpha), 1.0);
+        } else {
+            fragColor = vec4(texel.rgb * alpha, 1.0);
+        }
+        return;
+    }
+    if (mode == 0) {
+        float alpha = frag_tex_bounds.x < 0.0 ? 1.0 : texture(tex, frag_tex_coords).a;
+        float coverage = adjust(vec3(alpha)).g;
+        fragColor = vec4(frag_color.rgb, frag_color.a * coverage);
+        return;
+    }
+    // the green subpixel is at the center of the pixel. Each channel is filtered with
+    // FreeType's default LCD filter to reduce color fringes.
+    float s[7];
+    for (int i = 0; i < 7; i++) {
+        s[i] = subpixel(i - 3);
+    }
+    vec3 coverage = adjust(vec3(
+        0.031 * s[0] + 0.302 * s[1] + 0.337 * s[2] + 0.302 * s[3] + 0.031 * s[4],
+        0.031 * s[1] + 0.302 * s[2] + 0.337 * s[3] + 0.302 * s[4] + 0.031 * s[5],
+        0.031 * s[2] + 0.302 * s[3] + 0.337 * s[4] + 0.302 * s[5] + 0.031 * s[6]
+    )) * frag_color.a;
+    if (mode == 1) {
+        fragColor = vec4(coverage, 1.0);
+    } else {
+        fragColor = vec4(frag_color.rgb * coverage, 1.0);
+    }
+}";
@@ -0,0 +1,97 @@
+extern crate unicode_normalization;
+
+use std::ops::Range;
+
+use self::unicode_normalization::UnicodeNormalization;
+
+use text::link::Link;
+use text::style::TextStyle;
+
+/// The character inline boxes take the place of in the text
+pub const OBJECT_REPLACEMENT: char = '\u{FFFC}';
+
+/// Space in the flow of text for an icon, image or widget, which the caller draws
+#[derive(Clone, Copy)]
+pub struct InlineBox {
+    /// Identifies the box in Layout::boxes
+    pub id: usize,
+    pub width: f32,
+    pub height: f32,
+    /// Distance from the top of the box down to the baseline of the text it sits on
+    pub baseline: f32,
+}
+
+impl InlineBox {
+    /// A box that sits on the baseline
+    pub fn new(id: usize, width: f32, height: f32) -> InlineBox {
+        InlineBox {
+            id: id,
+            width: width,
+            height: height,
+            baseline: height,
+        }
+    }
+
+    /// Moves the baseline so the box is centered on a line of text with the given ascent and
+    /// descent, both as distances from the baseline. This is how icons usually sit next to a
+    /// label.
+    pub fn centered(mut self, ascent: f32, descent: f32) -> InlineBox {
+        self.baseline = self.height / 2.0 + (ascent - descent) / 2.0;
+        self
+    }
+}
+
+/// Text made of spans in different styles, with inline boxes
+pub struct RichText {
+    /// The NFC normalized text
+    pub text: String,
+    /// Byte ranges of text and their styles, in order
+    pub spans: Vec<(Range<usize>, TextStyle)>,
+    /// Byte indices of the object replacement characters that stand for each box
+    pub boxes: Vec<(usize, InlineBox)>,
+    /// Byte ranges of links, in order
+    pub links: Vec<(Range<usize>, Link)>,
+}
+
+impl RichText {
+    pub fn new() -> RichText {
+        RichText {
+            text: String::new(),
+            spans: Vec::new(),
+            boxes: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+
+    pub fn plain(text: &str, style: &TextStyle) -> RichText {
+        let mut rich = RichText::new();
+        rich.push(text, style);
+        rich
+    }
+
+    /// Adds text in a style. Each span is normalized on its own, so characters are not composed
+    /// across spans.
+    pub fn push(&mut self, text: &str, style: &TextStyle) {
+        let start = self.text.len();
+        self.text.extend(text.nfc());
+        self.spans.push((start..self.text.len(), style.clone()));
+    }
+
+    /// Adds text in a style that is a link
+    pub fn push_link(&mut self, text: &str, style: &TextStyle, link: Link) {
+        let start = self.text.len();
+        self.push(text, style);
+        self.links.push((start..self.text.len(), link));
+    }
+
+    /// Adds an inline box. It is part of the span before it, or the span after it at the start of
+    /// the text, which sets the line height it is laid out with.
+    pub fn push_box(&mut self, inline_box: InlineBox) {
+        let index = self.text.len();
+        self.text.push(OBJECT_REPLACEMENT);
+        if let Some(&mut (ref mut range, _)) = self.spans.last_mut() {
+            range.end = self.text.len();
+        }
+        self.boxes.push((index, inline_box));
+    }
+}
@@ -0,0 +1,126 @@
+//! Just enough of the OpenType file format to read tables rusttype doesn't expose, and to write
+//! fonts with some of their tables replaced
+
+use text::style::Tag;
+
+pub fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+pub fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    Some(read_u16(data, offset)? as i16)
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some((read_u16(data, offset)? as u32) << 16 | read_u16(data, offset + 2)? as u32)
+}
+
+/// Reads a 16.16 fixed point number
+pub fn read_fixed(data: &[u8], offset: usize) -> Option<f32> {
+    Some(read_u32(data, offset)? as i32 as f32 / 65536.0)
+}
+
+/// Reads a 2.14 fixed point number, as used for normalized variation coordinates
+pub fn read_f2dot14(data: &[u8], offset: usize) -> Option<f32> {
+    Some(read_i16(data, offset)? as f32 / 16384.0)
+}
+
+pub fn read_tag(data: &[u8], offset: usize) -> Option<Tag> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Returns the tags and data of the tables in a font file, or in the first font of a collection
+pub fn tables(data: &[u8]) -> Option<Vec<(Tag, &[u8])>> {
+    let font_offset = if read_tag(data, 0)? == *b"ttcf" {
+        read_u32(data, 12)? as usize
+    } else {
+        0
+    };
+    let table_count = read_u16(data, font_offset + 4)? as usize;
+    (0..table_count)
+        .map(|i| {
+            let record = font_offset + 12 + i * 16;
+            let offset = read_u32(data, record + 8)? as usize;
+            let length = read_u32(data, record + 12)? as usize;
+            Some((read_tag(data, record)?, data.get(offset..offset + length)?))
+        })
+        .collect()
+}
+
+/// Returns the data of a table in a font file, or in the first font of a collection
+pub fn find_table<'a>(data: &'a [u8], tag: Tag) -> Option<&'a [u8]> {
+    tables(data)?
+        .into_iter()
+        .find(|&(t, _)| t == tag)
+        .map(|(_, table)| table)
+}
+
+pub fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.push((value >> 8) as u8);
+    out.push(value as u8);
+}
+
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    write_u16(out, (value >> 16) as u16);
+    write_u16(out, value as u16);
+}
+
+/// Overwrites a u16 in data that has already been written
+pub fn set_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset] = (value >> 8) as u8;
+    data[offset + 1] = value as u8;
+}
+
+/// Sum of the big endian u32s in data, with the end padded with zeros
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let word = (0..4).fold(0u32, |word, i| {
+            word << 8 | *chunk.get(i).unwrap_or(&0) as u32
+        });
+        sum.wrapping_add(word)
+    })
+}
+
+/// Writes a font file made of the given tables. The checkSumAdjustment of the head table should
+/// be zero, and is filled in.
+pub fn write_font(mut tables: Vec<(Tag, Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|&(tag, _)| tag);
+    let count = tables.len();
+    let (mut search_range, mut entry_selector) = (1, 0);
+    while search_range * 2 <= count {
+        search_range *= 2;
+        entry_selector += 1;
+    }
+    let mut out = Vec::new();
+    write_u32(&mut out, 0x00010000);
+    write_u16(&mut out, count as u16);
+    write_u16(&mut out, (search_range * 16) as u16);
+    write_u16(&mut out, entry_selector);
+    write_u16(&mut out, (count.saturating_sub(search_range) * 16) as u16);
+    let mut offset = 12 + count * 16;
+    let mut head = None;
+    for &(tag, ref data) in &tables {
+        if tag == *b"head" {
+            head = Some(offset);
+        }
+        out.extend_from_slice(&tag);
+        write_u32(&mut out, checksum(data));
+        write_u32(&mut out, offset as u32);
+        write_u32(&mut out, data.len() as u32);
+        offset += (data.len() + 3) & !3;
+    }
+    for &(_, ref data) in &tables {
+        out.extend_from_slice(data);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+    }
+    if let Some(head) = head {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&out));
+        set_u16(&mut out, head + 8, (adjustment >> 16) as u16);
+        set_u16(&mut out, head + 10, adjustment as u16);
+    }
+    out
+}
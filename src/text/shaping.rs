@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+use rusttype::{Font, GlyphId, Scale, ScaledGlyph};
+
+use text::gsub::{self, GlyphInfo, Gsub};
+use text::style::{Tag, TextStyle};
+
+/// Features that are on unless a style turns them off
+const DEFAULT_FEATURES: &[Tag] = &[
+    *b"ccmp", *b"locl", *b"rlig", *b"liga", *b"clig", *b"calt", *b"rclt",
+];
+
+pub struct ShapedGlyph<'font> {
+    pub glyph: ScaledGlyph<'font>,
+    /// Offset of the glyph from the left edge of the space it takes up
+    pub offset: f32,
+    pub advance: f32,
+    /// If the glyph has a fixed width and should not be kerned
+    pub tabular: bool,
+}
+
+/// Picks glyphs for characters, applying the OpenType features of a style with the font's GSUB
+/// table. rusttype doesn't read GPOS, so kern uses the kern table, and marks are placed by the
+/// layout rather than by anchors.
+pub struct Shaper<'a, 'font: 'a> {
+    font: &'a Font<'font>,
+    gsub: Option<&'a Gsub<'font>>,
+    scale: Scale,
+    kern: bool,
+    /// Lookups of the features for each script the font has
+    script_lookups: Vec<(Tag, Vec<u16>)>,
+    /// Lookups of the features for text in other scripts or without one
+    lookups: Vec<u16>,
+    /// Width digits are set in when tnum is on and the font has no tabular digits of its own
+    tabular_width: Option<f32>,
+}
+
+impl<'a, 'font> Shaper<'a, 'font> {
+    pub fn new(
+        font: &'a Font<'font>,
+        gsub: Option<&'a Gsub<'font>>,
+        style: &TextStyle,
+    ) -> Shaper<'a, 'font> {
+        let scale = Scale::uniform(style.size);
+        let mut features: Vec<Tag> = DEFAULT_FEATURES
+            .iter()
+            .cloned()
+            .filter(|&tag| style.feature(tag, true))
+            .collect();
+        let turned_on: Vec<Tag> = style
+            .features
+            .iter()
+            .filter(|&&(tag, enabled)| enabled && !DEFAULT_FEATURES.contains(&tag))
+            .map(|&(tag, _)| tag)
+            .collect();
+        features.extend(turned_on);
+        let has_tnum = gsub.map_or(false, |gsub| gsub.features().contains(b"tnum"));
+        let tabular_width = if style.feature(*b"tnum", false) && !has_tnum {
+            Some(
+                "0123456789"
+                    .chars()
+                    .map(|c| font.glyph(c).scaled(scale).h_metrics().advance_width)
+                    .fold(0.0, f32::max),
+            )
+        } else {
+            None
+        };
+        Shaper {
+            font: font,
+            gsub: gsub,
+            scale: scale,
+            kern: style.feature(*b"kern", true),
+            script_lookups: gsub.map_or(Vec::new(), |gsub| {
+                gsub.scripts()
+                    .into_iter()
+                    .map(|tag| (tag, gsub.lookups(&[tag], &features)))
+                    .collect()
+            }),
+            lookups: gsub.map_or(Vec::new(), |gsub| gsub.lookups(&[], &features)),
+            tabular_width: tabular_width,
+        }
+    }
+
+    pub fn font(&self) -> &'a Font<'font> {
+        self.font
+    }
+
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    /// Returns if the font has a glyph for a character, rather than using .notdef
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.font.glyph(c).id() != GlyphId(0)
+    }
+
+    /// Maps characters to glyphs and applies the substitutions of the features. Each character
+    /// comes with the text range it stands for, and the glyphs come with the ranges of the
+    /// characters they replaced.
+    pub fn substitute(&self, chars: &[(char, Range<usize>)]) -> Vec<GlyphInfo> {
+        let mut glyphs: Vec<GlyphInfo> = chars
+            .iter()
+            .map(|&(c, ref range)| GlyphInfo {
+                id: self.font.glyph(c).id().0 as u16,
+                range: range.clone(),
+            })
+            .collect();
+        if let Some(gsub) = self.gsub {
+            let scripts = gsub::script_tags(chars.iter().map(|&(c, _)| c));
+            let lookups = scripts
+                .iter()
+                .filter_map(|tag| self.script_lookups.iter().find(|&&(t, _)| t == *tag))
+                .map(|&(_, ref lookups)| lookups)
+                .next()
+                .unwrap_or(&self.lookups);
+            gsub.apply(lookups, &mut glyphs);
+        }
+        glyphs
+    }
+
+    /// Scales a glyph that came from substitute. Digits get a fixed width if tnum has to be
+    /// faked.
+    pub fn shaped(&self, id: u16, digit: bool) -> ShapedGlyph<'font> {
+        let glyph = self.font.glyph(GlyphId(id.into())).scaled(self.scale);
+        let advance = glyph.h_metrics().advance_width;
+        match self.tabular_width {
+            Some(width) if digit => ShapedGlyph {
+                glyph: glyph,
+                offset: (width - advance) / 2.0,
+                advance: width,
+                tabular: true,
+            },
+            _ => ShapedGlyph {
+                glyph: glyph,
+                offset: 0.0,
+                advance: advance,
+                tabular: false,
+            },
+        }
+    }
+
+    /// Shapes a character on its own, so only features that substitute single glyphs apply
+    pub fn glyph(&self, c: char) -> ShapedGlyph<'font> {
+        let len = c.len_utf8();
+        let id = match self.substitute(&[(c, 0..len)]).first() {
+            Some(g) => g.id,
+            None => 0,
+        };
+        self.shaped(id, c.is_digit(10))
+    }
+
+    pub fn kerning(&self, first: GlyphId, second: GlyphId) -> f32 {
+        if self.kern {
+            self.font.pair_kerning(self.scale, first, second)
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use text::color::Color;
+
+/// An OpenType tag, such as `*b"tnum"`
+pub type Tag = [u8; 4];
+
+#[derive(Clone, PartialEq)]
+pub struct TextStyle {
+    /// Name the font family was loaded into the FontSet with
+    pub family: String,
+    /// Font size in pixels
+    pub size: f32,
+    pub bold: bool,
+    pub italic: bool,
+    pub color: Color,
+    /// Language of the text, which picks the hyphenation patterns used when wrapping
+    pub language: Option<String>,
+    /// OpenType features that are explicitly turned on or off
+    pub features: Vec<(Tag, bool)>,
+    /// Values of variable font axes, such as wght or wdth
+    pub variations: Vec<(Tag, f32)>,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> TextStyle {
+        TextStyle {
+            family: "sans".to_string(),
+            size: size,
+            bold: false,
+            italic: false,
+            color: [1.0, 1.0, 1.0, 1.0],
+            language: None,
+            features: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+
+    pub fn with_family(mut self, family: &str) -> TextStyle {
+        self.family = family.to_string();
+        self
+    }
+
+    /// Turns an OpenType feature on or off. Features are applied with the font's GSUB table, so
+    /// ones the face doesn't have do nothing; see `Face::has_feature`.
+    pub fn with_feature(mut self, tag: Tag, enabled: bool) -> TextStyle {
+        self.features.retain(|&(t, _)| t != tag);
+        self.features.push((tag, enabled));
+        self
+    }
+
+    pub fn with_bold(mut self, bold: bool) -> TextStyle {
+        self.bold = bold;
+        self
+    }
+
+    pub fn with_italic(mut self, italic: bool) -> TextStyle {
+        self.italic = italic;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> TextStyle {
+        self.color = color;
+        self
+    }
+
+    pub fn with_language(mut self, language: &str) -> TextStyle {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    pub fn with_variation(mut self, tag: Tag, value: f32) -> TextStyle {
+        self.variations.retain(|&(t, _)| t != tag);
+        self.variations.push((tag, value));
+        self
+    }
+
+    /// Returns if a feature is turned on, or the default if the style doesn't set it
+    pub fn feature(&self, tag: Tag, default: bool) -> bool {
+        match self.features.iter().find(|&&(t, _)| t == tag) {
+            Some(&(_, enabled)) => enabled,
+            None => default,
+        }
+    }
+}
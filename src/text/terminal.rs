@@ -0,0 +1,674 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+use rusttype::{point, vector, PositionedGlyph, Rect, Scale};
+
+use gl_basic;
+use gl_basic::types::*;
+
+use text::color::Color;
+use text::font::{FaceId, FontSet, InstanceId, Synthesis};
+use text::style::TextStyle;
+use text::{
+    empty_quad, multiply, projection_matrix, push_quad, vec4, view_matrix, GlGlyphCache, Matrix,
+    RasterOptions, Vertex, DEFAULT_GAMMA, FRAG_SHADER_SOURCE, SOLID, VERT_SHADER_SRC,
+};
+
+/// Family name of the terminal font
+const MONO: &str = "mono";
+
+/// Family name of fonts loaded for characters the terminal font doesn't have
+const FALLBACK: &str = "fallback";
+
+/// Background color that isn't drawn, so whatever is behind the terminal shows through
+pub const TRANSPARENT: Color = [0.0, 0.0, 0.0, 0.0];
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub c: char,
+    /// Combining marks drawn over the character, in the order they were written
+    pub marks: [Option<char>; 2],
+    /// Columns the character takes up: 2 for a wide character, whose right half covers the next
+    /// cell, 0 for that covered cell, and 1 otherwise
+    pub width: u8,
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+impl Cell {
+    /// A space in white on the default background
+    pub fn blank() -> Cell {
+        Cell {
+            c: ' ',
+            marks: [None; 2],
+            width: 1,
+            fg: [1.0, 1.0, 1.0, 1.0],
+            bg: TRANSPARENT,
+            bold: false,
+            underline: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CursorShape {
+    Block,
+    Underline,
+    /// A thin line at the left of the cell
+    Bar,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Cursor {
+    pub col: usize,
+    pub row: usize,
+    pub shape: CursorShape,
+    /// Blinking is done by turning this on and off
+    pub visible: bool,
+    pub color: Color,
+}
+
+/// The cells of a terminal screen and its cursor. Rows that changed are remembered, so the
+/// renderer only has to update those.
+pub struct Grid {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    dirty: Vec<bool>,
+    cursor: Cursor,
+}
+
+impl Grid {
+    pub fn new(cols: usize, rows: usize) -> Grid {
+        Grid {
+            cols: cols,
+            rows: rows,
+            cells: vec![Cell::blank(); cols * rows],
+            dirty: vec![true; rows],
+            cursor: Cursor {
+                col: 0,
+                row: 0,
+                shape: CursorShape::Block,
+                visible: true,
+                color: [1.0, 1.0, 1.0, 1.0],
+            },
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cell(&self, col: usize, row: usize) -> &Cell {
+        &self.cells[row * self.cols + col]
+    }
+
+    pub fn set(&mut self, col: usize, row: usize, cell: Cell) {
+        self.cells[row * self.cols + col] = cell;
+        self.dirty[row] = true;
+    }
+
+    pub fn row(&self, row: usize) -> &[Cell] {
+        &self.cells[row * self.cols..(row + 1) * self.cols]
+    }
+
+    pub fn row_mut(&mut self, row: usize) -> &mut [Cell] {
+        self.dirty[row] = true;
+        &mut self.cells[row * self.cols..(row + 1) * self.cols]
+    }
+
+    /// Fills every cell with a cell
+    pub fn clear(&mut self, cell: Cell) {
+        for c in &mut self.cells {
+            *c = cell;
+        }
+        self.mark_all_dirty();
+    }
+
+    /// Changes the size of the grid, keeping the cells at the top left
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let mut cells = vec![Cell::blank(); cols * rows];
+        for row in 0..rows.min(self.rows) {
+            for col in 0..cols.min(self.cols) {
+                cells[row * cols + col] = self.cells[row * self.cols + col];
+            }
+        }
+        self.cells = cells;
+        self.cols = cols;
+        self.rows = rows;
+        self.dirty = vec![true; rows];
+        self.cursor.col = self.cursor.col.min(cols.saturating_sub(1));
+        self.cursor.row = self.cursor.row.min(rows.saturating_sub(1));
+    }
+
+    /// Moves a range of rows up by a number of rows. Rows moved out of the range are lost and
+    /// the rows uncovered at the bottom are filled with a blank cell.
+    pub fn scroll_up(&mut self, rows: Range<usize>, count: usize, blank: Cell) {
+        let count = count.min(rows.len());
+        let cols = self.cols;
+        for row in rows.clone() {
+            if row + count < rows.end {
+                let from = (row + count) * cols;
+                for col in 0..cols {
+                    self.cells[row * cols + col] = self.cells[from + col];
+                }
+            } else {
+                for cell in &mut self.cells[row * cols..(row + 1) * cols] {
+                    *cell = blank;
+                }
+            }
+            self.dirty[row] = true;
+        }
+    }
+
+    /// Moves a range of rows down by a number of rows, filling the top with a blank cell
+    pub fn scroll_down(&mut self, rows: Range<usize>, count: usize, blank: Cell) {
+        let count = count.min(rows.len());
+        let cols = self.cols;
+        for row in rows.clone().rev() {
+            if row >= rows.start + count {
+                let from = (row - count) * cols;
+                for col in 0..cols {
+                    self.cells[row * cols + col] = self.cells[from + col];
+                }
+            } else {
+                for cell in &mut self.cells[row * cols..(row + 1) * cols] {
+                    *cell = blank;
+                }
+            }
+            self.dirty[row] = true;
+        }
+    }
+
+    pub fn is_dirty(&self, row: usize) -> bool {
+        self.dirty[row]
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        for d in &mut self.dirty {
+            *d = true;
+        }
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    pub fn set_cursor(&mut self, cursor: Cursor) {
+        // a block cursor changes the color of the glyph under it
+        if let Some(d) = self.dirty.get_mut(self.cursor.row) {
+            *d = true;
+        }
+        self.cursor = Cursor {
+            col: cursor.col.min(self.cols.saturating_sub(1)),
+            row: cursor.row.min(self.rows.saturating_sub(1)),
+            ..cursor
+        };
+        if let Some(d) = self.dirty.get_mut(self.cursor.row) {
+            *d = true;
+        }
+    }
+}
+
+/// Combining marks drawn over a cell, as many as Cell::marks holds
+const MARKS: usize = 2;
+
+/// A glyph of a cell, with the font instance it is from
+struct CellGlyph<'font> {
+    col: usize,
+    /// 0 for the character of the cell, and 1 and up for its combining marks
+    layer: usize,
+    font_id: InstanceId,
+    glyph: PositionedGlyph<'font>,
+}
+
+/// Draws a Grid in DejaVu Sans Mono with fixed size cells. The vertex buffer has a slot for
+/// each quad of each cell, so only rows that changed are uploaded.
+pub struct GlTerminalRenderer<'font> {
+    cache: GlGlyphCache<'font>,
+    fonts: FontSet<'font>,
+    program: Rc<gl_basic::Program>,
+    object: gl_basic::Object,
+    size: (f32, f32),
+    view: Matrix,
+    gamma: f32,
+    contrast: f32,
+    font_size: f32,
+    /// Width and height of a cell, in whole physical pixels
+    cell: (f32, f32),
+    /// Distance from the top of a cell to the baseline
+    ascent: f32,
+    /// Columns and rows the vertex buffer has slots for
+    grid_size: (usize, usize),
+    /// Size of the cache texture when rows were last built. Glyphs move when it grows.
+    cache_dimensions: (u32, u32),
+    /// Faces of regular and bold text, and what is synthesized for them
+    styles: [(FaceId, Synthesis); 2],
+    /// The face and instance each character was found in, for regular and bold text
+    char_faces: HashMap<(char, bool), (FaceId, InstanceId)>,
+    /// Glyphs of each row, kept so rows that didn't change are queued without looking them up
+    row_glyphs: Vec<Vec<CellGlyph<'font>>>,
+}
+
+impl<'font> GlTerminalRenderer<'font> {
+    /// Creates a renderer for a screen of the given size in layout pixels, with text of a font
+    /// size
+    pub fn new(
+        screen_width: f32,
+        screen_height: f32,
+        font_size: f32,
+    ) -> Result<GlTerminalRenderer<'font>, String> {
+        let font_data = include_bytes!("../../fonts/dejavu/DejaVuSansMono.ttf");
+        let mut fonts = FontSet::new();
+        if let Err(e) = fonts.load(MONO, font_data as &[u8]) {
+            return Err(format!("terminal font: {}", e));
+        }
+
+        let program = match gl_basic::Program::compile(VERT_SHADER_SRC, FRAG_SHADER_SOURCE) {
+            Ok(p) => Rc::new(p),
+            Err(e) => return Err(format!("terminal shader: {}", e)),
+        };
+
+        let object = match Vertex::new_object(program.clone()) {
+            Ok(o) => o,
+            Err(e) => return Err(format!("terminal object: {}", e)),
+        };
+
+        let cache = match GlGlyphCache::new(RasterOptions::new(1.0)) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("terminal cache: {}", e)),
+        };
+
+        let style = |bold| {
+            let style = TextStyle::new(font_size).with_family(MONO).with_bold(bold);
+            fonts.select(&style)
+        };
+        let styles = match (style(false), style(true)) {
+            (Some(regular), Some(bold)) => [regular, bold],
+            _ => return Err("terminal font: no face was loaded".to_string()),
+        };
+
+        let mut renderer = GlTerminalRenderer {
+            cache: cache,
+            fonts: fonts,
+            program: program,
+            object: object,
+            size: (screen_width, screen_height),
+            view: view_matrix(vector(0.0, 0.0), 1.0),
+            gamma: DEFAULT_GAMMA,
+            contrast: 0.0,
+            font_size: font_size,
+            cell: (0.0, 0.0),
+            ascent: 0.0,
+            grid_size: (0, 0),
+            cache_dimensions: (0, 0),
+            styles: styles,
+            char_faces: HashMap::new(),
+            row_glyphs: Vec::new(),
+        };
+        renderer.measure_cells();
+        Ok(renderer)
+    }
+
+    /// Loads a font to fall back to for characters DejaVu Sans Mono doesn't have
+    pub fn load_fallback_font(&mut self, data: &'font [u8]) -> Result<FaceId, String> {
+        let face = self.fonts.load(FALLBACK, data)?;
+        // characters that were missing may be in the new font, so every row is looked up again
+        self.char_faces.clear();
+        self.grid_size = (0, 0);
+        Ok(face)
+    }
+
+    /// Sets the antialiasing and scale factor glyphs are rasterized with. Cells are measured
+    /// again, so the number of cells that fit on the screen may change.
+    pub fn set_raster_options(&mut self, options: RasterOptions) -> Result<(), String> {
+        if options != self.cache.options {
+            self.cache = GlGlyphCache::new(options)?;
+            self.measure_cells();
+            self.grid_size = (0, 0);
+        }
+        Ok(())
+    }
+
+    /// See GlGlyphRenderer::set_gamma
+    pub fn set_gamma(&mut self, gamma: f32, contrast: f32) {
+        self.gamma = gamma;
+        self.contrast = contrast;
+    }
+
+    pub fn set_screen_size(&mut self, screen_width: f32, screen_height: f32) {
+        self.size = (screen_width, screen_height);
+    }
+
+    /// See GlGlyphRenderer::set_view
+    pub fn set_view(&mut self, view: Matrix) {
+        self.view = view;
+    }
+
+    /// Width and height of a cell in layout pixels
+    pub fn cell_size(&self) -> (f32, f32) {
+        self.cell
+    }
+
+    /// Columns and rows of whole cells that fit in an area
+    pub fn grid_size_for(&self, width: f32, height: f32) -> (usize, usize) {
+        (
+            (width / self.cell.0).floor().max(1.0) as usize,
+            (height / self.cell.1).floor().max(1.0) as usize,
+        )
+    }
+
+    /// Measures cells from the advance of the font, rounded to physical pixels so every cell
+    /// starts on a pixel
+    fn measure_cells(&mut self) {
+        let factor = self.cache.options.scale_factor;
+        let round = |x: f32| (x * factor).round() / factor;
+        let font = &self.fonts.face(0).font;
+        let scale = Scale::uniform(self.font_size);
+        let v_metrics = font.v_metrics(scale);
+        let advance = font.glyph('M').scaled(scale).h_metrics().advance_width;
+        self.cell = (
+            round(advance).max(1.0 / factor),
+            round(v_metrics.ascent - v_metrics.descent + v_metrics.line_gap).max(1.0 / factor),
+        );
+        self.ascent = round(v_metrics.ascent + v_metrics.line_gap / 2.0);
+    }
+
+    /// The face a character is drawn from in regular or bold text, and its instance
+    fn face_for(&mut self, c: char, bold: bool) -> (FaceId, InstanceId) {
+        if let Some(&found) = self.char_faces.get(&(c, bold)) {
+            return found;
+        }
+        let (face, synthesis) = self.styles[bold as usize];
+        let face = self
+            .fonts
+            .fallbacks(face)
+            .into_iter()
+            .find(|&f| self.fonts.face(f).font.glyph(c).id().0 != 0)
+            .unwrap_or(face);
+        let found = (face, self.fonts.instance(face, &[], synthesis));
+        self.char_faces.insert((c, bold), found);
+        found
+    }
+
+    /// The glyphs of the characters and combining marks of a row
+    fn resolve_row(&mut self, grid: &Grid, row: usize) -> Vec<CellGlyph<'font>> {
+        let scale = Scale::uniform(self.font_size);
+        let baseline = row as f32 * self.cell.1 + self.ascent;
+        let mut glyphs = Vec::new();
+        for (col, cell) in grid.row(row).iter().enumerate() {
+            if cell.width == 0 {
+                continue;
+            }
+            let face = self.styles[cell.bold as usize].0;
+            let (found, font_id) = self.face_for(cell.c, cell.bold);
+            let base = self.fonts.font(font_id).glyph(cell.c).scaled(scale);
+            let advance = base.h_metrics().advance_width;
+            // glyphs from other fonts don't fit the cells exactly, so they are centered in them
+            let x = if found == face {
+                col as f32 * self.cell.0
+            } else {
+                (col as f32 + cell.width as f32 / 2.0) * self.cell.0 - advance / 2.0
+            };
+            if cell.c != ' ' && !cell.c.is_control() {
+                glyphs.push(CellGlyph {
+                    col: col,
+                    layer: 0,
+                    font_id: font_id,
+                    glyph: base.positioned(point(x, baseline)),
+                });
+            }
+            for (i, mark) in cell.marks.iter().enumerate() {
+                let mark = match *mark {
+                    Some(mark) => mark,
+                    None => continue,
+                };
+                let (_, font_id) = self.face_for(mark, cell.bold);
+                let glyph = self.fonts.font(font_id).glyph(mark).scaled(scale);
+                let mark_advance = glyph.h_metrics().advance_width;
+                // as in layouts, zero width marks are drawn from the pen position after the base,
+                // and marks with a width of their own are centered over it
+                let mark_x = if mark_advance != 0.0 {
+                    x + (advance - mark_advance) / 2.0
+                } else {
+                    x + advance
+                };
+                glyphs.push(CellGlyph {
+                    col: col,
+                    layer: 1 + i,
+                    font_id: font_id,
+                    glyph: glyph.positioned(point(mark_x, baseline)),
+                });
+            }
+        }
+        glyphs
+    }
+
+    /// Uploads the rows of a grid that changed since the last update, and the cursor
+    pub fn update(&mut self, grid: &mut Grid) {
+        let (cols, rows) = (grid.cols, grid.rows);
+        let cells = cols * rows;
+        // where each kind of quad starts in the vertex buffer: backgrounds, the cursor, glyphs,
+        // combining marks and underlines
+        let cursor_slot = cells;
+        let glyph_slots = cursor_slot + 1;
+        let mark_slots = glyph_slots + cells;
+        let underline_slots = mark_slots + cells * MARKS;
+        if self.grid_size != (cols, rows) {
+            let slots = underline_slots + cells;
+            let vertices: Vec<Vertex> = (0..slots).flat_map(|_| empty_quad().to_vec()).collect();
+            let indices: Vec<[gl::types::GLuint; 3]> = (0..slots as gl::types::GLuint)
+                .flat_map(|i| {
+                    let i = i * 4;
+                    vec![[i + 0, i + 1, i + 2], [i + 0, i + 2, i + 3]]
+                })
+                .collect();
+            Vertex::set_vertices(&mut self.object, vertices);
+            self.object.set_indices(indices);
+            self.grid_size = (cols, rows);
+            self.row_glyphs = (0..rows).map(|_| Vec::new()).collect();
+            grid.mark_all_dirty();
+        }
+
+        for row in 0..rows {
+            if grid.dirty[row] {
+                self.row_glyphs[row] = self.resolve_row(grid, row);
+            }
+        }
+        // every glyph on screen is queued, so none of them are evicted to make room for new ones
+        let queued: Vec<(usize, PositionedGlyph<'font>)> = self
+            .row_glyphs
+            .iter()
+            .flat_map(|glyphs| glyphs.iter().map(|g| (g.font_id, g.glyph.clone())))
+            .collect();
+        self.cache.cache_glyphs(&queued);
+        if self.cache.dimensions() != self.cache_dimensions {
+            self.cache_dimensions = self.cache.dimensions();
+            grid.mark_all_dirty();
+        }
+
+        let cursor = grid.cursor;
+        let (cell_width, cell_height) = self.cell;
+        let thickness = (self.font_size / 14.0).max(1.0 / self.cache.options.scale_factor);
+        let no_uv = Rect {
+            min: point(0.0, 0.0),
+            max: point(0.0, 0.0),
+        };
+        let solid = |rect: Rect<f32>, color: Color| {
+            let mut quads = Vec::with_capacity(1);
+            push_quad(&mut quads, corners(rect), no_uv, SOLID, vec4(color));
+            quads[0]
+        };
+        // the cursor covers both cells of a wide character
+        let cursor_cell = if cursor.col < cols && cursor.row < rows {
+            let mut col = cursor.col;
+            if col > 0 && grid.cells[cursor.row * cols + col].width == 0 {
+                col -= 1;
+            }
+            let width = grid.cells[cursor.row * cols + col].width.max(1) as usize;
+            Some((col, width))
+        } else {
+            None
+        };
+        for row in 0..rows {
+            if !grid.dirty[row] {
+                continue;
+            }
+            let mut backgrounds = vec![empty_quad(); cols];
+            let mut glyphs = vec![empty_quad(); cols];
+            let mut marks = vec![empty_quad(); cols * MARKS];
+            let mut underlines = vec![empty_quad(); cols];
+            let mut colors = vec![[0.0; 4]; cols];
+            for col in 0..cols {
+                let cell = grid.cells[row * cols + col];
+                if cell.width == 0 {
+                    continue;
+                }
+                let rect = Rect {
+                    min: point(col as f32 * cell_width, row as f32 * cell_height),
+                    max: point(
+                        (col + cell.width as usize) as f32 * cell_width,
+                        (row + 1) as f32 * cell_height,
+                    ),
+                };
+                if cell.bg[3] > 0.0 {
+                    backgrounds[col] = solid(rect, cell.bg);
+                }
+                // the glyph under a block cursor takes the color of the background
+                let under_block = cursor.visible
+                    && cursor.shape == CursorShape::Block
+                    && cursor.row == row
+                    && cursor_cell.map_or(false, |(c, _)| c == col);
+                colors[col] = if under_block {
+                    if cell.bg[3] > 0.0 {
+                        cell.bg
+                    } else {
+                        [0.0, 0.0, 0.0, 1.0]
+                    }
+                } else {
+                    cell.fg
+                };
+                if cell.underline {
+                    let top = rect.min.y + self.ascent + thickness;
+                    underlines[col] = solid(
+                        Rect {
+                            min: point(rect.min.x, top),
+                            max: point(rect.max.x, top + thickness),
+                        },
+                        colors[col],
+                    );
+                }
+            }
+            for g in &self.row_glyphs[row] {
+                let cached = match self.cache.rect_for(g.font_id, &g.glyph) {
+                    Some(cached) => cached,
+                    None => continue,
+                };
+                let tex_bounds = Vec4 {
+                    x: cached.uv_bounds.min.x,
+                    y: cached.uv_bounds.min.y,
+                    z: cached.uv_bounds.max.x,
+                    w: cached.uv_bounds.max.y,
+                };
+                let color = vec4(colors[g.col]);
+                let mut quads = Vec::with_capacity(1);
+                push_quad(
+                    &mut quads,
+                    corners(cached.screen),
+                    cached.uv,
+                    tex_bounds,
+                    color,
+                );
+                match g.layer {
+                    0 => glyphs[g.col] = quads[0],
+                    layer => marks[g.col * MARKS + layer - 1] = quads[0],
+                }
+            }
+            let flatten = |quads: Vec<[Vertex; 4]>| -> Vec<Vertex> {
+                quads.iter().flat_map(|q| q.iter().cloned()).collect()
+            };
+            let first = row * cols;
+            Vertex::update_vertices(&mut self.object, first * 4, &flatten(backgrounds));
+            Vertex::update_vertices(
+                &mut self.object,
+                (glyph_slots + first) * 4,
+                &flatten(glyphs),
+            );
+            Vertex::update_vertices(
+                &mut self.object,
+                (mark_slots + first * MARKS) * 4,
+                &flatten(marks),
+            );
+            Vertex::update_vertices(
+                &mut self.object,
+                (underline_slots + first) * 4,
+                &flatten(underlines),
+            );
+            grid.dirty[row] = false;
+        }
+
+        let cursor_quad = match cursor_cell {
+            Some((col, width)) if cursor.visible => {
+                let (x, y) = (col as f32 * cell_width, cursor.row as f32 * cell_height);
+                let width = width as f32 * cell_width;
+                let rect = match cursor.shape {
+                    CursorShape::Block => Rect {
+                        min: point(x, y),
+                        max: point(x + width, y + cell_height),
+                    },
+                    CursorShape::Underline => Rect {
+                        min: point(x, y + cell_height - thickness * 2.0),
+                        max: point(x + width, y + cell_height),
+                    },
+                    CursorShape::Bar => Rect {
+                        min: point(x, y),
+                        max: point(x + thickness * 2.0, y + cell_height),
+                    },
+                };
+                solid(rect, cursor.color)
+            }
+            _ => empty_quad(),
+        };
+        Vertex::update_vertices(&mut self.object, cursor_slot * 4, &cursor_quad);
+    }
+
+    pub fn draw(&self) {
+        let transform = multiply(&projection_matrix(self.size), &self.view);
+        self.cache.draw(
+            &self.program,
+            &self.object,
+            &transform,
+            self.gamma,
+            self.contrast,
+        );
+    }
+}
+
+/// Corners of a rect in the order push_quad takes them
+fn corners(rect: Rect<f32>) -> [Vec2; 4] {
+    [
+        Vec2 {
+            x: rect.min.x,
+            y: rect.max.y,
+        },
+        Vec2 {
+            x: rect.min.x,
+            y: rect.min.y,
+        },
+        Vec2 {
+            x: rect.max.x,
+            y: rect.min.y,
+        },
+        Vec2 {
+            x: rect.max.x,
+            y: rect.max.y,
+        },
+    ]
+}
@@ -0,0 +1,181 @@
+use rusttype::{point, vector, Point, Vector};
+
+use text::color::Color;
+use text::layout::{Layout, WritingMode};
+
+/// Number of straight segments Bézier curves are flattened into
+const CURVE_SEGMENTS: usize = 16;
+
+/// A change to how a cluster of glyphs is drawn, applied about the center of the cluster on its
+/// baseline. Marks and color layers move with their base glyph.
+#[derive(Clone, Copy, PartialEq)]
+pub struct GlyphTransform {
+    pub offset: Vector<f32>,
+    /// Angle in radians, clockwise since y is down
+    pub rotation: f32,
+    pub scale: f32,
+    /// Color the glyphs are drawn in instead of their own
+    pub color: Option<Color>,
+    /// Multiplies the alpha of the color
+    pub opacity: f32,
+}
+
+impl GlyphTransform {
+    pub fn identity() -> GlyphTransform {
+        GlyphTransform {
+            offset: vector(0.0, 0.0),
+            rotation: 0.0,
+            scale: 1.0,
+            color: None,
+            opacity: 1.0,
+        }
+    }
+
+    /// Moves a point that is relative to the center of the cluster
+    pub fn apply(&self, p: Vector<f32>) -> Vector<f32> {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (x, y) = (p.x * self.scale, p.y * self.scale);
+        vector(
+            x * cos - y * sin + self.offset.x,
+            x * sin + y * cos + self.offset.y,
+        )
+    }
+}
+
+/// A path made of straight lines and Bézier curves, which curves are flattened into
+pub struct Path {
+    points: Vec<Point<f32>>,
+    /// Distance along the path of each point
+    distances: Vec<f32>,
+}
+
+impl Path {
+    pub fn new(start: Point<f32>) -> Path {
+        Path {
+            points: vec![start],
+            distances: vec![0.0],
+        }
+    }
+
+    /// A path through a list of points
+    pub fn polyline(points: &[Point<f32>]) -> Path {
+        let mut path = Path::new(points.first().cloned().unwrap_or(point(0.0, 0.0)));
+        for &p in points.iter().skip(1) {
+            path = path.line_to(p);
+        }
+        path
+    }
+
+    pub fn line_to(mut self, p: Point<f32>) -> Path {
+        let last = self.points[self.points.len() - 1];
+        let distance = self.distances[self.distances.len() - 1] + length(p - last);
+        self.points.push(p);
+        self.distances.push(distance);
+        self
+    }
+
+    pub fn quad_to(self, control: Point<f32>, p: Point<f32>) -> Path {
+        let start = self.points[self.points.len() - 1];
+        self.flatten(
+            |t| {
+                let u = 1.0 - t;
+                (u * u, 2.0 * u * t, t * t, 0.0)
+            },
+            [start, control, p, p],
+        )
+    }
+
+    pub fn cubic_to(self, control1: Point<f32>, control2: Point<f32>, p: Point<f32>) -> Path {
+        let start = self.points[self.points.len() - 1];
+        self.flatten(
+            |t| {
+                let u = 1.0 - t;
+                (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t)
+            },
+            [start, control1, control2, p],
+        )
+    }
+
+    /// Adds a curve as straight segments, given the weights of its four points at each t
+    fn flatten<F>(mut self, weights: F, points: [Point<f32>; 4]) -> Path
+    where
+        F: Fn(f32) -> (f32, f32, f32, f32),
+    {
+        for i in 1..CURVE_SEGMENTS + 1 {
+            let (a, b, c, d) = weights(i as f32 / CURVE_SEGMENTS as f32);
+            self = self.line_to(point(
+                a * points[0].x + b * points[1].x + c * points[2].x + d * points[3].x,
+                a * points[0].y + b * points[1].y + c * points[2].y + d * points[3].y,
+            ));
+        }
+        self
+    }
+
+    /// Distance from the start to the end of the path
+    pub fn length(&self) -> f32 {
+        self.distances[self.distances.len() - 1]
+    }
+
+    /// Returns the point a distance along the path and the angle of the path there, or None if
+    /// the distance is off either end
+    pub fn point_at(&self, distance: f32) -> Option<(Point<f32>, f32)> {
+        if distance < 0.0 || distance > self.length() || self.points.len() < 2 {
+            return None;
+        }
+        let i = match self.distances.iter().position(|&d| d > distance) {
+            Some(i) => i,
+            None => self.points.len() - 1,
+        };
+        let (a, b) = (self.points[i - 1], self.points[i]);
+        let segment = self.distances[i] - self.distances[i - 1];
+        let t = if segment > 0.0 {
+            (distance - self.distances[i - 1]) / segment
+        } else {
+            0.0
+        };
+        let d = b - a;
+        Some((point(a.x + d.x * t, a.y + d.y * t), d.y.atan2(d.x)))
+    }
+}
+
+fn length(v: Vector<f32>) -> f32 {
+    (v.x * v.x + v.y * v.y).sqrt()
+}
+
+/// Returns transforms that place each cluster of a horizontal layout along a path, starting a
+/// distance along it. Lines after the first follow the path at their distance below the first
+/// baseline, and clusters that run off the end of the path are hidden.
+pub fn along_path(layout: &Layout, path: &Path, start: f32) -> Vec<GlyphTransform> {
+    let mut transforms = vec![GlyphTransform::identity(); layout.clusters.len()];
+    if layout.writing_mode != WritingMode::Horizontal {
+        return transforms;
+    }
+    let first_baseline = match layout.lines.first() {
+        Some(line) => line.baseline,
+        None => return transforms,
+    };
+    for line in &layout.lines {
+        let below = line.baseline - first_baseline;
+        for i in line.clusters.clone() {
+            let c = &layout.clusters[i];
+            let center = point(c.pos + c.advance / 2.0, line.baseline);
+            transforms[i] = match path.point_at(start + c.pos + c.advance / 2.0) {
+                Some((p, angle)) => {
+                    // below the path is to its right, since y is down
+                    let (sin, cos) = angle.sin_cos();
+                    let target = point(p.x - below * sin, p.y + below * cos);
+                    GlyphTransform {
+                        offset: target - center,
+                        rotation: angle,
+                        ..GlyphTransform::identity()
+                    }
+                }
+                None => GlyphTransform {
+                    scale: 0.0,
+                    ..GlyphTransform::identity()
+                },
+            };
+        }
+    }
+    transforms
+}
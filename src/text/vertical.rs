@@ -0,0 +1,70 @@
+//! Character properties for vertical text, roughly following UAX #50
+
+use text::color;
+
+/// Returns if a character stays upright in vertical text, rather than being turned sideways
+pub fn is_upright(c: char) -> bool {
+    match c as u32 {
+        // prolonged sound mark and wave dash follow the direction of the text
+        0x30FC | 0x301C => false,
+        0x1100..=0x11FF
+        | 0x2E80..=0x2FFF
+        | 0x3000..=0x303F
+        | 0x3040..=0x30FF
+        | 0x3100..=0x31FF
+        | 0x3200..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA960..=0xA97F
+        | 0xAC00..=0xD7FF
+        | 0xF900..=0xFAFF
+        | 0xFE10..=0xFE1F
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFF => true,
+        _ => color::is_emoji(c),
+    }
+}
+
+/// Returns the vertical presentation form of punctuation, which fonts without a vert feature
+/// often still have glyphs for
+pub fn vertical_form(c: char) -> Option<char> {
+    Some(match c {
+        '，' => '︐',
+        '、' => '︑',
+        '。' => '︒',
+        '：' => '︓',
+        '；' => '︔',
+        '！' => '︕',
+        '？' => '︖',
+        '…' => '︙',
+        '‥' => '︰',
+        '—' => '︱',
+        '–' => '︲',
+        '（' => '︵',
+        '）' => '︶',
+        '｛' => '︷',
+        '｝' => '︸',
+        '〔' => '︹',
+        '〕' => '︺',
+        '【' => '︻',
+        '】' => '︼',
+        '《' => '︽',
+        '》' => '︾',
+        '〈' => '︿',
+        '〉' => '﹀',
+        '「' => '﹁',
+        '」' => '﹂',
+        '『' => '﹃',
+        '』' => '﹄',
+        _ => return None,
+    })
+}
+
+/// Returns if a run of sideways text is a short number that should be set horizontally within a
+/// single column (tate-chu-yoko)
+pub fn is_tate_chu_yoko(run: &str) -> bool {
+    let count = run.chars().count();
+    count > 0 && count <= 2 && run.chars().all(|c| c.is_digit(10))
+}
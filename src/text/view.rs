@@ -0,0 +1,387 @@
+use std::ops::Range;
+
+use rusttype::{point, vector, Point};
+
+use text::font::FontSet;
+use text::layout::{self, Layout, LayoutOptions};
+use text::style::TextStyle;
+use text::{view_matrix, GlGlyphRenderer};
+
+/// Fraction of the scrolling speed that is kept after a second of kinetic scrolling
+const FRICTION: f32 = 0.05;
+
+/// Speed in pixels per second below which kinetic scrolling stops
+const MIN_VELOCITY: f32 = 10.0;
+
+/// Estimated height of a paragraph relative to the font size, until one has been measured
+const LINE_HEIGHT: f32 = 1.2;
+
+/// A scrolling view of a long text, such as a log file, that only lays out the paragraphs on
+/// screen and a margin around them. Paragraphs that haven't been laid out are given the height
+/// of a single line until they are.
+///
+/// Scrolling within the margin only moves the view of the renderer. The text is laid out and
+/// uploaded again when the screen runs out of laid out paragraphs.
+pub struct TextView {
+    text: String,
+    /// Byte range of each paragraph
+    paragraphs: Vec<Range<usize>>,
+    style: TextStyle,
+    options: LayoutOptions,
+    heights: Heights,
+    /// Height given to paragraphs that haven't been laid out
+    estimate: f64,
+    /// If the estimate is still a guess from the font size
+    estimate_guessed: bool,
+    /// Paragraph at the top of the screen, and how far the top of the screen is below its top.
+    /// Scrolling is kept relative to this, so that paragraphs above it being measured don't
+    /// make the text jump.
+    anchor: (usize, f64),
+    viewport_height: f32,
+    /// Distance beyond the screen that is laid out, above and below
+    pub margin: f32,
+    /// Speed of kinetic scrolling in pixels per second, positive when scrolling down
+    velocity: f32,
+    /// Paragraphs that are laid out in the renderer, or None if it has to be updated
+    window: Option<Range<usize>>,
+}
+
+impl TextView {
+    pub fn new(
+        text: &str,
+        style: TextStyle,
+        options: LayoutOptions,
+        viewport_height: f32,
+    ) -> TextView {
+        let estimate = (style.size * LINE_HEIGHT) as f64;
+        let paragraphs = layout::hard_lines(text);
+        TextView {
+            heights: Heights::new(paragraphs.len(), estimate),
+            text: text.to_string(),
+            paragraphs: paragraphs,
+            style: style,
+            options: options,
+            estimate: estimate,
+            estimate_guessed: true,
+            anchor: (0, 0.0),
+            viewport_height: viewport_height,
+            margin: viewport_height,
+            velocity: 0.0,
+            window: None,
+        }
+    }
+
+    pub fn paragraph_count(&self) -> usize {
+        self.paragraphs.len()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Byte range of a paragraph in the text, not including its separator
+    pub fn paragraph_range(&self, paragraph: usize) -> Range<usize> {
+        self.paragraphs[paragraph].clone()
+    }
+
+    pub fn style(&self) -> &TextStyle {
+        &self.style
+    }
+
+    pub fn options(&self) -> &LayoutOptions {
+        &self.options
+    }
+
+    /// Replaces the text, as after an edit, keeping the paragraph at the top of the screen where
+    /// it is. Any paragraph may have changed, so they all go back to estimated heights until
+    /// they are laid out again.
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.paragraphs = layout::hard_lines(text);
+        self.forget_heights();
+        self.window = None;
+        self.clamp();
+    }
+
+    /// Lays out the paragraphs again on the next update, when something they are laid out with
+    /// changed
+    pub fn relayout(&mut self) {
+        self.window = None;
+    }
+
+    /// Sets the size of the screen. A new width lays out everything again.
+    pub fn set_viewport(&mut self, width: f32, height: f32) {
+        if width != self.options.width {
+            self.options.width = width;
+            self.forget_heights();
+        }
+        self.viewport_height = height;
+        self.window = None;
+        self.clamp();
+    }
+
+    /// Height of the whole text, partly estimated. Positions in the text are f64, since f32
+    /// can't tell apart positions a pixel apart millions of lines down.
+    pub fn content_height(&self) -> f64 {
+        self.heights.sum(self.paragraphs.len())
+    }
+
+    /// Distance from the top of the text to the top of the screen
+    pub fn scroll_position(&self) -> f64 {
+        self.heights.sum(self.anchor.0) + self.anchor.1
+    }
+
+    /// Scrolls so a distance from the top of the text is at the top of the screen
+    pub fn scroll_to(&mut self, y: f64) {
+        self.anchor = self.heights.find(y);
+        self.clamp();
+    }
+
+    /// Scrolls down by a distance, or up if it is negative
+    pub fn scroll_by(&mut self, dy: f32) {
+        let y = self.scroll_position() + dy as f64;
+        self.scroll_to(y);
+    }
+
+    /// Scrolls so a paragraph is at the top of the screen
+    pub fn scroll_to_paragraph(&mut self, paragraph: usize) {
+        self.anchor = (paragraph.min(self.paragraphs.len().saturating_sub(1)), 0.0);
+        self.clamp();
+    }
+
+    /// Starts kinetic scrolling, as when a drag is released, at a speed in pixels per second
+    pub fn fling(&mut self, velocity: f32) {
+        self.velocity = velocity;
+    }
+
+    /// Stops kinetic scrolling, as when the text is touched
+    pub fn stop(&mut self) {
+        self.velocity = 0.0;
+    }
+
+    /// Advances kinetic scrolling by a number of seconds, returning if it is still moving
+    pub fn animate(&mut self, dt: f32) -> bool {
+        if self.velocity == 0.0 {
+            return false;
+        }
+        let decay = FRICTION.powf(dt);
+        // distance covered while the speed decays exponentially over dt
+        let distance = self.velocity * (decay - 1.0) / FRICTION.ln();
+        self.velocity *= decay;
+        if self.velocity.abs() < MIN_VELOCITY {
+            self.velocity = 0.0;
+        }
+        self.scroll_by(distance);
+        self.velocity != 0.0
+    }
+
+    /// Converts a point on the screen to a point in the layout of the renderer, for hit testing
+    pub fn layout_point(&self, p: Point<f32>) -> Point<f32> {
+        point(p.x, p.y + self.window_offset())
+    }
+
+    /// Lays out the paragraphs around the screen into the renderer if they aren't already, and
+    /// moves its view to the scroll position
+    pub fn update<'font>(&mut self, renderer: &mut GlGlyphRenderer<'font>) {
+        self.update_with(renderer, |fonts, view, i| {
+            let text = &view.text[view.paragraphs[i].clone()];
+            layout::layout_paragraph(fonts, text, &view.style, &view.options)
+        });
+    }
+
+    /// Like update, for text that isn't in a single style. Paragraphs are laid out by a function
+    /// given the view and the index of the paragraph.
+    pub fn update_with<'font, F>(&mut self, renderer: &mut GlGlyphRenderer<'font>, mut lay_out: F)
+    where
+        F: FnMut(&mut FontSet<'font>, &TextView, usize) -> Layout<'font>,
+    {
+        let visible = self.range_below(self.anchor.0, self.anchor.1, 0.0);
+        let laid_out = match self.window {
+            Some(ref window) => window.start <= visible.start && visible.end <= window.end,
+            None => false,
+        };
+        if !laid_out {
+            let mut start = self.anchor.0;
+            let mut above = self.anchor.1;
+            while start > 0 && above < self.margin as f64 {
+                start -= 1;
+                above += self.heights.get(start);
+            }
+            let end = self
+                .range_below(self.anchor.0, self.anchor.1, self.margin)
+                .end;
+            let mut layout: Option<Layout<'font>> = None;
+            let mut y = 0.0f32;
+            for i in start..end {
+                let paragraph = lay_out(renderer.fonts_mut(), self, i);
+                let height = match (paragraph.lines.first(), paragraph.lines.last()) {
+                    (Some(first), Some(last)) => last.extent.end - first.extent.start,
+                    _ => self.estimate as f32,
+                };
+                self.measure(i, height as f64);
+                match layout {
+                    Some(ref mut layout) => layout.append(paragraph, y),
+                    None => layout = Some(paragraph),
+                }
+                y += height;
+            }
+            if let Some(layout) = layout {
+                renderer.set_layout(layout);
+            }
+            self.window = Some(start..end);
+            // measuring may have changed the height of the text
+            self.clamp();
+        }
+        renderer.set_view(view_matrix(vector(0.0, -self.window_offset()), 1.0));
+    }
+
+    /// Distance from the top of the laid out paragraphs to the top of the screen, which is small
+    /// enough for f32 however far down the text they are
+    fn window_offset(&self) -> f32 {
+        match self.window {
+            Some(ref window) => {
+                let between = self.heights.range_sum(window.start, self.anchor.0);
+                (between + self.anchor.1) as f32
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Paragraphs that cover the screen and a distance below it, starting a distance down a
+    /// paragraph
+    fn range_below(&self, start: usize, offset: f64, margin: f32) -> Range<usize> {
+        let mut end = start;
+        let mut bottom = -offset;
+        while end < self.paragraphs.len() && bottom < (self.viewport_height + margin) as f64 {
+            bottom += self.heights.get(end);
+            end += 1;
+        }
+        start..end
+    }
+
+    /// Records the height of a paragraph that was laid out
+    fn measure(&mut self, paragraph: usize, height: f64) {
+        if self.estimate_guessed {
+            // the first paragraph measured is the best guess at the height of a line, which
+            // affects every other paragraph, so estimates are only changed this once
+            self.estimate_guessed = false;
+            self.estimate = height;
+            self.heights = Heights::new(self.paragraphs.len(), height);
+        }
+        let old = self.heights.get(paragraph);
+        if old != height {
+            self.heights.add(paragraph, height - old);
+        }
+    }
+
+    /// Goes back to estimated heights, when the width of the text changes
+    fn forget_heights(&mut self) {
+        let paragraph = self.anchor.0.min(self.paragraphs.len().saturating_sub(1));
+        self.heights = Heights::new(self.paragraphs.len(), self.estimate);
+        self.anchor = (paragraph, 0.0);
+    }
+
+    /// Keeps the screen within the text, and stops kinetic scrolling at either end
+    fn clamp(&mut self) {
+        let max = (self.content_height() - self.viewport_height as f64).max(0.0);
+        let y = self.scroll_position();
+        if y < 0.0 || y > max {
+            self.anchor = self.heights.find(y.max(0.0).min(max));
+            self.velocity = 0.0;
+        }
+    }
+}
+
+/// Heights of paragraphs in a Fenwick tree, so the height of the text above any paragraph and
+/// the paragraph at any height can be found in logarithmic time
+struct Heights {
+    /// 1-based tree, where element i sums the heights of the i & -i paragraphs ending at i
+    tree: Vec<f64>,
+}
+
+impl Heights {
+    /// Heights for a number of paragraphs that all have the same height
+    fn new(count: usize, height: f64) -> Heights {
+        let mut tree = vec![height; count + 1];
+        tree[0] = 0.0;
+        for i in 1..count + 1 {
+            let parent = i + (i & i.wrapping_neg());
+            if parent <= count {
+                tree[parent] += tree[i];
+            }
+        }
+        Heights { tree: tree }
+    }
+
+    fn len(&self) -> usize {
+        self.tree.len() - 1
+    }
+
+    /// Total height of the first count paragraphs
+    fn sum(&self, count: usize) -> f64 {
+        let mut i = count.min(self.len());
+        let mut sum = 0.0;
+        while i > 0 {
+            sum += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+
+    /// Total height of the paragraphs from start up to end, which is exact even when the sums
+    /// above them are large, since only the nodes that differ are added up
+    fn range_sum(&self, start: usize, end: usize) -> f64 {
+        let (mut i, mut j) = (end.min(self.len()), start.min(self.len()));
+        let mut sum = 0.0;
+        // the paths from i and j down meet at the node both sums share
+        while i != j {
+            if i > j {
+                sum += self.tree[i];
+                i -= i & i.wrapping_neg();
+            } else {
+                sum -= self.tree[j];
+                j -= j & j.wrapping_neg();
+            }
+        }
+        sum
+    }
+
+    fn get(&self, paragraph: usize) -> f64 {
+        self.range_sum(paragraph, paragraph + 1)
+    }
+
+    fn add(&mut self, paragraph: usize, delta: f64) {
+        let mut i = paragraph + 1;
+        while i <= self.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Returns the paragraph at a height from the top, and how far the height is below its top
+    fn find(&self, y: f64) -> (usize, f64) {
+        let len = self.len();
+        if len == 0 {
+            return (0, 0.0);
+        }
+        let mut step = 1;
+        while step * 2 <= len {
+            step *= 2;
+        }
+        // find the most paragraphs that fit above y
+        let (mut i, mut above) = (0, 0.0);
+        while step > 0 {
+            if i + step <= len && above + self.tree[i + step] <= y {
+                i += step;
+                above += self.tree[i];
+            }
+            step /= 2;
+        }
+        if i == len {
+            // below the end, so stay on the last paragraph
+            i = len - 1;
+            above = self.sum(i);
+        }
+        (i, y - above)
+    }
+}
@@ -0,0 +1,1258 @@
+//! A VT100/xterm escape sequence parser and the terminal state it drives, as in the state
+//! machine of https://vt100.net/emu/dec_ansi_parser. The parser is separate from the terminal so
+//! byte streams can be checked against the actions they produce.
+
+extern crate unicode_normalization;
+
+use std::char;
+use std::mem;
+use std::ops::Range;
+
+use self::unicode_normalization::char::is_combining_mark;
+
+use text::color::{self, Color};
+use text::terminal::{Cell, Cursor, CursorShape, Grid, TRANSPARENT};
+
+const REPLACEMENT: char = '\u{FFFD}';
+
+/// Columns between the default tab stops
+const TAB_WIDTH: usize = 8;
+
+/// Something a byte stream asks the terminal to do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Print(char),
+    /// A C0 control character, such as a line feed
+    Execute(u8),
+    /// A control sequence, ESC [ followed by parameters, intermediates and a final byte. Each
+    /// parameter comes with the sub-parameters after it separated by colons, as in 38:2:r:g:b.
+    /// Private markers such as `?` are the first intermediates, and missing parameters are 0.
+    Csi {
+        params: Vec<Vec<u32>>,
+        intermediates: Vec<u8>,
+        final_byte: u8,
+    },
+    Esc {
+        intermediates: Vec<u8>,
+        final_byte: u8,
+    },
+    /// An operating system command, split at semicolons
+    Osc(Vec<Vec<u8>>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiParam,
+    /// A malformed control sequence, which is skipped up to its final byte
+    CsiIgnore,
+    Osc,
+    /// DCS, SOS, PM and APC strings, which are skipped
+    IgnoredString,
+    /// An ESC in a string, which ends it, and whether the string is an OSC
+    StringEscape(bool),
+}
+
+/// Turns bytes into actions. Text is decoded as UTF-8, and 8-bit C1 controls aren't supported.
+pub struct Parser {
+    state: State,
+    params: Vec<Vec<u32>>,
+    /// Sub-parameters read so far of the parameter being read
+    group: Vec<u32>,
+    /// The parameter or sub-parameter being read, if it has any digits yet
+    param: Option<u32>,
+    intermediates: Vec<u8>,
+    osc: Vec<u8>,
+    /// The code point being decoded and the number of continuation bytes it still needs
+    utf8: (u32, usize),
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            params: Vec::new(),
+            group: Vec::new(),
+            param: None,
+            intermediates: Vec::new(),
+            osc: Vec::new(),
+            utf8: (0, 0),
+        }
+    }
+
+    /// Returns the actions of some bytes. Sequences may be split between calls.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for &b in bytes {
+            self.advance(b, &mut actions);
+        }
+        actions
+    }
+
+    fn advance(&mut self, b: u8, actions: &mut Vec<Action>) {
+        // strings take every byte up to BEL or ST, which is ESC \
+        match self.state {
+            State::Osc => {
+                match b {
+                    0x07 => {
+                        self.dispatch_osc(actions);
+                        self.state = State::Ground;
+                    }
+                    0x1B => self.state = State::StringEscape(true),
+                    _ => self.osc.push(b),
+                }
+                return;
+            }
+            State::IgnoredString => {
+                match b {
+                    0x07 => self.state = State::Ground,
+                    0x1B => self.state = State::StringEscape(false),
+                    _ => (),
+                }
+                return;
+            }
+            State::StringEscape(osc) => {
+                if osc {
+                    self.dispatch_osc(actions);
+                }
+                self.state = State::Ground;
+                if b != b'\\' {
+                    // the ESC starts a sequence of its own
+                    self.start_escape();
+                    self.advance(b, actions);
+                }
+                return;
+            }
+            _ => (),
+        }
+        match b {
+            // CAN and SUB cancel a sequence
+            0x18 | 0x1A => {
+                self.state = State::Ground;
+                return;
+            }
+            0x1B => {
+                self.start_escape();
+                return;
+            }
+            // other C0 controls are carried out in the middle of sequences too
+            0x00..=0x1F => {
+                self.utf8 = (0, 0);
+                actions.push(Action::Execute(b));
+                return;
+            }
+            0x7F => return,
+            _ => (),
+        }
+        match self.state {
+            State::Ground => self.print(b, actions),
+            State::Escape | State::EscapeIntermediate => match b {
+                0x20..=0x2F => {
+                    self.intermediates.push(b);
+                    self.state = State::EscapeIntermediate;
+                }
+                b'[' if self.state == State::Escape => self.state = State::CsiParam,
+                b']' if self.state == State::Escape => {
+                    self.osc.clear();
+                    self.state = State::Osc;
+                }
+                b'P' | b'X' | b'^' | b'_' if self.state == State::Escape => {
+                    self.state = State::IgnoredString
+                }
+                0x30..=0x7E => {
+                    actions.push(Action::Esc {
+                        intermediates: mem::replace(&mut self.intermediates, Vec::new()),
+                        final_byte: b,
+                    });
+                    self.state = State::Ground;
+                }
+                _ => self.state = State::Ground,
+            },
+            State::CsiParam => match b {
+                b'0'..=b'9' => {
+                    let digit = (b - b'0') as u32;
+                    let param = self.param.unwrap_or(0);
+                    self.param = Some(param.saturating_mul(10).saturating_add(digit));
+                }
+                b':' => self.group.push(self.param.take().unwrap_or(0)),
+                b';' => self.end_param(),
+                b'<'..=b'?'
+                    if self.params.is_empty()
+                        && self.group.is_empty()
+                        && self.param.is_none()
+                        && self.intermediates.is_empty() =>
+                {
+                    self.intermediates.push(b)
+                }
+                0x20..=0x2F => self.intermediates.push(b),
+                0x40..=0x7E => {
+                    if self.param.is_some() || !self.group.is_empty() || !self.params.is_empty() {
+                        self.end_param();
+                    }
+                    actions.push(Action::Csi {
+                        params: mem::replace(&mut self.params, Vec::new()),
+                        intermediates: mem::replace(&mut self.intermediates, Vec::new()),
+                        final_byte: b,
+                    });
+                    self.state = State::Ground;
+                }
+                _ => self.state = State::CsiIgnore,
+            },
+            State::CsiIgnore => {
+                if b >= 0x40 && b <= 0x7E {
+                    self.state = State::Ground;
+                }
+            }
+            State::Osc | State::IgnoredString | State::StringEscape(_) => unreachable!(),
+        }
+    }
+
+    fn end_param(&mut self) {
+        self.group.push(self.param.take().unwrap_or(0));
+        self.params.push(mem::replace(&mut self.group, Vec::new()));
+    }
+
+    fn start_escape(&mut self) {
+        self.state = State::Escape;
+        self.params.clear();
+        self.group.clear();
+        self.param = None;
+        self.intermediates.clear();
+        self.utf8 = (0, 0);
+    }
+
+    fn dispatch_osc(&mut self, actions: &mut Vec<Action>) {
+        let osc = mem::replace(&mut self.osc, Vec::new());
+        actions.push(Action::Osc(
+            osc.split(|&b| b == b';')
+                .map(|part| part.to_vec())
+                .collect(),
+        ));
+    }
+
+    /// Decodes a byte of UTF-8 text
+    fn print(&mut self, b: u8, actions: &mut Vec<Action>) {
+        let (code, remaining) = self.utf8;
+        if remaining > 0 {
+            if b & 0xC0 == 0x80 {
+                let code = code << 6 | (b & 0x3F) as u32;
+                self.utf8 = (code, remaining - 1);
+                if remaining == 1 {
+                    let c = char::from_u32(code).unwrap_or(REPLACEMENT);
+                    actions.push(Action::Print(c));
+                }
+                return;
+            }
+            // the sequence was cut short
+            actions.push(Action::Print(REPLACEMENT));
+            self.utf8 = (0, 0);
+        }
+        match b {
+            0x00..=0x7F => actions.push(Action::Print(b as char)),
+            0xC2..=0xDF => self.utf8 = ((b & 0x1F) as u32, 1),
+            0xE0..=0xEF => self.utf8 = ((b & 0x0F) as u32, 2),
+            0xF0..=0xF4 => self.utf8 = ((b & 0x07) as u32, 3),
+            _ => actions.push(Action::Print(REPLACEMENT)),
+        }
+    }
+}
+
+/// Attributes text is written with
+#[derive(Clone, Copy, PartialEq)]
+struct Pen {
+    /// None for the default color
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    underline: bool,
+    inverse: bool,
+}
+
+impl Pen {
+    fn new() -> Pen {
+        Pen {
+            fg: None,
+            bg: None,
+            bold: false,
+            underline: false,
+            inverse: false,
+        }
+    }
+}
+
+/// What DECSC saves and DECRC restores
+#[derive(Clone, Copy)]
+struct SavedCursor {
+    col: usize,
+    row: usize,
+    pen: Pen,
+    origin_mode: bool,
+    line_drawing: bool,
+}
+
+/// A terminal screen that escape sequences are carried out on. Output from a pty is passed to
+/// feed, and the grid is drawn with a GlTerminalRenderer.
+pub struct Terminal {
+    parser: Parser,
+    /// The screen being shown
+    grid: Grid,
+    /// The primary screen while the alternate screen is shown, and the other way around
+    other_grid: Grid,
+    alternate_screen: bool,
+    col: usize,
+    row: usize,
+    /// Set after writing in the last column, so the next character goes on the next line
+    wrap_pending: bool,
+    pen: Pen,
+    saved: SavedCursor,
+    /// Rows that scroll, the whole screen unless set by DECSTBM
+    scroll_top: usize,
+    scroll_bottom: usize,
+    /// If cursor rows are relative to the scroll region
+    origin_mode: bool,
+    autowrap: bool,
+    tab_stops: Vec<bool>,
+    /// If the DEC special graphics set is in use, which draws lines with letters
+    line_drawing: bool,
+    last_char: char,
+    cursor_visible: bool,
+    cursor_shape: CursorShape,
+    /// Colors of the 256 color palette. The first 16 are the named colors of SGR 30 to 37 and
+    /// 90 to 97.
+    pub palette: [Color; 256],
+    /// Color of text with the default color
+    pub foreground: Color,
+    /// Color of the background behind the terminal, which inverse text is drawn in
+    pub background: Color,
+    pub cursor_color: Color,
+    /// Set by OSC 0 and 2
+    pub title: String,
+    /// If arrow keys should send application sequences (ESC O A rather than ESC [ A)
+    pub application_cursor_keys: bool,
+    /// Replies to queries such as the cursor position, to be written back to the pty
+    responses: Vec<u8>,
+}
+
+impl Terminal {
+    pub fn new(cols: usize, rows: usize) -> Terminal {
+        let foreground = [0.9, 0.9, 0.9, 1.0];
+        Terminal {
+            parser: Parser::new(),
+            grid: Grid::new(cols, rows),
+            other_grid: Grid::new(cols, rows),
+            alternate_screen: false,
+            col: 0,
+            row: 0,
+            wrap_pending: false,
+            pen: Pen::new(),
+            saved: SavedCursor {
+                col: 0,
+                row: 0,
+                pen: Pen::new(),
+                origin_mode: false,
+                line_drawing: false,
+            },
+            scroll_top: 0,
+            scroll_bottom: rows,
+            origin_mode: false,
+            autowrap: true,
+            tab_stops: default_tab_stops(cols),
+            line_drawing: false,
+            last_char: ' ',
+            cursor_visible: true,
+            cursor_shape: CursorShape::Block,
+            palette: xterm_palette(),
+            foreground: foreground,
+            background: [0.0, 0.0, 0.0, 1.0],
+            cursor_color: foreground,
+            title: String::new(),
+            application_cursor_keys: false,
+            responses: Vec::new(),
+        }
+    }
+
+    /// The screen being shown
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// The screen being shown, for GlTerminalRenderer::update
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.grid
+    }
+
+    /// Carries out output from a program
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for action in self.parser.feed(bytes) {
+            self.perform(action);
+        }
+        let cursor = Cursor {
+            col: self.col,
+            row: self.row,
+            shape: self.cursor_shape,
+            visible: self.cursor_visible,
+            color: self.cursor_color,
+        };
+        if self.grid.cursor() != cursor {
+            self.grid.set_cursor(cursor);
+        }
+    }
+
+    /// Takes the replies to queries that should be written back to the program
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.responses, Vec::new())
+    }
+
+    /// Changes the number of columns and rows, keeping the text at the top left. The program
+    /// should be told with TIOCSWINSZ.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.grid.resize(cols, rows);
+        self.other_grid.resize(cols, rows);
+        self.scroll_top = 0;
+        self.scroll_bottom = rows;
+        self.tab_stops = default_tab_stops(cols);
+        self.col = self.col.min(cols.saturating_sub(1));
+        self.row = self.row.min(rows.saturating_sub(1));
+        self.wrap_pending = false;
+        // wide characters in the last column lose their right half when there are fewer columns
+        for row in 0..rows {
+            self.repair_wide(row);
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => self.print(c),
+            Action::Execute(b) => self.execute(b),
+            Action::Csi {
+                params,
+                intermediates,
+                final_byte,
+            } => self.csi(&params, &intermediates, final_byte),
+            Action::Esc {
+                intermediates,
+                final_byte,
+            } => self.esc(&intermediates, final_byte),
+            Action::Osc(parts) => {
+                if parts.len() >= 2 && (parts[0] == b"0" || parts[0] == b"2") {
+                    self.title = String::from_utf8_lossy(&parts[1..].join(&b';')).into_owned();
+                }
+            }
+        }
+    }
+
+    /// A blank cell in the current background, which erased cells are filled with
+    fn blank(&self) -> Cell {
+        Cell {
+            bg: self.pen.bg.unwrap_or(TRANSPARENT),
+            ..Cell::blank()
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        let c = if self.line_drawing {
+            line_drawing(c)
+        } else {
+            c
+        };
+        let (cols, rows) = (self.grid.cols(), self.grid.rows());
+        let width = char_width(c).min(cols);
+        if width == 0 {
+            self.combine(c);
+            return;
+        }
+        if self.wrap_pending && self.autowrap {
+            self.col = 0;
+            self.line_feed();
+        }
+        self.wrap_pending = false;
+        // a wide character doesn't fit in the last column, so it goes on the next line, or over
+        // the column before if lines don't wrap
+        if self.col + width > cols {
+            if self.autowrap {
+                self.col = 0;
+                self.line_feed();
+            } else {
+                self.col = cols - width;
+            }
+        }
+        let mut fg = self.pen.fg.unwrap_or(self.foreground);
+        let mut bg = self.pen.bg.unwrap_or(TRANSPARENT);
+        if self.pen.inverse {
+            let behind = if bg[3] > 0.0 { bg } else { self.background };
+            bg = fg;
+            fg = behind;
+        }
+        if self.col < cols && self.row < rows {
+            let cell = Cell {
+                c: c,
+                marks: [None; 2],
+                width: width as u8,
+                fg: fg,
+                bg: bg,
+                bold: self.pen.bold,
+                underline: self.pen.underline,
+            };
+            self.grid.set(self.col, self.row, cell);
+            if width == 2 {
+                let covered = Cell {
+                    c: ' ',
+                    width: 0,
+                    ..cell
+                };
+                self.grid.set(self.col + 1, self.row, covered);
+            }
+            let row = self.row;
+            self.repair_wide(row);
+        }
+        self.last_char = c;
+        if self.col + width < cols {
+            self.col += width;
+        } else {
+            self.col = cols - 1;
+            self.wrap_pending = true;
+        }
+    }
+
+    /// Adds a combining mark to the character written last. Joiners and variation selectors
+    /// are left out, since cells can't be joined.
+    fn combine(&mut self, mark: char) {
+        if color::is_invisible(mark) {
+            return;
+        }
+        let col = if self.wrap_pending {
+            self.col
+        } else if self.col > 0 {
+            self.col - 1
+        } else {
+            return;
+        };
+        if self.row >= self.grid.rows() || col >= self.grid.cols() {
+            return;
+        }
+        let mut col = col;
+        if self.grid.cell(col, self.row).width == 0 && col > 0 {
+            col -= 1;
+        }
+        let mut cell = *self.grid.cell(col, self.row);
+        if let Some(slot) = cell.marks.iter_mut().find(|m| m.is_none()) {
+            *slot = Some(mark);
+            self.grid.set(col, self.row, cell);
+        }
+    }
+
+    fn execute(&mut self, b: u8) {
+        match b {
+            0x08 => {
+                self.col = self.col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            0x09 => self.tab(1),
+            0x0A | 0x0B | 0x0C => self.line_feed(),
+            0x0D => {
+                self.col = 0;
+                self.wrap_pending = false;
+            }
+            _ => (),
+        }
+    }
+
+    fn esc(&mut self, intermediates: &[u8], final_byte: u8) {
+        match (intermediates, final_byte) {
+            (b"", b'D') => self.line_feed(),
+            (b"", b'E') => {
+                self.col = 0;
+                self.line_feed();
+            }
+            (b"", b'M') => self.reverse_index(),
+            (b"", b'H') => {
+                if let Some(stop) = self.tab_stops.get_mut(self.col) {
+                    *stop = true;
+                }
+            }
+            (b"", b'7') => self.save_cursor(),
+            (b"", b'8') => self.restore_cursor(),
+            (b"", b'c') => self.reset(),
+            (b"(", b'0') => self.line_drawing = true,
+            (b"(", _) => self.line_drawing = false,
+            _ => (),
+        }
+    }
+
+    fn csi(&mut self, groups: &[Vec<u32>], intermediates: &[u8], final_byte: u8) {
+        let params: Vec<u32> = groups.iter().map(|group| group[0]).collect();
+        // the nth parameter, with 0 or a missing parameter meaning a default
+        let param = |n: usize, default: u32| match params.get(n) {
+            Some(&p) if p != 0 => p,
+            _ => default,
+        };
+        let count = param(0, 1) as usize;
+        let (cols, rows) = (self.grid.cols(), self.grid.rows());
+        match (intermediates, final_byte) {
+            (b"", b'A') => {
+                let top = if self.row >= self.scroll_top {
+                    self.scroll_top
+                } else {
+                    0
+                };
+                self.row = self.row.saturating_sub(count).max(top);
+                self.wrap_pending = false;
+            }
+            (b"", b'B') | (b"", b'e') => {
+                let bottom = if self.row < self.scroll_bottom {
+                    self.scroll_bottom
+                } else {
+                    rows
+                };
+                self.row = self.row.saturating_add(count).min(bottom - 1);
+                self.wrap_pending = false;
+            }
+            (b"", b'C') | (b"", b'a') => {
+                self.col = self.col.saturating_add(count).min(cols - 1);
+                self.wrap_pending = false;
+            }
+            (b"", b'D') => {
+                self.col = self.col.saturating_sub(count);
+                self.wrap_pending = false;
+            }
+            (b"", b'E') => {
+                self.col = 0;
+                self.row = self.row.saturating_add(count).min(rows - 1);
+                self.wrap_pending = false;
+            }
+            (b"", b'F') => {
+                self.col = 0;
+                self.row = self.row.saturating_sub(count);
+                self.wrap_pending = false;
+            }
+            (b"", b'G') | (b"", b'`') => {
+                self.col = (count - 1).min(cols - 1);
+                self.wrap_pending = false;
+            }
+            (b"", b'H') | (b"", b'f') => {
+                let (row, col) = (param(0, 1) as usize - 1, param(1, 1) as usize - 1);
+                self.move_to(col, row);
+            }
+            (b"", b'd') => {
+                let col = self.col;
+                self.move_to(col, count - 1);
+            }
+            (b"", b'I') => self.tab(count),
+            (b"", b'Z') => {
+                for _ in 0..count {
+                    self.col = self.col.saturating_sub(1);
+                    while self.col > 0 && !self.tab_stops[self.col] {
+                        self.col -= 1;
+                    }
+                }
+                self.wrap_pending = false;
+            }
+            (b"", b'g') => match param(0, 0) {
+                0 => {
+                    if let Some(stop) = self.tab_stops.get_mut(self.col) {
+                        *stop = false;
+                    }
+                }
+                3 => {
+                    for stop in &mut self.tab_stops {
+                        *stop = false;
+                    }
+                }
+                _ => (),
+            },
+            (b"", b'J') => {
+                let blank = self.blank();
+                let (col, row) = (self.col, self.row);
+                match param(0, 0) {
+                    0 => {
+                        self.erase_cells(row, col..cols);
+                        for r in row + 1..rows {
+                            self.erase_cells(r, 0..cols);
+                        }
+                    }
+                    1 => {
+                        for r in 0..row {
+                            self.erase_cells(r, 0..cols);
+                        }
+                        self.erase_cells(row, 0..col + 1);
+                    }
+                    2 | 3 => self.grid.clear(blank),
+                    _ => (),
+                }
+            }
+            (b"", b'K') => {
+                let (col, row) = (self.col, self.row);
+                match param(0, 0) {
+                    0 => self.erase_cells(row, col..cols),
+                    1 => self.erase_cells(row, 0..col + 1),
+                    2 => self.erase_cells(row, 0..cols),
+                    _ => (),
+                }
+            }
+            (b"", b'X') => {
+                let (col, row) = (self.col, self.row);
+                self.erase_cells(row, col..col.saturating_add(count).min(cols));
+            }
+            (b"", b'@') => {
+                let blank = self.blank();
+                let col = self.col;
+                let cells = self.grid.row_mut(self.row);
+                let count = count.min(cols - col);
+                for i in (col + count..cols).rev() {
+                    cells[i] = cells[i - count];
+                }
+                for cell in &mut cells[col..col + count] {
+                    *cell = blank;
+                }
+                self.repair_wide(self.row);
+            }
+            (b"", b'P') => {
+                let blank = self.blank();
+                let col = self.col;
+                let cells = self.grid.row_mut(self.row);
+                let count = count.min(cols - col);
+                for i in col..cols - count {
+                    cells[i] = cells[i + count];
+                }
+                for cell in &mut cells[cols - count..] {
+                    *cell = blank;
+                }
+                self.repair_wide(self.row);
+            }
+            (b"", b'L') | (b"", b'M') => {
+                if self.row >= self.scroll_top && self.row < self.scroll_bottom {
+                    let blank = self.blank();
+                    let region = self.row..self.scroll_bottom;
+                    if final_byte == b'L' {
+                        self.grid.scroll_down(region, count, blank);
+                    } else {
+                        self.grid.scroll_up(region, count, blank);
+                    }
+                    self.col = 0;
+                    self.wrap_pending = false;
+                }
+            }
+            (b"", b'S') => {
+                let blank = self.blank();
+                let region = self.scroll_top..self.scroll_bottom;
+                self.grid.scroll_up(region, count, blank);
+            }
+            (b"", b'T') => {
+                let blank = self.blank();
+                let region = self.scroll_top..self.scroll_bottom;
+                self.grid.scroll_down(region, count, blank);
+            }
+            (b"", b'b') => {
+                // repeating more than fills the rest of the screen would only scroll it
+                let c = self.last_char;
+                let left = (rows - self.row) * cols - self.col;
+                for _ in 0..count.min(left) {
+                    self.print(c);
+                }
+            }
+            (b"", b'r') => {
+                let top = param(0, 1) as usize - 1;
+                let bottom = (param(1, rows as u32) as usize).min(rows);
+                if top + 1 < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            (b"", b'm') => self.sgr(groups),
+            (b"", b'n') => match param(0, 0) {
+                5 => self.responses.extend_from_slice(b"\x1b[0n"),
+                6 => {
+                    let row = if self.origin_mode {
+                        self.row.saturating_sub(self.scroll_top)
+                    } else {
+                        self.row
+                    };
+                    let report = format!("\x1b[{};{}R", row + 1, self.col + 1);
+                    self.responses.extend_from_slice(report.as_bytes());
+                }
+                _ => (),
+            },
+            (b"", b'c') => self.responses.extend_from_slice(b"\x1b[?1;2c"),
+            (b">", b'c') => self.responses.extend_from_slice(b"\x1b[>0;0;0c"),
+            (b"", b's') => self.save_cursor(),
+            (b"", b'u') => self.restore_cursor(),
+            (b"?", b'h') | (b"?", b'l') => {
+                let enable = final_byte == b'h';
+                for &mode in &params {
+                    self.set_private_mode(mode, enable);
+                }
+            }
+            (b" ", b'q') => {
+                self.cursor_shape = match param(0, 0) {
+                    3 | 4 => CursorShape::Underline,
+                    5 | 6 => CursorShape::Bar,
+                    _ => CursorShape::Block,
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u32, enable: bool) {
+        match mode {
+            1 => self.application_cursor_keys = enable,
+            6 => {
+                self.origin_mode = enable;
+                self.move_to(0, 0);
+            }
+            7 => self.autowrap = enable,
+            25 => self.cursor_visible = enable,
+            47 | 1047 => self.set_alternate_screen(enable),
+            1048 => {
+                if enable {
+                    self.save_cursor();
+                } else {
+                    self.restore_cursor();
+                }
+            }
+            1049 => {
+                if enable {
+                    self.save_cursor();
+                    self.set_alternate_screen(true);
+                } else {
+                    self.set_alternate_screen(false);
+                    self.restore_cursor();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Select graphic rendition, which sets the attributes of text written after it
+    fn sgr(&mut self, groups: &[Vec<u32>]) {
+        if groups.is_empty() {
+            self.pen = Pen::new();
+            return;
+        }
+        let mut i = 0;
+        while i < groups.len() {
+            match groups[i][0] {
+                0 => self.pen = Pen::new(),
+                1 => self.pen.bold = true,
+                22 => self.pen.bold = false,
+                4 => self.pen.underline = true,
+                24 => self.pen.underline = false,
+                7 => self.pen.inverse = true,
+                27 => self.pen.inverse = false,
+                p @ 30..=37 => self.pen.fg = Some(self.palette[(p - 30) as usize]),
+                p @ 90..=97 => self.pen.fg = Some(self.palette[(p - 90 + 8) as usize]),
+                39 => self.pen.fg = None,
+                p @ 40..=47 => self.pen.bg = Some(self.palette[(p - 40) as usize]),
+                p @ 100..=107 => self.pen.bg = Some(self.palette[(p - 100 + 8) as usize]),
+                49 => self.pen.bg = None,
+                p @ 38 | p @ 48 => {
+                    let color = if groups[i].len() > 1 {
+                        // 38:5:n, 38:2:r:g:b, or 38:2:id:r:g:b with a color space ID
+                        let sub = &groups[i][1..];
+                        match sub.len() {
+                            5 => self.extended_color(&[sub[0], sub[2], sub[3], sub[4]]),
+                            _ => self.extended_color(sub),
+                        }
+                        .0
+                    } else {
+                        let rest: Vec<u32> = groups[i + 1..].iter().map(|g| g[0]).collect();
+                        let (color, used) = self.extended_color(&rest);
+                        i += used;
+                        color
+                    };
+                    if p == 38 {
+                        self.pen.fg = color.or(self.pen.fg);
+                    } else {
+                        self.pen.bg = color.or(self.pen.bg);
+                    }
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+
+    /// Reads the color after SGR 38 or 48, which is 5 and a palette index or 2 and red, green
+    /// and blue. Returns it and the number of parameters it took up.
+    fn extended_color(&self, params: &[u32]) -> (Option<Color>, usize) {
+        match params.first() {
+            Some(&5) if params.len() >= 2 => (Some(self.palette[params[1].min(255) as usize]), 2),
+            Some(&2) if params.len() >= 4 => {
+                let channel = |v: u32| v.min(255) as f32 / 255.0;
+                (
+                    Some([
+                        channel(params[1]),
+                        channel(params[2]),
+                        channel(params[3]),
+                        1.0,
+                    ]),
+                    4,
+                )
+            }
+            _ => (None, params.len()),
+        }
+    }
+
+    /// Moves the cursor, relative to the scroll region in origin mode
+    fn move_to(&mut self, col: usize, row: usize) {
+        let (top, bottom) = if self.origin_mode {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.grid.rows())
+        };
+        self.col = col.min(self.grid.cols().saturating_sub(1));
+        self.row = top.saturating_add(row).min(bottom.saturating_sub(1));
+        self.wrap_pending = false;
+    }
+
+    fn line_feed(&mut self) {
+        if self.row + 1 == self.scroll_bottom {
+            let blank = self.blank();
+            let region = self.scroll_top..self.scroll_bottom;
+            self.grid.scroll_up(region, 1, blank);
+        } else if self.row + 1 < self.grid.rows() {
+            self.row += 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    fn reverse_index(&mut self) {
+        if self.row == self.scroll_top {
+            let blank = self.blank();
+            let region = self.scroll_top..self.scroll_bottom;
+            self.grid.scroll_down(region, 1, blank);
+        } else if self.row > 0 {
+            self.row -= 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    /// Moves to the next tab stop a number of times, or to the last column
+    fn tab(&mut self, count: usize) {
+        let last = self.grid.cols().saturating_sub(1);
+        for _ in 0..count {
+            self.col = (self.col + 1..last)
+                .find(|&col| self.tab_stops[col])
+                .unwrap_or(last);
+        }
+        self.wrap_pending = false;
+    }
+
+    fn erase_cells(&mut self, row: usize, cols: Range<usize>) {
+        let blank = self.blank();
+        for cell in &mut self.grid.row_mut(row)[cols] {
+            *cell = blank;
+        }
+        self.repair_wide(row);
+    }
+
+    /// Blanks the halves of wide characters whose other half was overwritten or moved away
+    fn repair_wide(&mut self, row: usize) {
+        let cells = self.grid.row_mut(row);
+        for col in 0..cells.len() {
+            let broken = match cells[col].width {
+                2 => cells.get(col + 1).map_or(true, |next| next.width != 0),
+                0 => col == 0 || cells[col - 1].width != 2,
+                _ => false,
+            };
+            if broken {
+                cells[col] = Cell {
+                    c: ' ',
+                    marks: [None; 2],
+                    width: 1,
+                    ..cells[col]
+                };
+            }
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = SavedCursor {
+            col: self.col,
+            row: self.row,
+            pen: self.pen,
+            origin_mode: self.origin_mode,
+            line_drawing: self.line_drawing,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved;
+        self.col = saved.col.min(self.grid.cols().saturating_sub(1));
+        self.row = saved.row.min(self.grid.rows().saturating_sub(1));
+        self.pen = saved.pen;
+        self.origin_mode = saved.origin_mode;
+        self.line_drawing = saved.line_drawing;
+        self.wrap_pending = false;
+        // the scroll region may have changed since, and the cursor can't leave it in origin mode
+        if self.origin_mode {
+            self.row = self
+                .row
+                .max(self.scroll_top)
+                .min(self.scroll_bottom.saturating_sub(1));
+        }
+    }
+
+    /// Switches to the alternate screen, which full screen programs draw on so the primary
+    /// screen is left as it was when they exit. It is cleared when it is switched to.
+    fn set_alternate_screen(&mut self, enable: bool) {
+        if enable == self.alternate_screen {
+            return;
+        }
+        mem::swap(&mut self.grid, &mut self.other_grid);
+        self.alternate_screen = enable;
+        if enable {
+            let blank = self.blank();
+            self.grid.clear(blank);
+        }
+        self.grid.mark_all_dirty();
+    }
+
+    /// Full reset, ESC c
+    fn reset(&mut self) {
+        let (cols, rows) = (self.grid.cols(), self.grid.rows());
+        self.set_alternate_screen(false);
+        self.pen = Pen::new();
+        self.grid.clear(Cell::blank());
+        self.col = 0;
+        self.row = 0;
+        self.wrap_pending = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows;
+        self.origin_mode = false;
+        self.autowrap = true;
+        self.tab_stops = default_tab_stops(cols);
+        self.line_drawing = false;
+        self.cursor_visible = true;
+        self.cursor_shape = CursorShape::Block;
+        self.application_cursor_keys = false;
+    }
+}
+
+/// Number of columns a character takes up, as wcwidth gives: 0 for combining marks and other
+/// characters without a width, 2 for East Asian wide and fullwidth characters and emoji, and 1
+/// for the rest
+pub fn char_width(c: char) -> usize {
+    if c == '\u{AD}' {
+        return 1;
+    }
+    if is_combining_mark(c) || color::is_invisible(c) {
+        return 0;
+    }
+    match c as u32 {
+        // medial vowels and final consonants of Hangul syllables made of jamo
+        0x1160..=0x11FF | 0xD7B0..=0xD7FF => 0,
+        0x1100..=0x115F | 0xA960..=0xA97F | 0xAC00..=0xD7A3 => 2,
+        0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF => 2,
+        0xA000..=0xA4CF | 0xF900..=0xFAFF | 0xFE10..=0xFE19 | 0xFE30..=0xFE6F => 2,
+        0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 => 2,
+        0x16FE0..=0x18CFF | 0x1B000..=0x1B2FF | 0x20000..=0x2FFFD | 0x30000..=0x3FFFD => 2,
+        // symbols shown as emoji by default
+        0x231A..=0x231B | 0x2329..=0x232A | 0x23E9..=0x23EC | 0x23F0 | 0x23F3 => 2,
+        0x25FD..=0x25FE | 0x2614..=0x2615 | 0x2648..=0x2653 | 0x267F | 0x2693 | 0x26A1 => 2,
+        0x26AA..=0x26AB | 0x26BD..=0x26BE | 0x26C4..=0x26C5 | 0x26CE | 0x26D4 | 0x26EA => 2,
+        0x26F2..=0x26F3 | 0x26F5 | 0x26FA | 0x26FD | 0x2705 | 0x270A..=0x270B | 0x2728 => 2,
+        0x274C | 0x274E | 0x2753..=0x2755 | 0x2757 | 0x2795..=0x2797 | 0x27B0 | 0x27BF => 2,
+        0x2B1B..=0x2B1C | 0x2B50 | 0x2B55 => 2,
+        0x1F004 | 0x1F0CF | 0x1F18E | 0x1F191..=0x1F19A | 0x1F200..=0x1F2FF => 2,
+        0x1F300..=0x1F64F | 0x1F680..=0x1F6FF | 0x1F7E0..=0x1F7EB => 2,
+        0x1F90C..=0x1F9FF | 0x1FA70..=0x1FAFF => 2,
+        _ => 1,
+    }
+}
+
+fn default_tab_stops(cols: usize) -> Vec<bool> {
+    (0..cols).map(|col| col % TAB_WIDTH == 0).collect()
+}
+
+/// The DEC special graphics character a letter stands for in line drawing mode
+fn line_drawing(c: char) -> char {
+    match c {
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        _ => c,
+    }
+}
+
+/// The default xterm colors: 16 named colors, a 6x6x6 color cube and 24 grays
+fn xterm_palette() -> [Color; 256] {
+    let named: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let rgb = |r: u8, g: u8, b: u8| [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+    let mut palette = [[0.0; 4]; 256];
+    for (i, &(r, g, b)) in named.iter().enumerate() {
+        palette[i] = rgb(r, g, b);
+    }
+    let levels = [0, 95, 135, 175, 215, 255];
+    for i in 0..216 {
+        palette[16 + i] = rgb(levels[i / 36], levels[i / 6 % 6], levels[i % 6]);
+    }
+    for i in 0..24 {
+        let gray = 8 + 10 * i as u8;
+        palette[232 + i] = rgb(gray, gray, gray);
+    }
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal(cols: usize, rows: usize, bytes: &[u8]) -> Terminal {
+        let mut terminal = Terminal::new(cols, rows);
+        terminal.feed(bytes);
+        terminal
+    }
+
+    /// The characters of a row, leaving out the cells covered by wide characters
+    fn row_text(terminal: &Terminal, row: usize) -> String {
+        terminal
+            .grid()
+            .row(row)
+            .iter()
+            .filter(|cell| cell.width != 0)
+            .map(|cell| cell.c)
+            .collect()
+    }
+
+    #[test]
+    fn sgr_colors() {
+        let t = terminal(
+            10,
+            1,
+            b"\x1b[38;5;196mA\x1b[48;2;10;20;30mB\x1b[38:2::1:2:3mC\x1b[1;4;7mD\x1b[0mE",
+        );
+        let cell = |col| *t.grid().cell(col, 0);
+        assert_eq!(cell(0).fg, t.palette[196]);
+        assert_eq!(cell(1).bg, [10.0 / 255.0, 20.0 / 255.0, 30.0 / 255.0, 1.0]);
+        assert_eq!(cell(2).fg, [1.0 / 255.0, 2.0 / 255.0, 3.0 / 255.0, 1.0]);
+        assert_eq!(cell(3).bg, [1.0 / 255.0, 2.0 / 255.0, 3.0 / 255.0, 1.0]);
+        assert_eq!(cell(3).fg, [10.0 / 255.0, 20.0 / 255.0, 30.0 / 255.0, 1.0]);
+        assert!(cell(3).bold && cell(3).underline);
+        assert_eq!(cell(4).fg, t.foreground);
+        assert_eq!(cell(4).bg, TRANSPARENT);
+        assert!(!cell(4).bold);
+    }
+
+    #[test]
+    fn cursor_position() {
+        let mut t = terminal(10, 5, b"\x1b[3;5HX\x1b[HY");
+        assert_eq!(t.grid().cell(4, 2).c, 'X');
+        assert_eq!(t.grid().cell(0, 0).c, 'Y');
+        t.feed(b"\x1b[4294967295;4294967295HZ\x1b[4294967295A\x1b[4294967295C\x1b[4294967295B");
+        assert_eq!(t.grid().cell(9, 4).c, 'Z');
+        assert_eq!((t.col, t.row), (9, 4));
+        t.feed(b"\x1b[2;3H\x1b[6n");
+        assert_eq!(t.take_responses(), b"\x1b[2;3R".to_vec());
+    }
+
+    #[test]
+    fn erase() {
+        let mut t = terminal(4, 3, b"abcd\r\nefgh\r\nijkl\x1b[2;2H\x1b[K");
+        assert_eq!(row_text(&t, 1), "e   ");
+        t.feed(b"\x1b[1K");
+        assert_eq!(row_text(&t, 1), "    ");
+        t.feed(b"\x1b[1;3H\x1b[J");
+        assert_eq!(row_text(&t, 0), "ab  ");
+        assert_eq!(row_text(&t, 2), "    ");
+        t.feed(b"\x1b[2J");
+        assert_eq!(row_text(&t, 0), "    ");
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut t = terminal(2, 5, b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r");
+        assert_eq!((t.col, t.row), (0, 0));
+        t.feed(b"\x1b[4;1H\nx");
+        let rows: Vec<String> = (0..5).map(|row| row_text(&t, row)).collect();
+        assert_eq!(rows, ["1 ", "3 ", "4 ", "x ", "5 "]);
+        t.feed(b"\x1b[2;1H\x1bM");
+        let rows: Vec<String> = (0..5).map(|row| row_text(&t, row)).collect();
+        assert_eq!(rows, ["1 ", "  ", "3 ", "4 ", "5 "]);
+    }
+
+    #[test]
+    fn alternate_screen() {
+        let mut t = terminal(4, 2, b"main\x1b[1;2H\x1b[?1049h");
+        assert_eq!(row_text(&t, 0), "    ");
+        t.feed(b"alt");
+        assert_eq!(row_text(&t, 0), " alt");
+        t.feed(b"\x1b[?1049l");
+        assert_eq!(row_text(&t, 0), "main");
+        assert_eq!((t.col, t.row), (1, 0));
+    }
+
+    #[test]
+    fn origin_mode_cursor_restored_above_region() {
+        let mut t = terminal(20, 12, b"\x1b[?6h\x1b7\x1b[5;10r\x1b8\x1b[6n");
+        assert_eq!(t.take_responses(), b"\x1b[1;1R".to_vec());
+        assert_eq!(t.row, 4);
+    }
+
+    #[test]
+    fn repeat_is_capped() {
+        let t = terminal(3, 2, b"a\x1b[4294967295b");
+        assert_eq!(row_text(&t, 0), "aaa");
+        assert_eq!(row_text(&t, 1), "aaa");
+    }
+
+    #[test]
+    fn wide_and_combining_characters() {
+        let mut t = terminal(5, 3, "中a\u{301}😀".as_bytes());
+        assert_eq!(t.grid().cell(0, 0).width, 2);
+        assert_eq!(t.grid().cell(1, 0).width, 0);
+        assert_eq!(t.grid().cell(2, 0).c, 'a');
+        assert_eq!(t.grid().cell(2, 0).marks, [Some('\u{301}'), None]);
+        assert_eq!(row_text(&t, 0), "中a😀");
+        assert!(t.wrap_pending);
+        // a wide character doesn't fit in the last column, so it wraps
+        t.feed("\r\nabcd中".as_bytes());
+        assert_eq!(row_text(&t, 1), "abcd ");
+        assert_eq!(row_text(&t, 2), "中   ");
+        // writing over half of a wide character blanks the other half
+        t.feed(b"\x1b[1;2Hx");
+        assert_eq!(row_text(&t, 0), " xa😀");
+    }
+}